@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use pin_project::pin_project;
+
+/// A handle which can abort a parallel future from elsewhere.
+///
+/// This type is constructed by the
+/// [`par_with_abort`][crate::IntoFutureExt::par_with_abort] method on
+/// [`IntoFutureExt`][crate::IntoFutureExt]. It can be cloned and shared between
+/// threads freely. Dropping it does not abort the future.
+#[derive(Debug, Clone)]
+pub struct AbortHandle {
+    inner: Arc<Inner>,
+}
+
+impl AbortHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                aborted: AtomicBool::new(false),
+                waker: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Abort the future.
+    ///
+    /// The spawned task stops at its next await point, and the parallel future
+    /// resolves to `Err(Aborted)`. If the future has already completed, this
+    /// does nothing.
+    pub fn abort(&self) {
+        self.inner.aborted.store(true, Ordering::SeqCst);
+        if let Some(waker) = self.inner.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns `true` if [`abort`][AbortHandle::abort] has been called.
+    pub fn is_aborted(&self) -> bool {
+        self.inner.aborted.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    aborted: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+/// A future which can be aborted through an [`AbortHandle`].
+///
+/// This type is constructed by the
+/// [`par_with_abort`][crate::IntoFutureExt::par_with_abort] method on
+/// [`IntoFutureExt`][crate::IntoFutureExt].
+#[pin_project]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Abortable<Fut> {
+    #[pin]
+    fut: Fut,
+    handle: AbortHandle,
+}
+
+impl<Fut> Abortable<Fut> {
+    pub(crate) fn new(fut: Fut, handle: AbortHandle) -> Self {
+        Self { fut, handle }
+    }
+}
+
+impl<Fut: Future> Future for Abortable<Fut> {
+    type Output = Result<Fut::Output, Aborted>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = &this.handle.inner;
+
+        // Register the waker before checking the flag, so an abort which
+        // happens in between still wakes us up.
+        match &mut *inner.waker.lock().unwrap() {
+            Some(waker) => waker.clone_from(cx.waker()),
+            waker @ None => *waker = Some(cx.waker().clone()),
+        }
+        if inner.aborted.load(Ordering::SeqCst) {
+            return Poll::Ready(Err(Aborted { _priv: () }));
+        }
+        this.fut.poll(cx).map(Ok)
+    }
+}
+
+/// The error returned when a parallel future is aborted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aborted {
+    _priv: (),
+}
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "future was aborted".fmt(f)
+    }
+}
+
+impl Error for Aborted {}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+
+    use crate::prelude::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn abort() {
+        runtime::block_on(async {
+            let (started, is_started) = drop_signal();
+            let (fut, handle) = async move {
+                drop(started);
+                future::pending::<()>().await;
+            }
+            .par_with_abort();
+            let handle_2 = handle.clone();
+            let fut = fut.detach();
+
+            is_started.await;
+            handle_2.abort();
+            assert!(fut.await.is_err());
+            assert!(handle.is_aborted());
+        })
+    }
+
+    #[test]
+    fn abort_before_spawn() {
+        runtime::block_on(async {
+            let (fut, handle) = async { 12 }.par_with_abort();
+            handle.abort();
+            assert!(fut.await.is_err());
+        })
+    }
+}
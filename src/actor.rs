@@ -0,0 +1,252 @@
+//! A mailbox for sending messages to a parallel future.
+//!
+//! This is the channel used by [`par_actor`]: the spawned
+//! future owns the [`Receiver`], and the caller keeps one or more
+//! [`Sender`]s to send it messages while the future runs.
+
+use std::error::Error;
+use std::fmt;
+use std::future::{poll_fn, IntoFuture};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use atomic_waker::AtomicWaker;
+use concurrent_queue::{ConcurrentQueue, PushError};
+use futures_core::Stream;
+
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// Create a parallel future which receives messages over a channel.
+///
+/// The closure is passed the receiving half of an unbounded channel, and
+/// returns the future to spawn. The sending half is returned along with the
+/// `ParallelFuture`, so messages can be sent to the future while it runs, and
+/// its output can be awaited once it's done. The channel closes when the
+/// future completes or is cancelled, after which sending fails. Likewise the
+/// receiver yields `None` once every sender has been dropped, which is a
+/// natural signal for the future to wrap up.
+///
+/// Like [`par`][crate::IntoFutureExt::par], the future is spawned lazily.
+/// Messages sent before then are queued up until it starts.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_actor;
+///
+/// parallel_future::par_block_on(async {
+///     let (sender, fut) = par_actor(|mut receiver| async move {
+///         let mut sum = 0;
+///         while let Some(n) = receiver.recv().await {
+///             sum += n;
+///         }
+///         sum
+///     });
+///     for n in 1..=3 {
+///         sender.send(n).unwrap();
+///     }
+///     drop(sender); // ← lets the actor finish
+///     assert_eq!(fut.await, 6);
+/// })
+/// ```
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_actor<F, Fut, M>(f: F) -> (Sender<M>, ParallelFuture<Fut>)
+where
+    F: FnOnce(Receiver<M>) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let (sender, receiver) = channel();
+    (sender, f(receiver).par())
+}
+
+/// Create a new unbounded channel.
+fn channel<M>() -> (Sender<M>, Receiver<M>) {
+    let inner = Arc::new(Inner {
+        messages: ConcurrentQueue::unbounded(),
+        waker: AtomicWaker::new(),
+        senders: AtomicUsize::new(1),
+    });
+    let sender = Sender {
+        inner: inner.clone(),
+    };
+    (sender, Receiver { inner })
+}
+
+/// The queue is closed from either side: by the receiver when it's dropped,
+/// and by the last sender when it's dropped. Messages queued up before the
+/// last sender was dropped can still be received.
+#[derive(Debug)]
+struct Inner<M> {
+    messages: ConcurrentQueue<M>,
+    waker: AtomicWaker,
+    senders: AtomicUsize,
+}
+
+/// The sending half of the channel created by [`par_actor`].
+///
+/// Senders can be cloned to send messages from several places.
+pub struct Sender<M> {
+    inner: Arc<Inner<M>>,
+}
+
+impl<M> Sender<M> {
+    /// Send a message to the parallel future.
+    ///
+    /// The channel is unbounded, so this never waits. It returns an error
+    /// containing the message if the future has already completed or been
+    /// cancelled.
+    pub fn send(&self, message: M) -> Result<(), SendError<M>> {
+        match self.inner.messages.push(message) {
+            Ok(()) => {
+                self.inner.waker.wake();
+                Ok(())
+            }
+            Err(PushError::Closed(message) | PushError::Full(message)) => Err(SendError(message)),
+        }
+    }
+
+    /// Returns `true` once the parallel future has completed or been
+    /// cancelled, after which no more messages can be sent.
+    pub fn is_closed(&self) -> bool {
+        self.inner.messages.is_closed()
+    }
+}
+
+impl<M> Clone for Sender<M> {
+    fn clone(&self) -> Self {
+        self.inner.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M> Drop for Sender<M> {
+    fn drop(&mut self) {
+        if self.inner.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.inner.messages.close();
+            self.inner.waker.wake();
+        }
+    }
+}
+
+impl<M> fmt::Debug for Sender<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("closed", &self.is_closed())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The receiving half of the channel created by [`par_actor`].
+///
+/// Messages can be received with [`recv`][Receiver::recv], or by using the
+/// receiver as a [`Stream`].
+pub struct Receiver<M> {
+    inner: Arc<Inner<M>>,
+}
+
+impl<M> Receiver<M> {
+    /// Wait for the next message.
+    ///
+    /// Returns `None` once every sender has been dropped and all queued up
+    /// messages have been received.
+    pub async fn recv(&mut self) -> Option<M> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<M>> {
+        // Register the waker before checking the queue, so a message which is
+        // sent in between still wakes us up.
+        self.inner.waker.register(cx.waker());
+        match self.inner.messages.pop() {
+            Ok(message) => Poll::Ready(Some(message)),
+            Err(err) if err.is_closed() => Poll::Ready(None),
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
+impl<M> Stream for Receiver<M> {
+    type Item = M;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_recv(cx)
+    }
+}
+
+impl<M> Drop for Receiver<M> {
+    fn drop(&mut self) {
+        self.inner.messages.close();
+    }
+}
+
+impl<M> fmt::Debug for Receiver<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("queued", &self.inner.messages.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The error returned when sending a message to a parallel future which has
+/// already completed or been cancelled.
+///
+/// It contains the message which couldn't be sent.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<M>(pub M);
+
+impl<M> fmt::Debug for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<M> fmt::Display for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "sending on a closed channel".fmt(f)
+    }
+}
+
+impl<M> Error for SendError<M> {}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::runtime;
+
+    #[test]
+    fn receives_messages() {
+        runtime::block_on(async {
+            let (sender, mut fut) = par_actor(|mut receiver| async move {
+                let mut messages = Vec::new();
+                while let Some(message) = receiver.recv().await {
+                    messages.push(message);
+                }
+                messages
+            });
+            fut.start();
+            for n in 0..3 {
+                sender.clone().send(n).unwrap();
+                crate::yield_now().await;
+            }
+            drop(sender);
+            assert_eq!(fut.await, vec![0, 1, 2]);
+        })
+    }
+
+    #[test]
+    fn closes_when_done() {
+        runtime::block_on(async {
+            let (sender, fut) = par_actor(|mut receiver| async move { receiver.recv().await });
+            sender.send(12).unwrap();
+            assert_eq!(fut.await, Some(12));
+            assert!(sender.is_closed());
+            assert_eq!(sender.send(13), Err(SendError(13)));
+        })
+    }
+}
@@ -0,0 +1,120 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use pin_project::pin_project;
+
+thread_local! {
+    /// Bytes allocated on this thread since the current task was last polled.
+    static ALLOCATED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A global allocator which attributes allocations to the parallel future
+/// being polled.
+///
+/// Installing it as the global allocator makes the bytes each task allocated
+/// available through
+/// [`TaskEvent::allocated_bytes`][crate::TaskEvent::allocated_bytes], on the
+/// events passed to the metrics hook. The actual allocations are forwarded to
+/// the wrapped allocator, which is the system allocator by default. This
+/// requires the `track-allocations` feature.
+///
+/// The count is approximate:
+///
+/// - Only allocations made while the task's future is being polled are
+///   counted. Work done on the task's behalf on other threads, such as inside
+///   [`par_blocking`][crate::par_blocking], or by the runtime itself, isn't.
+/// - Parallel futures polled from inside another task, such as with a nested
+///   `block_on`, count towards their own task rather than the outer one.
+/// - Deallocations aren't subtracted, so this is the total number of bytes
+///   requested over the task's life, not how much memory it holds on to.
+///   Growing an allocation in place counts the bytes it grew by.
+///
+/// Without this allocator installed, every task reports zero bytes.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::TrackingAllocator;
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static ALLOCATOR: TrackingAllocator = TrackingAllocator::new(System);
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TrackingAllocator<A = System> {
+    inner: A,
+}
+
+impl<A> TrackingAllocator<A> {
+    /// Wrap an allocator, counting the bytes allocated through it.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+// SAFETY: every call is forwarded to the wrapped allocator unchanged. Counting
+// doesn't allocate, since the thread-local has a const initializer and no
+// destructor.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        self.inner.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        self.inner.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size.saturating_sub(layout.size()));
+        self.inner.realloc(ptr, layout, new_size)
+    }
+}
+
+fn record(bytes: usize) {
+    // The thread-local may already be gone while the thread shuts down.
+    let _ =
+        ALLOCATED.try_with(|allocated| allocated.set(allocated.get().wrapping_add(bytes as u64)));
+}
+
+/// Count the bytes allocated while polling a future.
+#[pin_project]
+pub(crate) struct Tracked<F> {
+    #[pin]
+    fut: F,
+    allocated: Arc<AtomicU64>,
+}
+
+impl<F> Tracked<F> {
+    pub(crate) fn new(fut: F, allocated: Arc<AtomicU64>) -> Self {
+        Self { fut, allocated }
+    }
+}
+
+impl<F: Future> Future for Tracked<F> {
+    type Output = F::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        // Save the count of whatever is polling us, so nested tasks don't
+        // count towards each other.
+        let outer = ALLOCATED.with(|allocated| allocated.replace(0));
+        let poll = this.fut.poll(cx);
+        let bytes = ALLOCATED.with(|allocated| allocated.replace(outer));
+        this.allocated.fetch_add(bytes, Ordering::Relaxed);
+        poll
+    }
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator::new(System);
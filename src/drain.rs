@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use atomic_waker::AtomicWaker;
+use pin_project::pin_project;
+
+use crate::executor::TaskId;
+use crate::runtime;
+
+/// Every task which is currently in flight, so they can be waited on and
+/// cancelled when the program shuts down. This is only tracked with the `drain`
+/// feature enabled, since it takes a global lock on every spawn and completion.
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    tasks: BTreeMap::new(),
+    drained: Vec::new(),
+});
+
+struct Registry {
+    tasks: BTreeMap<TaskId, Arc<Switch>>,
+    /// Calls to `drain` waiting for `tasks` to become empty.
+    drained: Vec<Waker>,
+}
+
+/// Cancels a single task from outside of it.
+#[derive(Debug, Default)]
+pub(crate) struct Switch {
+    cancelled: AtomicBool,
+    /// Whether the task's handle has been dropped, so nothing is waiting on
+    /// its output anymore.
+    orphaned: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Switch {
+    /// Cancel the task, unless something is still waiting on its output.
+    /// Returns whether it was cancelled.
+    fn cancel_orphaned(&self) -> bool {
+        if !self.orphaned.load(Ordering::SeqCst) {
+            return false;
+        }
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.waker.wake();
+        true
+    }
+}
+
+/// Marks a task as orphaned once its handle is dropped.
+#[derive(Debug)]
+pub(crate) struct Watch {
+    switch: Arc<Switch>,
+}
+
+impl Watch {
+    pub(crate) fn new(switch: Arc<Switch>) -> Self {
+        Self { switch }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.switch.orphaned.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Add a task to the registry, returning the switch which cancels it.
+pub(crate) fn register(id: TaskId) -> Arc<Switch> {
+    let switch = Arc::new(Switch::default());
+    REGISTRY.lock().unwrap().tasks.insert(id, switch.clone());
+    switch
+}
+
+/// Remove a task from the registry once it's done or dropped.
+pub(crate) fn unregister(id: TaskId) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.tasks.remove(&id);
+    if registry.tasks.is_empty() {
+        registry.drained.drain(..).for_each(Waker::wake);
+    }
+}
+
+/// Wait for every task spawned by a parallel future to finish, cancelling the
+/// ones nobody is waiting on which are still running after `timeout`.
+///
+/// This is meant to be called at the end of `main`, so tasks which were
+/// detached or kept alive get a chance to finish before the program exits,
+/// rather than being cut off in the middle of their work. Tasks spawned while
+/// draining are waited on as well. Once `timeout` has passed, every task which
+/// is still in flight and whose handle has been dropped is cancelled, and this
+/// returns how many that were. If all tasks finished in time, this returns
+/// `0`.
+///
+/// Tasks which still have a `ParallelFuture` or `DetachedHandle` are never
+/// cancelled by this, so awaiting them keeps working: they're left running
+/// once the timeout passes, and aren't counted. Cancelled tasks stop at their
+/// next await point, like they would when their `ParallelFuture` is dropped;
+/// this doesn't wait for that to happen.
+///
+/// This requires the `drain` feature, which tracks every task in a global
+/// registry. That takes a lock whenever a task is spawned or completes.
+///
+/// This waits on every task in the process, so calling it from inside a
+/// parallel future waits on that future's own task too, and always runs into
+/// the timeout.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let handle = async_std::task::sleep(Duration::from_millis(10))
+///         .par()
+///         .detach();
+///     drop(handle); // ← the task keeps running in the background
+///
+///     let cancelled = parallel_future::drain(Duration::from_secs(1)).await;
+///     assert_eq!(cancelled, 0);
+/// })
+/// ```
+pub async fn drain(timeout: Duration) -> usize {
+    let mut timeout = runtime::sleep(timeout);
+    poll_fn(|cx| {
+        {
+            let mut registry = REGISTRY.lock().unwrap();
+            if registry.tasks.is_empty() {
+                return Poll::Ready(0);
+            }
+            if !registry.drained.iter().any(|w| w.will_wake(cx.waker())) {
+                registry.drained.push(cx.waker().clone());
+            }
+        }
+        if timeout.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        // Cancel the tasks outside of the lock, since their wakers may run
+        // arbitrary code.
+        let tasks: Vec<_> = REGISTRY.lock().unwrap().tasks.values().cloned().collect();
+        let cancelled = tasks.iter().filter(|switch| switch.cancel_orphaned());
+        Poll::Ready(cancelled.count())
+    })
+    .await
+}
+
+/// A task's future, which is dropped as soon as the task's switch is flipped.
+#[pin_project]
+pub(crate) struct Drainable<F> {
+    #[pin]
+    fut: Option<F>,
+    switch: Arc<Switch>,
+}
+
+impl<F> Drainable<F> {
+    pub(crate) fn new(fut: F, switch: Arc<Switch>) -> Self {
+        Self {
+            fut: Some(fut),
+            switch,
+        }
+    }
+}
+
+impl<F: Future<Output = ()>> Future for Drainable<F> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        // Register the waker before checking the flag, so a cancellation which
+        // happens in between still wakes us up.
+        this.switch.waker.register(cx.waker());
+        if this.switch.cancelled.load(Ordering::SeqCst) {
+            this.fut.set(None);
+        }
+        match this.fut.as_mut().as_pin_mut() {
+            Some(fut) => fut.poll(cx),
+            None => Poll::Ready(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::prelude::*;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn drain() {
+        // Draining waits on the tasks of every other test in the process, so
+        // this runs in a child process which re-runs just this test.
+        if std::env::var_os("PARALLEL_FUTURE_DRAIN").is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "drain::test::drain"])
+                .env("PARALLEL_FUTURE_DRAIN", "1")
+                .stdout(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+        runtime::block_on(async {
+            for _ in 0..2 {
+                let fut = async {
+                    crate::yield_now().await;
+                    COMPLETED.fetch_add(1, Ordering::SeqCst);
+                };
+                drop(fut.par().detach());
+            }
+            let (guard, dropped) = drop_signal();
+            let fut = async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            };
+            drop(fut.par().detach());
+            // Tasks which are still being waited on are left running.
+            let (release, released) = drop_signal();
+            let kept = async move {
+                released.await;
+                12
+            }
+            .par()
+            .detach();
+            let cancelled = super::drain(Duration::from_millis(50)).await;
+            assert_eq!(cancelled, 1);
+            assert_eq!(COMPLETED.load(Ordering::SeqCst), 2);
+            dropped.await;
+            drop(release);
+            assert_eq!(kept.await, 12);
+
+            // Once every task is gone, draining returns right away.
+            assert_eq!(super::drain(Duration::from_secs(10)).await, 0);
+        })
+    }
+}
@@ -0,0 +1,1116 @@
+//! Executors which parallel futures can be spawned on.
+//!
+//! By default [`par`][crate::IntoFutureExt::par] spawns futures on the runtime
+//! selected through cargo features. The [`Executor`] trait makes it possible to
+//! spawn futures on any other executor through
+//! [`par_on`][crate::IntoFutureExt::par_on] instead, or installed as the
+//! default for the whole application through [`set_default_executor`]. The
+//! runtime selected through cargo features can be sized through [`configure`].
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::any::Any;
+use core::fmt;
+use core::future::Future;
+#[cfg(feature = "std")]
+use core::panic::Location;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{ready, Context, Poll};
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::hash::BuildHasher;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "std")]
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+use pin_project::pin_project;
+
+#[cfg(feature = "drain")]
+use crate::drain::{Drainable, Watch};
+#[cfg(feature = "std")]
+use crate::metrics::Lifecycle;
+use crate::oneshot;
+#[cfg(feature = "std")]
+use crate::runtime;
+#[cfg(feature = "std")]
+use crate::yield_now;
+
+#[cfg(feature = "async-std")]
+pub use crate::runtime::AsyncStd;
+#[cfg(feature = "std")]
+pub use crate::runtime::RawJoinHandle;
+#[cfg(feature = "smol")]
+pub use crate::runtime::Smol;
+#[cfg(feature = "tokio")]
+pub use crate::runtime::Tokio;
+
+/// An executor which can run tasks in parallel.
+///
+/// # Examples
+///
+/// Spawning on the `AsyncStd` executor requires the `async-std` feature.
+#[cfg_attr(feature = "async-std", doc = "```")]
+#[cfg_attr(not(feature = "async-std"), doc = "```ignore")]
+/// use parallel_future::prelude::*;
+/// use parallel_future::executor::{AsyncStd, BoxedHandle, Executor, Task};
+///
+/// /// An executor which logs every task it spawns.
+/// #[derive(Debug, Clone)]
+/// struct Logged;
+///
+/// impl Executor for Logged {
+///     fn spawn(&self, task: Task) -> BoxedHandle {
+///         println!("spawning a task");
+///         AsyncStd.spawn(task)
+///     }
+/// }
+///
+/// parallel_future::par_block_on(async {
+///     let res = async { 12 }.par_on(&Logged).await;
+///     assert_eq!(res, 12);
+/// })
+/// ```
+pub trait Executor: fmt::Debug + Send + Sync {
+    /// Spawn a task onto the executor.
+    ///
+    /// Dropping the returned handle should detach the task, and calling
+    /// [`TaskHandle::cancel`] on it should cancel the task.
+    fn spawn(&self, task: Task) -> BoxedHandle;
+
+    /// Spawn a task onto the executor, returning an error rather than
+    /// panicking if the executor can't spawn tasks.
+    ///
+    /// Executors which can fail to spawn, for example because they've been
+    /// shut down, should override this. The default implementation calls
+    /// [`spawn`][Executor::spawn].
+    fn try_spawn(&self, task: Task) -> Result<BoxedHandle, SpawnError> {
+        Ok(self.spawn(task))
+    }
+}
+
+/// The error returned when an [`Executor`] can't spawn a task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpawnError {
+    _priv: (),
+}
+
+impl SpawnError {
+    /// Create a new `SpawnError`.
+    pub fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "the executor cannot spawn tasks".fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for SpawnError {}
+
+/// A type-erased future which can be spawned onto an [`Executor`].
+///
+/// Tasks carry the options they were configured with through
+/// [`Builder`][crate::future::Builder]. Executors should honor the options they
+/// support, and ignore the rest.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Task {
+    fut: Pin<Box<dyn Future<Output = ()> + Send + 'static>>,
+    id: TaskId,
+    config: Config,
+}
+
+impl Task {
+    fn new<F>(fut: F, id: TaskId, config: Config) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            fut: Box::pin(fut),
+            id,
+            config,
+        }
+    }
+
+    /// Returns the id of the task.
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Returns the name of the task, if one was set.
+    pub fn name(&self) -> Option<&str> {
+        self.config.name.as_deref()
+    }
+
+    /// Returns the requested stack size of the task in bytes, if one was set.
+    pub fn stack_size(&self) -> Option<usize> {
+        self.config.stack_size
+    }
+
+    /// Returns the scheduling priority of the task.
+    pub fn priority(&self) -> Priority {
+        self.config.priority
+    }
+
+    /// Returns the core or worker the task should preferably run on, if one
+    /// was set.
+    ///
+    /// This is only a hint: executors which can't pin tasks are free to
+    /// ignore it.
+    pub fn affinity(&self) -> Option<usize> {
+        self.config.affinity
+    }
+
+    /// Returns `true` if the task should preferably run on the thread which
+    /// spawned it.
+    ///
+    /// This is only a hint: executors without per-thread queues are free to
+    /// ignore it.
+    pub fn prefers_local(&self) -> bool {
+        self.config.prefer_local
+    }
+
+    /// Returns what happens when the task panics.
+    pub fn panic_policy(&self) -> PanicPolicy {
+        self.config.panic_policy
+    }
+
+    /// Returns where the task's parallel future was created. This requires
+    /// the `debug` feature.
+    #[cfg(feature = "debug")]
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.config.location
+    }
+}
+
+impl Future for Task {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.fut.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task")
+            .field("id", &self.id)
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A process-unique identifier for a spawned task.
+///
+/// Ids are allocated when a task is spawned, which for parallel futures is when
+/// they're first polled. They increase monotonically in spawn order, and are
+/// never reused. Unlike task names, ids are always unique.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    /// Allocate a new id.
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the id as a number.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The options a task is spawned with.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) name: Option<Arc<str>>,
+    pub(crate) stack_size: Option<usize>,
+    pub(crate) priority: Priority,
+    pub(crate) affinity: Option<usize>,
+    pub(crate) prefer_local: bool,
+    pub(crate) yield_every: usize,
+    pub(crate) panic_policy: PanicPolicy,
+    /// The longest the task's start may be delayed by.
+    #[cfg(feature = "std")]
+    pub(crate) stagger: Option<Duration>,
+    #[cfg(feature = "tracing")]
+    pub(crate) span: Option<tracing::Span>,
+    /// Where the parallel future was created.
+    #[cfg(feature = "debug")]
+    pub(crate) location: Option<&'static Location<'static>>,
+}
+
+impl Config {
+    /// Returns where the parallel future was created, if that was recorded.
+    #[cfg(feature = "std")]
+    pub(crate) fn location(&self) -> Option<&'static Location<'static>> {
+        #[cfg(feature = "debug")]
+        return self.location;
+        #[cfg(not(feature = "debug"))]
+        return None;
+    }
+}
+
+/// With the `debug` feature enabled, this records the caller as the location
+/// the task is spawned from. Functions which create parallel futures are
+/// `#[track_caller]` under that feature, so this is the user's call site.
+impl Default for Config {
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn default() -> Self {
+        Self {
+            name: None,
+            stack_size: None,
+            priority: Priority::default(),
+            affinity: None,
+            prefer_local: false,
+            yield_every: 0,
+            panic_policy: PanicPolicy::default(),
+            #[cfg(feature = "std")]
+            stagger: None,
+            #[cfg(feature = "tracing")]
+            span: None,
+            #[cfg(feature = "debug")]
+            location: Some(Location::caller()),
+        }
+    }
+}
+
+/// The scheduling priority of a task.
+///
+/// Neither async-std nor Tokio support task priorities, so on those this is
+/// best-effort: `Low` priority tasks yield back to the scheduler between every
+/// poll, giving other tasks a chance to run first. `High` priority tasks are
+/// scheduled like `Normal` ones. Custom executors can read the priority
+/// through [`Task::priority`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum Priority {
+    /// Background work which may be delayed in favor of other tasks.
+    Low,
+    /// The default priority.
+    #[default]
+    Normal,
+    /// Latency-sensitive work.
+    High,
+}
+
+/// What happens when a task panics.
+///
+/// To resolve to a `Result` carrying the panic instead, use
+/// [`Builder::catch_panics`][crate::future::Builder::catch_panics], which
+/// changes the output type of the parallel future.
+///
+/// Panics can only be caught with the `std` feature enabled. Without it, a
+/// panic in a task is handled however the executor running it handles panics,
+/// regardless of the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PanicPolicy {
+    /// Resume the panic in the task awaiting the parallel future. This is the
+    /// default.
+    #[default]
+    Propagate,
+    /// Abort the process right away, for servers which should fail fast rather
+    /// than keep running with a broken invariant. This applies even if the
+    /// parallel future was detached or dropped.
+    Abort,
+}
+
+/// A handle to a task spawned on an [`Executor`].
+///
+/// Dropping the handle detaches the task.
+pub trait TaskHandle: Send {
+    /// Cancel the task.
+    fn cancel(self: Box<Self>);
+}
+
+/// A boxed [`TaskHandle`], as returned by [`Executor::spawn`].
+pub type BoxedHandle = Box<dyn TaskHandle>;
+
+/// The output of a task, or the payload of the panic it raised.
+pub(crate) type TaskResult<T> = Result<T, Box<dyn Any + Send>>;
+
+/// A handle to a typed task, created by [`spawn`].
+pub(crate) struct JoinHandle<T> {
+    output: oneshot::Receiver<TaskResult<T>>,
+    handle: BoxedHandle,
+    id: TaskId,
+    name: Option<Arc<str>>,
+    /// Whether the output has been returned, which fuses the handle.
+    done: bool,
+    /// How long the task took from spawn to completion, once it completed.
+    #[cfg(feature = "metrics")]
+    elapsed: Arc<OnceLock<Duration>>,
+    /// Lets draining cancel the task once this handle has been dropped.
+    #[cfg(feature = "drain")]
+    _watch: Watch,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns the id of the task.
+    pub(crate) fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Returns the name of the task, if one was set.
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns how long the task took from spawn to completion, once it has
+    /// completed.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn elapsed(&self) -> Option<Duration> {
+        self.elapsed.get().copied()
+    }
+
+    /// Cancel the task.
+    pub(crate) fn cancel(self) {
+        self.handle.cancel();
+    }
+
+    /// Returns `true` once the task has produced its output, or has been
+    /// dropped by the executor without completing.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.output.is_closed()
+    }
+
+    /// Returns a future which resolves once the task has finished, after this
+    /// handle has been dropped.
+    #[cfg(feature = "std")]
+    pub(crate) fn closed(&self) -> oneshot::Closed<TaskResult<T>> {
+        self.output.closed()
+    }
+
+    /// Poll until the task has finished, discarding its output.
+    #[cfg(feature = "std")]
+    pub(crate) fn poll_finished(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.output).poll(cx).map(drop)
+    }
+
+    /// Poll for the output of the task, without resuming panics.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the output has already been returned, rather than
+    /// reporting the task as cancelled.
+    pub(crate) fn poll_output(&mut self, cx: &mut Context<'_>) -> Poll<TaskResult<T>> {
+        assert!(!self.done, "parallel future polled after completion");
+        match ready!(Pin::new(&mut self.output).poll(cx)) {
+            Some(output) => {
+                self.done = true;
+                Poll::Ready(output)
+            }
+            None => panic!("the spawned task was cancelled"),
+        }
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match ready!(self.poll_output(cx)) {
+            Ok(output) => Poll::Ready(output),
+            Err(payload) => resume_unwind(payload),
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The executor installed through [`set_default_executor`], if any.
+#[cfg(feature = "std")]
+static DEFAULT: OnceLock<Arc<dyn Executor>> = OnceLock::new();
+
+/// Install the executor which parallel futures are spawned on by default.
+///
+/// Without this, [`par`][crate::IntoFutureExt::par] spawns futures on the
+/// runtime selected through cargo features. Installing an executor makes every
+/// parallel future which isn't given one through
+/// [`par_on`][crate::IntoFutureExt::par_on] use it instead, so applications
+/// can choose the executor for libraries which can't choose the runtime
+/// features themselves. Timers and [`par_blocking`][crate::par_blocking] still
+/// use the runtime selected through cargo features.
+///
+/// The default executor can only be installed once, and should be installed
+/// at startup before any parallel futures are spawned. If one was already
+/// installed, the given executor is handed back as an error. Looking up the
+/// default executor doesn't take a lock.
+///
+/// # Examples
+///
+/// Spawning on the `AsyncStd` executor requires the `async-std` feature.
+#[cfg_attr(feature = "async-std", doc = "```")]
+#[cfg_attr(not(feature = "async-std"), doc = "```ignore")]
+/// use parallel_future::executor::{self, AsyncStd};
+/// use parallel_future::prelude::*;
+/// use std::sync::Arc;
+///
+/// executor::set_default_executor(Arc::new(AsyncStd)).unwrap();
+///
+/// parallel_future::par_block_on(async {
+///     let res = async { 12 }.par().await; // ← spawned on `AsyncStd`
+///     assert_eq!(res, 12);
+/// })
+/// ```
+#[cfg(feature = "std")]
+pub fn set_default_executor(executor: Arc<dyn Executor>) -> Result<(), Arc<dyn Executor>> {
+    DEFAULT.set(executor)
+}
+
+/// The options passed to [`configure`], if any, and whether the runtime has
+/// already read them.
+#[cfg(feature = "std")]
+static RUNTIME: Mutex<Startup> = Mutex::new(Startup {
+    config: None,
+    started: false,
+});
+
+#[cfg(feature = "std")]
+struct Startup {
+    config: Option<RuntimeConfig>,
+    started: bool,
+}
+
+/// Options for the runtime selected through cargo features.
+///
+/// This is passed to [`configure`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    pub(crate) worker_threads: Option<usize>,
+}
+
+#[cfg(feature = "std")]
+impl RuntimeConfig {
+    /// Create a new config, which leaves every option at the runtime's
+    /// default.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of threads the runtime runs tasks on.
+    ///
+    /// By default that's one thread per available CPU core.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `threads` is zero.
+    pub fn worker_threads(mut self, threads: usize) -> Self {
+        assert!(threads > 0, "a runtime needs at least one worker thread");
+        self.worker_threads = Some(threads);
+        self
+    }
+}
+
+/// Configure the runtime selected through cargo features.
+///
+/// This lets applications size the runtime from their own configuration.
+/// Runtimes only read these options when they start, which is when the first
+/// task is spawned on them, so this should be called at startup before any
+/// parallel futures are spawned. The runtime can only be configured once.
+///
+/// Options are never silently ignored: the given config is handed back as an
+/// error if the runtime was already configured or has already started, or if
+/// it doesn't support one of the options that were set. Which options are
+/// supported depends on the runtime:
+///
+/// - The smol runtime supports the number of worker threads.
+/// - async-std only reads the number of worker threads from the
+///   `ASYNC_STD_THREAD_COUNT` environment variable, which has to be set before
+///   the program starts, so it doesn't support any options.
+/// - Tokio tasks are spawned on whichever runtime is current, which the
+///   application builds itself, so it doesn't support any options either. Set
+///   them on Tokio's runtime builder instead.
+///
+/// Executors installed through [`set_default_executor`] or passed to
+/// [`par_on`][crate::IntoFutureExt::par_on] are configured by whoever created
+/// them, and ignore these options as well.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::executor::{self, RuntimeConfig};
+///
+/// let config = RuntimeConfig::new().worker_threads(2);
+/// if let Err(config) = executor::configure(config) {
+///     // ← async-std is sized through `ASYNC_STD_THREAD_COUNT` instead
+///     println!("runtime options not applied: {:?}", config);
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn configure(config: RuntimeConfig) -> Result<(), RuntimeConfig> {
+    // Check and store the options under the same lock the runtime reads them
+    // under, so they can't arrive just after it started.
+    let mut startup = RUNTIME.lock().unwrap();
+    if startup.started || startup.config.is_some() || runtime::configure(&config).is_err() {
+        return Err(config);
+    }
+    startup.config = Some(config);
+    Ok(())
+}
+
+/// Returns the options set through [`configure`], for the runtime to start
+/// with. Options passed to `configure` after this are rejected.
+#[cfg(all(feature = "smol", not(any(feature = "async-std", feature = "tokio"))))]
+pub(crate) fn start_runtime() -> RuntimeConfig {
+    let mut startup = RUNTIME.lock().unwrap();
+    startup.started = true;
+    startup.config.clone().unwrap_or_default()
+}
+
+/// Returns the executor installed through [`set_default_executor`], or else
+/// the executor of the runtime selected through cargo features.
+#[cfg(feature = "std")]
+fn default_executor() -> &'static dyn Executor {
+    match DEFAULT.get() {
+        Some(executor) => &**executor,
+        None => runtime::DEFAULT,
+    }
+}
+
+/// Without `std` there's no runtime, and so no default executor either.
+#[cfg(not(feature = "std"))]
+fn default_executor() -> &'static dyn Executor {
+    panic!("there is no default executor without the `std` feature, use `par_on` instead")
+}
+
+/// Spawn a future on an executor, falling back to the default executor.
+///
+/// This panics if the executor can't spawn tasks. See [`try_spawn`] for a
+/// fallible version.
+pub(crate) fn spawn<F>(
+    executor: Option<&dyn Executor>,
+    config: Config,
+    fut: F,
+) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match spawn_with(executor, config, fut, |executor, task| {
+        Ok(executor.spawn(task))
+    }) {
+        Ok(handle) => handle,
+        Err(_) => unreachable!("spawning infallibly returned an error"),
+    }
+}
+
+/// Spawn a future on an executor, returning an error if it can't spawn tasks.
+pub(crate) fn try_spawn<F>(
+    executor: Option<&dyn Executor>,
+    config: Config,
+    fut: F,
+) -> Result<JoinHandle<F::Output>, SpawnError>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    spawn_with(executor, config, fut, |executor, task| {
+        executor.try_spawn(task)
+    })
+}
+
+/// Wrap a future in a task, and spawn it with the given function.
+///
+/// Panics inside the future are caught and resumed when the output is
+/// awaited, so they surface the same way regardless of the executor. Tasks
+/// with the [`PanicPolicy::Abort`] policy abort the process instead. With the
+/// `tracing` feature enabled, the future is instrumented with the configured
+/// span, or else the span which is current at the time of spawning. Lifecycle
+/// events are reported to the metrics hook, if one was registered, along with
+/// the bytes the future allocated if the `track-allocations` feature is on.
+/// Tasks which the executor refuses don't report any events. With the `drain`
+/// feature on, the task is cancelled early if the program is drained after
+/// its handle was dropped.
+fn spawn_with<F, S>(
+    executor: Option<&dyn Executor>,
+    config: Config,
+    fut: F,
+    spawn: S,
+) -> Result<JoinHandle<F::Output>, SpawnError>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+    S: FnOnce(&dyn Executor, Task) -> Result<BoxedHandle, SpawnError>,
+{
+    let (sender, output) = oneshot::channel();
+    let name = config.name.clone();
+    #[cfg(feature = "tracing")]
+    let fut = {
+        use tracing::Instrument;
+        let span = config.span.clone().unwrap_or_else(tracing::Span::current);
+        fut.instrument(span)
+    };
+    let id = TaskId::next();
+    #[cfg(feature = "std")]
+    let location = config.location();
+    #[cfg(feature = "std")]
+    let panic_policy = config.panic_policy;
+    #[cfg(feature = "std")]
+    let stagger = config.stagger;
+    #[cfg(feature = "std")]
+    let lifecycle = Lifecycle::new(id, name.clone(), location);
+    #[cfg(feature = "std")]
+    let handoff = lifecycle.handoff();
+    #[cfg(feature = "track-allocations")]
+    let fut = lifecycle.track(fut);
+    #[cfg(feature = "drain")]
+    let switch = lifecycle.switch();
+    #[cfg(feature = "metrics")]
+    let (elapsed, started) = (Arc::new(OnceLock::new()), Instant::now());
+    #[cfg(feature = "metrics")]
+    let elapsed_2 = elapsed.clone();
+    let fut = async move {
+        #[cfg(feature = "std")]
+        lifecycle.polled();
+        #[cfg(feature = "std")]
+        if let Some(max) = stagger {
+            runtime::sleep(jitter(max)).await;
+        }
+        let output = catch_unwind(fut).await;
+        #[cfg(feature = "metrics")]
+        let _ = elapsed_2.set(started.elapsed());
+        #[cfg(feature = "debug")]
+        if let (Err(_), Some(location)) = (&output, location) {
+            // The panic message was printed on this thread, so print where the
+            // task came from right after it.
+            std::eprintln!("note: the panicking task was spawned at {location}");
+        }
+        #[cfg(feature = "std")]
+        if output.is_err() && panic_policy == PanicPolicy::Abort {
+            std::process::abort();
+        }
+        #[cfg(feature = "std")]
+        lifecycle.finish(output.is_err());
+        sender.send(output);
+    };
+    #[cfg(feature = "drain")]
+    let fut = Drainable::new(fut, switch.clone());
+    let yield_every = match config.priority {
+        Priority::Low => 1,
+        Priority::Normal | Priority::High => config.yield_every,
+    };
+    let task = match yield_every {
+        0 => Task::new(fut, id, config),
+        n => Task::new(YieldEvery::new(fut, n), id, config),
+    };
+    let handle = spawn(executor.unwrap_or_else(|| default_executor()), task)?;
+    #[cfg(feature = "std")]
+    handoff.accepted();
+    Ok(JoinHandle {
+        output,
+        handle,
+        id,
+        name,
+        done: false,
+        #[cfg(feature = "metrics")]
+        elapsed,
+        #[cfg(feature = "drain")]
+        _watch: Watch::new(switch),
+    })
+}
+
+/// Spawn a non-`Send` future on the current thread.
+///
+/// Like [`spawn`], panics inside the future are caught and resumed when the
+/// output is awaited.
+#[cfg(feature = "async-std")]
+pub(crate) fn spawn_local<F>(fut: F, name: Option<Arc<str>>) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let (sender, output) = oneshot::channel();
+    #[cfg(feature = "tracing")]
+    let fut = {
+        use tracing::Instrument;
+        fut.instrument(tracing::Span::current())
+    };
+    let id = TaskId::next();
+    let lifecycle = Lifecycle::new(id, name.clone(), None);
+    let handoff = lifecycle.handoff();
+    #[cfg(feature = "track-allocations")]
+    let fut = lifecycle.track(fut);
+    #[cfg(feature = "drain")]
+    let switch = lifecycle.switch();
+    #[cfg(feature = "metrics")]
+    let (elapsed, started) = (Arc::new(OnceLock::new()), Instant::now());
+    #[cfg(feature = "metrics")]
+    let elapsed_2 = elapsed.clone();
+    let fut = async move {
+        lifecycle.polled();
+        let output = catch_unwind(fut).await;
+        #[cfg(feature = "metrics")]
+        let _ = elapsed_2.set(started.elapsed());
+        lifecycle.finish(output.is_err());
+        sender.send(output);
+    };
+    #[cfg(feature = "drain")]
+    let fut = Drainable::new(fut, switch.clone());
+    let handle = runtime::spawn_local(fut, name.as_deref());
+    handoff.accepted();
+    JoinHandle {
+        output,
+        handle,
+        id,
+        name,
+        done: false,
+        #[cfg(feature = "metrics")]
+        elapsed,
+        #[cfg(feature = "drain")]
+        _watch: Watch::new(switch),
+    }
+}
+
+/// A future which yields to the scheduler after every `n` polls.
+#[pin_project]
+struct YieldEvery<F> {
+    #[pin]
+    fut: F,
+    n: usize,
+    polls: usize,
+}
+
+impl<F> YieldEvery<F> {
+    fn new(fut: F, n: usize) -> Self {
+        Self { fut, n, polls: 0 }
+    }
+}
+
+impl<F: Future> Future for YieldEvery<F> {
+    type Output = F::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if *this.polls == *this.n {
+            *this.polls = 0;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        *this.polls += 1;
+        #[cfg(feature = "std")]
+        yield_now::take_yielded();
+        let poll = this.fut.poll(cx);
+        // Yielding on its own starts the task's budget over.
+        #[cfg(feature = "std")]
+        if yield_now::take_yielded() {
+            *this.polls = 0;
+        }
+        poll
+    }
+}
+
+/// Pick a random duration between zero and `max`.
+#[cfg(feature = "std")]
+fn jitter(max: Duration) -> Duration {
+    // Every `RandomState` is seeded differently, which is random enough to
+    // spread tasks out without pulling in a dependency.
+    let random = RandomState::new().hash_one(());
+    let nanos = max.as_nanos().max(1);
+    Duration::from_nanos((u128::from(random) % nanos) as u64)
+}
+
+/// Catch panics raised while polling a future.
+#[cfg(feature = "std")]
+async fn catch_unwind<F: Future>(fut: F) -> TaskResult<F::Output> {
+    let mut fut = core::pin::pin!(fut);
+    core::future::poll_fn(|cx| {
+        match panic::catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    })
+    .await
+}
+
+/// Without `std` panics can't be caught, so every task completes normally.
+#[cfg(not(feature = "std"))]
+async fn catch_unwind<F: Future>(fut: F) -> TaskResult<F::Output> {
+    Ok(fut.await)
+}
+
+/// Resume a panic caught by [`catch_unwind`].
+#[cfg(feature = "std")]
+fn resume_unwind(payload: Box<dyn Any + Send>) -> ! {
+    panic::resume_unwind(payload)
+}
+
+/// Without `std` panics are never caught, so there's nothing to resume.
+#[cfg(not(feature = "std"))]
+fn resume_unwind(_payload: Box<dyn Any + Send>) -> ! {
+    unreachable!("panics aren't caught without the `std` feature")
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::future::poll_fn;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::prelude::*;
+    use crate::testing::TestExecutor;
+
+    #[derive(Debug, Clone, Default)]
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Executor for Counting {
+        fn spawn(&self, task: Task) -> BoxedHandle {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            runtime::DEFAULT.spawn(task)
+        }
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct Recording(Arc<Mutex<String>>);
+
+    impl Executor for Recording {
+        fn spawn(&self, task: Task) -> BoxedHandle {
+            let config = format!(
+                "{:?} {:?} {:?} {:?} {:?}",
+                task.name(),
+                task.stack_size(),
+                task.priority(),
+                task.affinity(),
+                task.prefers_local()
+            );
+            *self.0.lock().unwrap() = config;
+            runtime::DEFAULT.spawn(task)
+        }
+    }
+
+    /// An executor which has been shut down.
+    #[derive(Debug, Clone)]
+    struct ShutDown;
+
+    impl Executor for ShutDown {
+        fn spawn(&self, _task: Task) -> BoxedHandle {
+            panic!("the executor has been shut down")
+        }
+
+        fn try_spawn(&self, _task: Task) -> Result<BoxedHandle, SpawnError> {
+            Err(SpawnError::new())
+        }
+    }
+
+    /// A future which wakes itself and returns `Pending` a number of times.
+    async fn pending(times: usize) {
+        let mut polls = 0;
+        poll_fn(|cx| {
+            if polls == times {
+                return Poll::Ready(());
+            }
+            polls += 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+
+    #[test]
+    fn jitter() {
+        let max = Duration::from_millis(200);
+        let delays: Vec<_> = (0..20).map(|_| super::jitter(max)).collect();
+        assert!(delays.iter().all(|delay| *delay <= max));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+
+    #[test]
+    fn configure() {
+        // The runtime is shared with the other tests, so make sure it has
+        // started: options can't be applied after that, and async-std and
+        // Tokio never support them.
+        runtime::block_on(async { async {}.par().await });
+        let config = RuntimeConfig::new().worker_threads(2);
+        assert_eq!(super::configure(config.clone()), Err(config));
+
+        // The runtime can only be configured once.
+        #[cfg(any(feature = "async-std", feature = "tokio"))]
+        {
+            assert_eq!(super::configure(RuntimeConfig::new()), Ok(()));
+            let res = super::configure(RuntimeConfig::new());
+            assert_eq!(res, Err(RuntimeConfig::new()));
+        }
+    }
+
+    #[test]
+    fn set_default_executor() {
+        // The default executor can't be uninstalled, and would be used by every
+        // other test in the process, so this runs in a child process which
+        // re-runs just this test.
+        if std::env::var_os("PARALLEL_FUTURE_DEFAULT_EXECUTOR").is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "executor::test::set_default_executor"])
+                .env("PARALLEL_FUTURE_DEFAULT_EXECUTOR", "1")
+                .stdout(std::process::Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+            return;
+        }
+
+        runtime::block_on(async {
+            let executor = Counting::default();
+            super::set_default_executor(Arc::new(executor.clone())).unwrap();
+            assert_eq!(async { 12 }.par().await, 12);
+            assert_eq!(executor.0.load(Ordering::SeqCst), 1);
+
+            // The default executor can only be installed once.
+            let res = super::set_default_executor(Arc::new(Counting::default()));
+            assert!(res.is_err());
+        })
+    }
+
+    #[test]
+    fn par_on() {
+        runtime::block_on(async {
+            let executor = Counting::default();
+            let res = async { "chashu is a cat" }.par_on(&executor).await;
+            assert_eq!(res, "chashu is a cat");
+            assert_eq!(executor.0.load(Ordering::SeqCst), 1);
+        })
+    }
+
+    #[test]
+    fn try_spawn() {
+        runtime::block_on(async {
+            let mut fut = async { 12 }.par_on(&ShutDown);
+            assert_eq!(fut.try_spawn(), Err(SpawnError::new()));
+            assert!(!fut.has_started());
+
+            let mut fut = async { 12 }.par_on(&Counting::default());
+            assert_eq!(fut.try_spawn(), Ok(()));
+            assert!(fut.has_started());
+            assert_eq!(fut.await, 12);
+
+            let fut = async { 12 }.try_par().unwrap();
+            assert_eq!(fut.await, 12);
+        })
+    }
+
+    #[test]
+    fn builder_options() {
+        runtime::block_on(async {
+            let executor = Recording::default();
+            let res = async { 12 }
+                .par_builder()
+                .name("nori")
+                .stack_size(1024)
+                .priority(Priority::Low)
+                .affinity(3)
+                .prefer_local()
+                .on(&executor)
+                .build()
+                .await;
+            assert_eq!(res, 12);
+            assert_eq!(
+                *executor.0.lock().unwrap(),
+                r#"Some("nori") Some(1024) Low Some(3) true"#
+            );
+        })
+    }
+
+    #[test]
+    fn yield_every() {
+        let steps = |yield_every| {
+            let executor = TestExecutor::new();
+            let fut = pending(3)
+                .par_builder()
+                .yield_every(yield_every)
+                .on(&executor)
+                .build()
+                .detach();
+            let mut steps = 0;
+            while executor.step() {
+                steps += 1;
+            }
+            assert!(fut.is_finished());
+            steps
+        };
+        assert_eq!(steps(0), 4);
+        assert_eq!(steps(1), 7);
+        assert_eq!(steps(2), 5);
+    }
+
+    #[test]
+    fn yield_now_resets_budget() {
+        let executor = TestExecutor::new();
+        let fut = async {
+            for _ in 0..3 {
+                crate::yield_now().await;
+            }
+        }
+        .par_builder()
+        .yield_every(2)
+        .on(&executor)
+        .build()
+        .detach();
+        let mut steps = 0;
+        while executor.step() {
+            steps += 1;
+        }
+        assert!(fut.is_finished());
+        assert_eq!(steps, 4); // ← no polls spent on the budget's yields
+    }
+}
+
+/// The tests above need a runtime, so without `std` this covers spawning on an
+/// executor passed in explicitly.
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_test {
+    use core::pin::pin;
+    use core::task::Waker;
+
+    use super::*;
+    use crate::IntoFutureExt;
+
+    /// Runs tasks to completion as soon as they're spawned.
+    #[derive(Debug, Clone)]
+    struct Inline;
+
+    impl Executor for Inline {
+        fn spawn(&self, mut task: Task) -> BoxedHandle {
+            let mut cx = Context::from_waker(Waker::noop());
+            while Pin::new(&mut task).poll(&mut cx).is_pending() {}
+            Box::new(Done)
+        }
+    }
+
+    struct Done;
+
+    impl TaskHandle for Done {
+        fn cancel(self: Box<Self>) {}
+    }
+
+    #[test]
+    fn par_on() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let fut = pin!(async { 12 }.par_on(&Inline));
+        assert_eq!(fut.poll(&mut cx), Poll::Ready(12));
+
+        let fut = pin!(async { 12 }.par_on(&Inline).map(|n| n * 2));
+        assert_eq!(fut.poll(&mut cx), Poll::Ready(24));
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::future::IntoFuture;
+
+use crate::executor::Executor;
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// A factory which creates a fresh parallel future every time it's called.
+///
+/// A `ParallelFuture` can only be run once. A `ParFactory` wraps a closure
+/// which creates futures instead, so the same work can be scheduled again
+/// without reconstructing the closure. The factory can be cloned if the
+/// closure can be.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::ParFactory;
+///
+/// parallel_future::par_block_on(async {
+///     let factory = ParFactory::new(|| async { 12 });
+///     assert_eq!(factory.par().await, 12);
+///     assert_eq!(factory.par().await, 12); // ← runs the closure again
+/// })
+/// ```
+#[derive(Clone)]
+pub struct ParFactory<F> {
+    f: F,
+}
+
+impl<F, Fut> ParFactory<F>
+where
+    F: Fn() -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    /// Create a new factory from a closure.
+    pub fn new(f: F) -> Self {
+        Self { f }
+    }
+
+    /// Create a new parallelizable future.
+    ///
+    /// See [`par`][crate::IntoFutureExt::par] for more details.
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn par(&self) -> ParallelFuture<Fut> {
+        (self.f)().par()
+    }
+
+    /// Create a new parallelizable future which is spawned on the given
+    /// executor.
+    ///
+    /// See [`par_on`][crate::IntoFutureExt::par_on] for more details.
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn par_on<E>(&self, executor: &E) -> ParallelFuture<Fut>
+    where
+        E: Executor + Clone + 'static,
+    {
+        (self.f)().par_on(executor)
+    }
+}
+
+impl<F> fmt::Debug for ParFactory<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParFactory").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::runtime;
+
+    #[test]
+    fn reruns() {
+        runtime::block_on(async {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let factory = ParFactory::new(move || {
+                let runs = runs.clone();
+                async move { runs.fetch_add(1, Ordering::SeqCst) }
+            });
+            let clone = factory.clone();
+            let (a, b) = (factory.par(), clone.par()).join().await;
+            assert_eq!(a + b, 1);
+            assert_eq!(factory.par().await, 2);
+        })
+    }
+}
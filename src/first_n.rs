@@ -0,0 +1,171 @@
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// Run fallible futures in parallel, and resolve with the first `n` successful
+/// outputs.
+///
+/// All futures are spawned when the returned future is first polled. As soon
+/// as `n` of them have succeeded, the rest are cancelled right away. Errors are
+/// discarded; if fewer than `n` futures succeed, the returned future resolves
+/// with every successful output once all futures have completed. Outputs are
+/// returned in the order the futures completed in.
+///
+/// This is the "hedged request" pattern: sending the same request to multiple
+/// replicas, and using whichever respond first.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_first_n;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let futs = [30, 10, 20].map(|ms| async move {
+///         async_std::task::sleep(Duration::from_millis(ms)).await;
+///         Ok::<_, ()>(ms)
+///     });
+///     let outputs = par_first_n(futs, 2).await;
+///     assert_eq!(outputs, vec![10, 20]);
+/// })
+/// ```
+pub fn par_first_n<I, Fut, T, E>(futs: I, n: usize) -> FirstN<Fut, T>
+where
+    I: IntoIterator<Item = Fut>,
+    Fut: IntoFuture<Output = Result<T, E>>,
+    Fut::IntoFuture: Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    let in_flight = match n {
+        0 => Vec::new(),
+        _ => futs
+            .into_iter()
+            .map(|fut| fut.par().cancel_on_drop())
+            .collect(),
+    };
+    // At most one output per future, however large `n` is.
+    let outputs = Vec::with_capacity(in_flight.len().min(n));
+    FirstN {
+        in_flight,
+        outputs,
+        n,
+    }
+}
+
+/// A future which resolves with the first successful outputs of parallel
+/// futures.
+///
+/// This type is constructed by the [`par_first_n`][crate::par_first_n]
+/// function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct FirstN<Fut: IntoFuture, T> {
+    in_flight: Vec<ParallelFuture<Fut>>,
+    outputs: Vec<T>,
+    n: usize,
+}
+
+// The futures and outputs are never pinned, so `FirstN` can be moved
+// regardless of their types.
+impl<Fut: IntoFuture, T> Unpin for FirstN<Fut, T> {}
+
+impl<Fut, T, E> Future for FirstN<Fut, T>
+where
+    Fut: IntoFuture<Output = Result<T, E>>,
+    Fut::IntoFuture: Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    type Output = Vec<T>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut i = 0;
+        while i < this.in_flight.len() && this.outputs.len() < this.n {
+            match Pin::new(&mut this.in_flight[i]).poll(cx) {
+                Poll::Ready(output) => {
+                    drop(this.in_flight.swap_remove(i));
+                    if let Ok(output) = output {
+                        this.outputs.push(output);
+                    }
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+
+        if this.outputs.len() == this.n || this.in_flight.is_empty() {
+            // Dropping the remaining futures cancels them.
+            this.in_flight.clear();
+            return Poll::Ready(mem::take(&mut this.outputs));
+        }
+        Poll::Pending
+    }
+}
+
+impl<Fut, T> fmt::Debug for FirstN<Fut, T>
+where
+    Fut: IntoFuture + fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FirstN")
+            .field("in_flight", &self.in_flight)
+            .field("outputs", &self.outputs)
+            .field("n", &self.n)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn cancels_the_rest() {
+        runtime::block_on(async {
+            let (guard_1, dropped_1) = drop_signal();
+            let (guard_2, dropped_2) = drop_signal();
+            let futs = vec![None, Some(guard_1), None, Some(guard_2)]
+                .into_iter()
+                .enumerate()
+                .map(|(n, guard)| async move {
+                    if let Some(_guard) = guard {
+                        future::pending::<()>().await;
+                    }
+                    Ok::<_, ()>(n)
+                });
+            let mut outputs = par_first_n(futs, 2).await;
+            outputs.sort();
+            assert_eq!(outputs, vec![0, 2]);
+            (dropped_1, dropped_2).join().await;
+        })
+    }
+
+    #[test]
+    fn skips_errors() {
+        runtime::block_on(async {
+            let futs = [Err(1), Ok(2), Err(3), Ok(4)].map(|res| async move { res });
+            let mut outputs = par_first_n(futs, 3).await;
+            outputs.sort();
+            assert_eq!(outputs, vec![2, 4]);
+
+            let futs = [async { Ok::<_, ()>(1) }];
+            assert!(par_first_n(futs, 0).await.is_empty());
+
+            // Asking for more outputs than there are futures returns them all.
+            let futs = [Ok(1), Err(2), Ok(3)].map(|res| async move { res });
+            let mut outputs = par_first_n(futs, usize::MAX).await;
+            outputs.sort();
+            assert_eq!(outputs, vec![1, 3]);
+        })
+    }
+}
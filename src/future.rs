@@ -0,0 +1,1160 @@
+//! Combinators for parallel futures.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt;
+use core::future::{Future, IntoFuture};
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+#[cfg(feature = "std")]
+use std::{
+    any::Any,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use pin_project::pin_project;
+
+use crate::executor::{Config, Executor, PanicPolicy, Priority};
+#[cfg(feature = "std")]
+use crate::runtime::{self, BoxFuture, Sleep};
+use crate::ParallelFuture;
+
+#[cfg(feature = "std")]
+pub use crate::abort::{Abortable, Aborted};
+#[cfg(feature = "std")]
+pub use crate::first_n::FirstN;
+#[cfg(feature = "std")]
+pub use crate::limiter::Limited;
+#[cfg(feature = "std")]
+pub use crate::nursery::NurseryFuture;
+#[cfg(feature = "std")]
+pub use crate::rate_limiter::RateLimited;
+#[cfg(feature = "std")]
+pub use crate::result::ParResult;
+#[cfg(feature = "std")]
+pub use crate::retry::Retry;
+pub use crate::select::{Select, SelectAll};
+#[cfg(feature = "std")]
+pub use crate::shared::Shared;
+#[cfg(feature = "std")]
+pub use crate::token::Cancelled;
+#[cfg(feature = "std")]
+pub use crate::yield_now::YieldNow;
+
+/// Configure the task a future is spawned as.
+///
+/// This type is constructed by the
+/// [`par_builder`][crate::IntoFutureExt::par_builder] method on
+/// [`IntoFutureExt`][crate::IntoFutureExt].
+#[derive(Debug)]
+#[must_use = "builders do nothing unless you `.await` them or call `build`"]
+pub struct Builder<Fut> {
+    into_future: Fut,
+    executor: Option<Arc<dyn Executor>>,
+    config: Config,
+}
+
+impl<Fut> Builder<Fut> {
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub(crate) fn new(into_future: Fut) -> Self {
+        Self {
+            into_future,
+            executor: None,
+            config: Config::default(),
+        }
+    }
+
+    /// Spawn the task on the given executor, rather than the default one.
+    ///
+    /// See [`par_on`][crate::IntoFutureExt::par_on] for more details.
+    pub fn on<E>(mut self, executor: &E) -> Self
+    where
+        E: Executor + Clone + 'static,
+    {
+        self.executor = Some(Arc::new(executor.clone()));
+        self
+    }
+
+    /// Set the name of the task.
+    ///
+    /// The name is passed on to the runtime where supported. Tokio can only
+    /// name tasks through its unstable APIs, so there the name is ignored.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.config.name = Some(name.into().into());
+        self
+    }
+
+    /// Set the stack size of the task in bytes.
+    ///
+    /// Tasks on async-std and Tokio run on the runtime's worker threads and
+    /// don't have a stack of their own, so for those this is a no-op. Custom
+    /// [`Executor`]s can read the stack size through
+    /// [`Task::stack_size`][crate::executor::Task::stack_size].
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.config.stack_size = Some(bytes);
+        self
+    }
+
+    /// Make the task yield back to the scheduler after every `n` polls.
+    ///
+    /// This gives other tasks a chance to run in between polls of long-running
+    /// futures, without having to add yield points to the future itself. It
+    /// only affects this task, and yield points can only be inserted between
+    /// polls: a future which never returns `Pending` still can't be
+    /// interrupted. Setting `n` to `0` disables this, which is the default.
+    ///
+    /// When the future yields on its own through `yield_now`, the count starts
+    /// over.
+    pub fn yield_every(mut self, n: usize) -> Self {
+        self.config.yield_every = n;
+        self
+    }
+
+    /// Set the scheduling priority of the task.
+    ///
+    /// See [`Priority`] for how each runtime treats priorities.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.config.priority = priority;
+        self
+    }
+
+    /// Set what happens when the task panics.
+    ///
+    /// By default panics are resumed in the task awaiting the parallel future.
+    /// See [`PanicPolicy`] for the alternatives.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use parallel_future::prelude::*;
+    /// use parallel_future::executor::PanicPolicy;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     async { panic!("oh no") }
+    ///         .par_builder()
+    ///         .on_panic(PanicPolicy::Abort)
+    ///         .await; // ← aborts the process
+    /// })
+    /// ```
+    pub fn on_panic(mut self, policy: PanicPolicy) -> Self {
+        self.config.panic_policy = policy;
+        self
+    }
+
+    /// Hint that the task should run on the core or worker with the given id.
+    ///
+    /// This is a best-effort hint. async-std, Tokio and smol schedule tasks on
+    /// work-stealing thread pools which don't support pinning, so for those
+    /// this is a no-op. Custom [`Executor`]s which
+    /// run one worker per core can read the hint through
+    /// [`Task::affinity`][crate::executor::Task::affinity] to place tasks
+    /// deterministically.
+    pub fn affinity(mut self, core_id: usize) -> Self {
+        self.config.affinity = Some(core_id);
+        self
+    }
+
+    /// Hint that the task should run on the thread which spawned it, and only
+    /// move to another thread when that one is busy.
+    ///
+    /// Short tasks which work on data the spawning task just touched run
+    /// faster on the same core, since that data is still in its cache. How
+    /// much of this is possible depends on the runtime:
+    ///
+    /// - Tokio already does this for every task spawned from one of its worker
+    ///   threads: the task goes into that worker's local queue, and other
+    ///   workers only steal it when they run out of work. Tasks spawned from
+    ///   outside the runtime go into its global queue.
+    /// - async-std and smol always schedule tasks onto a global queue shared
+    ///   by all of their threads, so there this is a no-op.
+    /// - Custom [`Executor`]s can read the hint
+    ///   through [`Task::prefers_local`][crate::executor::Task::prefers_local].
+    pub fn prefer_local(mut self) -> Self {
+        self.config.prefer_local = true;
+        self
+    }
+
+    /// Delay the start of the task by a random amount of time, up to `max`.
+    ///
+    /// Lazy parallel futures which are joined together are all spawned on the
+    /// first poll of the join, so their work starts at the same moment. Giving
+    /// each of them a random delay spreads that burst out over `max`, which
+    /// smooths out the load on rate-sensitive downstreams. The task is still
+    /// spawned right away; it sleeps before its future is first polled.
+    ///
+    /// For a hard limit on how many tasks start per interval, use a
+    /// [`RateLimiter`][crate::RateLimiter] instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use futures_concurrency::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let futs: Vec<_> = (0..8)
+    ///         .map(|n| {
+    ///             async move { n }
+    ///                 .par_builder()
+    ///                 .stagger(Duration::from_millis(20))
+    ///                 .build()
+    ///         })
+    ///         .collect();
+    ///     let outputs = futs.join().await; // ← starts spread out over 20ms
+    ///     assert_eq!(outputs.into_iter().sum::<i32>(), 28);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn stagger(mut self, max: Duration) -> Self {
+        self.config.stagger = Some(max);
+        self
+    }
+}
+
+impl<Fut> Builder<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    /// Convert the future into a parallelizable future with the configured
+    /// options.
+    ///
+    /// Builders can also be `.await`ed directly, which calls this method.
+    pub fn build(self) -> ParallelFuture<Fut> {
+        ParallelFuture::new(self.into_future, self.executor, self.config)
+    }
+
+    /// Convert the future into a parallelizable future with the configured
+    /// options, which resolves to `Err` with the panic payload if it panics.
+    ///
+    /// This is the same as calling [`build`][Builder::build] followed by
+    /// [`catch_unwind`][crate::ParallelFuture::catch_unwind].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { panic!("oh no") }
+    ///         .par_builder()
+    ///         .name("panicky")
+    ///         .catch_panics()
+    ///         .await;
+    ///     assert!(res.is_err());
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn catch_panics(self) -> CatchUnwind<Fut> {
+        self.build().catch_unwind()
+    }
+}
+
+impl<Fut> IntoFuture for Builder<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = Fut::Output;
+    type IntoFuture = ParallelFuture<Fut>;
+    fn into_future(self) -> Self::IntoFuture {
+        self.build()
+    }
+}
+
+/// Convert a configured builder into a parallelizable future.
+///
+/// This is the same as calling [`build`][Builder::build], and lets APIs which
+/// take `impl Into<ParallelFuture<Fut>>` accept both plain parallel futures and
+/// builders.
+impl<Fut> From<Builder<Fut>> for ParallelFuture<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    fn from(builder: Builder<Fut>) -> Self {
+        builder.build()
+    }
+}
+
+/// A parallel future which catches panics in the spawned task.
+///
+/// This type is constructed by the
+/// [`catch_unwind`][crate::ParallelFuture::catch_unwind] method on
+/// [`ParallelFuture`], and the [`catch_panics`][Builder::catch_panics] method
+/// on [`Builder`].
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg(feature = "std")]
+pub struct CatchUnwind<Fut: IntoFuture> {
+    inner: ParallelFuture<Fut>,
+}
+
+#[cfg(feature = "std")]
+impl<Fut: IntoFuture> CatchUnwind<Fut> {
+    pub(crate) fn new(inner: ParallelFuture<Fut>) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Fut> Future for CatchUnwind<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = Result<Fut::Output, Box<dyn Any + Send>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner.poll_output(cx)
+    }
+}
+
+/// Chain more combinators onto the futures returned by [`ParallelFuture`]'s
+/// combinators.
+///
+/// This is implemented for [`Map`], [`MapErr`], [`Inspect`] and [`Then`], so
+/// calls like `.map(..).inspect(..)` can be chained, and can't be implemented
+/// outside of this crate. Each method works the same as the method of the same
+/// name on [`ParallelFuture`]. It's included in the
+/// [`prelude`][crate::prelude].
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+///
+/// parallel_future::par_block_on(async {
+///     let res = async { 1 }
+///         .par()
+///         .map(|n| n + 1)
+///         .inspect(|n| println!("mapped to {n}"))
+///         .then(|n| async move { n * 2 })
+///         .await;
+///     assert_eq!(res, 4);
+/// })
+/// ```
+pub trait ParallelFutureExt: Future + Sized + sealed::Sealed {
+    /// Map the output with a closure.
+    ///
+    /// See [`ParallelFuture::map`] for more details.
+    fn map<F, U>(self, f: F) -> Map<Self, F>
+    where
+        F: FnOnce(Self::Output) -> U,
+    {
+        Map::new(self, f)
+    }
+
+    /// Map the error of a fallible output with a closure.
+    ///
+    /// See [`ParallelFuture::map_err`] for more details.
+    fn map_err<F, T, E, E2>(self, f: F) -> MapErr<Self, F>
+    where
+        Self: Future<Output = Result<T, E>>,
+        F: FnOnce(E) -> E2,
+    {
+        MapErr::new(self, f)
+    }
+
+    /// Call a closure with a reference to the output.
+    ///
+    /// See [`ParallelFuture::inspect`] for more details.
+    fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        F: FnOnce(&Self::Output),
+    {
+        Inspect::new(self, f)
+    }
+
+    /// Pass the output to an async continuation.
+    ///
+    /// See [`ParallelFuture::then`] for more details.
+    fn then<F, Fut2>(self, f: F) -> Then<Self, F, Fut2>
+    where
+        F: FnOnce(Self::Output) -> Fut2,
+        Fut2: IntoFuture,
+    {
+        Then::new(self, f)
+    }
+}
+
+impl<Fut: Future + sealed::Sealed> ParallelFutureExt for Fut {}
+
+mod sealed {
+    #![allow(unreachable_pub)]
+
+    use core::future::IntoFuture;
+
+    /// Keeps [`ParallelFutureExt`][super::ParallelFutureExt] from being
+    /// implemented outside of this crate.
+    pub trait Sealed {}
+
+    impl<Fut, F> Sealed for super::Map<Fut, F> {}
+    impl<Fut, F> Sealed for super::MapErr<Fut, F> {}
+    impl<Fut, F> Sealed for super::Inspect<Fut, F> {}
+    impl<Fut, F, Fut2: IntoFuture> Sealed for super::Then<Fut, F, Fut2> {}
+}
+
+/// A parallel future which maps its output with a closure.
+///
+/// This type is constructed by the [`map`][crate::ParallelFuture::map] method
+/// on [`ParallelFuture`] and [`ParallelFutureExt`]. Dropping it cancels the
+/// spawned future, regardless of how many combinators it's nested in.
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Map<Fut, F> {
+    #[pin]
+    inner: Fut,
+    f: Option<F>,
+}
+
+impl<Fut, F> Map<Fut, F> {
+    pub(crate) fn new(inner: Fut, f: F) -> Self {
+        Self { inner, f: Some(f) }
+    }
+}
+
+impl<Fut, F, U> Future for Map<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(Fut::Output) -> U,
+{
+    type Output = U;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.inner.poll(cx));
+        let f = this.f.take().expect("`Map` polled after completion");
+        Poll::Ready(f(output))
+    }
+}
+
+impl<Fut: fmt::Debug, F> fmt::Debug for Map<Fut, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Map")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A parallel future which calls a closure with a reference to its output.
+///
+/// This type is constructed by the [`inspect`][crate::ParallelFuture::inspect]
+/// method on [`ParallelFuture`] and [`ParallelFutureExt`]. Dropping it cancels
+/// the spawned future, regardless of how many combinators it's nested in.
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Inspect<Fut, F> {
+    #[pin]
+    inner: Fut,
+    f: Option<F>,
+}
+
+impl<Fut, F> Inspect<Fut, F> {
+    pub(crate) fn new(inner: Fut, f: F) -> Self {
+        Self { inner, f: Some(f) }
+    }
+}
+
+impl<Fut, F> Future for Inspect<Fut, F>
+where
+    Fut: Future,
+    F: FnOnce(&Fut::Output),
+{
+    type Output = Fut::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.inner.poll(cx));
+        let f = this.f.take().expect("`Inspect` polled after completion");
+        f(&output);
+        Poll::Ready(output)
+    }
+}
+
+impl<Fut: fmt::Debug, F> fmt::Debug for Inspect<Fut, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inspect")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A parallel future which maps the error of its fallible output with a
+/// closure.
+///
+/// This type is constructed by the [`map_err`][crate::ParallelFuture::map_err]
+/// method on [`ParallelFuture`] and [`ParallelFutureExt`]. Dropping it cancels
+/// the spawned future, regardless of how many combinators it's nested in.
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct MapErr<Fut, F> {
+    #[pin]
+    inner: Fut,
+    f: Option<F>,
+}
+
+impl<Fut, F> MapErr<Fut, F> {
+    pub(crate) fn new(inner: Fut, f: F) -> Self {
+        Self { inner, f: Some(f) }
+    }
+}
+
+impl<Fut, F, T, E, E2> Future for MapErr<Fut, F>
+where
+    Fut: Future<Output = Result<T, E>>,
+    F: FnOnce(E) -> E2,
+{
+    type Output = Result<T, E2>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let output = ready!(this.inner.poll(cx));
+        let f = this.f.take().expect("`MapErr` polled after completion");
+        Poll::Ready(output.map_err(f))
+    }
+}
+
+impl<Fut: fmt::Debug, F> fmt::Debug for MapErr<Fut, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapErr")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A parallel future which awaits the future it resolves to.
+///
+/// This type is constructed by the [`flatten`][crate::ParallelFuture::flatten]
+/// method on [`ParallelFuture`].
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Flatten<Fut>
+where
+    Fut: IntoFuture,
+    Fut::Output: IntoFuture,
+{
+    #[pin]
+    state: FlattenState<Fut>,
+}
+
+#[pin_project(project = FlattenStateProj)]
+enum FlattenState<Fut>
+where
+    Fut: IntoFuture,
+    Fut::Output: IntoFuture,
+{
+    // Boxed, since `ParallelFuture` is large compared to the other states.
+    First(Box<ParallelFuture<Fut>>),
+    Second(#[pin] <Fut::Output as IntoFuture>::IntoFuture),
+    Done,
+}
+
+impl<Fut> Flatten<Fut>
+where
+    Fut: IntoFuture,
+    Fut::Output: IntoFuture,
+{
+    pub(crate) fn new(inner: ParallelFuture<Fut>) -> Self {
+        Self {
+            state: FlattenState::First(Box::new(inner)),
+        }
+    }
+}
+
+impl<Fut> Future for Flatten<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: IntoFuture + Send + 'static,
+{
+    type Output = <Fut::Output as IntoFuture>::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.project().state;
+        loop {
+            match state.as_mut().project() {
+                FlattenStateProj::First(fut) => {
+                    let inner = ready!(Pin::new(fut).poll(cx));
+                    state.set(FlattenState::Second(inner.into_future()));
+                }
+                FlattenStateProj::Second(fut) => {
+                    let output = ready!(fut.poll(cx));
+                    state.set(FlattenState::Done);
+                    return Poll::Ready(output);
+                }
+                FlattenStateProj::Done => panic!("`Flatten` polled after completion"),
+            }
+        }
+    }
+}
+
+impl<Fut> fmt::Debug for Flatten<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+    Fut::Output: IntoFuture,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match &self.state {
+            FlattenState::First(_) => "first",
+            FlattenState::Second(_) => "second",
+            FlattenState::Done => "done",
+        };
+        f.debug_struct("Flatten")
+            .field("stage", &stage)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A parallel future which passes its output to an async continuation.
+///
+/// This type is constructed by the [`then`][crate::ParallelFuture::then]
+/// method on [`ParallelFuture`] and [`ParallelFutureExt`]. Dropping it cancels
+/// the spawned future while it's running, or else the continuation.
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Then<Fut, F, Fut2: IntoFuture> {
+    #[pin]
+    state: ThenState<Fut, F, Fut2>,
+}
+
+#[pin_project(project = ThenStateProj)]
+enum ThenState<Fut, F, Fut2: IntoFuture> {
+    First(#[pin] Fut, Option<F>),
+    Second(#[pin] Fut2::IntoFuture),
+    Done,
+}
+
+impl<Fut, F, Fut2: IntoFuture> Then<Fut, F, Fut2> {
+    pub(crate) fn new(inner: Fut, f: F) -> Self {
+        Self {
+            state: ThenState::First(inner, Some(f)),
+        }
+    }
+}
+
+impl<Fut, F, Fut2> Future for Then<Fut, F, Fut2>
+where
+    Fut: Future,
+    F: FnOnce(Fut::Output) -> Fut2,
+    Fut2: IntoFuture,
+{
+    type Output = Fut2::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.project().state;
+        loop {
+            match state.as_mut().project() {
+                ThenStateProj::First(fut, f) => {
+                    let output = ready!(fut.poll(cx));
+                    let f = f.take().expect("`Then` polled after completion");
+                    state.set(ThenState::Second(f(output).into_future()));
+                }
+                ThenStateProj::Second(fut) => {
+                    let output = ready!(fut.poll(cx));
+                    state.set(ThenState::Done);
+                    return Poll::Ready(output);
+                }
+                ThenStateProj::Done => panic!("`Then` polled after completion"),
+            }
+        }
+    }
+}
+
+impl<Fut, F, Fut2: IntoFuture> fmt::Debug for Then<Fut, F, Fut2> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match &self.state {
+            ThenState::First(..) => "first",
+            ThenState::Second(_) => "second",
+            ThenState::Done => "done",
+        };
+        f.debug_struct("Then")
+            .field("stage", &stage)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A parallel future whose output can be awaited more than once.
+///
+/// This type is constructed by the [`buffered`][crate::ParallelFuture::buffered]
+/// method on [`ParallelFuture`]. Awaiting it by reference, as
+/// `(&mut fut).await`, resolves to a clone of the output every time. Until the
+/// future has completed, dropping it cancels the spawned task.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Buffered<Fut: IntoFuture> {
+    inner: ParallelFuture<Fut>,
+    output: Option<Fut::Output>,
+}
+
+impl<Fut: IntoFuture> Buffered<Fut> {
+    pub(crate) fn new(inner: ParallelFuture<Fut>) -> Self {
+        Self {
+            inner,
+            output: None,
+        }
+    }
+
+    /// Returns the output, once the future has completed.
+    ///
+    /// This doesn't poll the future, so it returns `None` until the future has
+    /// been awaited to completion at least once.
+    pub fn get(&self) -> Option<&Fut::Output> {
+        self.output.as_ref()
+    }
+}
+
+// The parallel future is always `Unpin`, and the output is never pinned.
+impl<Fut: IntoFuture> Unpin for Buffered<Fut> {}
+
+impl<Fut> Future for Buffered<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Clone + Send + 'static,
+{
+    type Output = Fut::Output;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Some(output) = &this.output {
+            return Poll::Ready(output.clone());
+        }
+        let output = ready!(Pin::new(&mut this.inner).poll(cx));
+        Poll::Ready(this.output.insert(output).clone())
+    }
+}
+
+impl<Fut> fmt::Debug for Buffered<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+    Fut::Output: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffered")
+            .field("inner", &self.inner)
+            .field("output", &self.output)
+            .finish()
+    }
+}
+
+/// A future which runs a closure on the blocking thread pool.
+///
+/// This type is constructed by the [`par_blocking`][crate::par_blocking]
+/// function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg(feature = "std")]
+pub struct Blocking<F, T> {
+    f: Option<F>,
+    handle: Option<BoxFuture<T>>,
+}
+
+#[cfg(feature = "std")]
+impl<F, T> Blocking<F, T> {
+    pub(crate) fn new(f: F) -> Self {
+        Self {
+            f: Some(f),
+            handle: None,
+        }
+    }
+}
+
+// The closure is never pinned, so `Blocking` can be moved regardless of `F`.
+#[cfg(feature = "std")]
+impl<F, T> Unpin for Blocking<F, T> {}
+
+#[cfg(feature = "std")]
+impl<F, T> Future for Blocking<F, T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    type Output = T;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let f = &mut this.f;
+        let handle = this.handle.get_or_insert_with(|| {
+            let f = f.take().expect("`Blocking` polled after completion");
+            runtime::spawn_blocking(f)
+        });
+        handle.as_mut().poll(cx)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, T> fmt::Debug for Blocking<F, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blocking").finish_non_exhaustive()
+    }
+}
+
+/// A future which calls a closure to create the future to run, on its first
+/// poll.
+///
+/// This type is constructed by the [`spawn_fn`][crate::spawn_fn] function.
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg(feature = "std")]
+pub struct SpawnFn<F, Fut: IntoFuture> {
+    f: Option<F>,
+    #[pin]
+    fut: Option<Fut::IntoFuture>,
+}
+
+#[cfg(feature = "std")]
+impl<F, Fut: IntoFuture> SpawnFn<F, Fut> {
+    pub(crate) fn new(f: F) -> Self {
+        Self {
+            f: Some(f),
+            fut: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Fut> Future for SpawnFn<F, Fut>
+where
+    F: FnOnce() -> Fut,
+    Fut: IntoFuture,
+{
+    type Output = Fut::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if let Some(f) = this.f.take() {
+            this.fut.set(Some(f().into_future()));
+        }
+        this.fut
+            .as_pin_mut()
+            .expect("`SpawnFn` polled after completion")
+            .poll(cx)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Fut: IntoFuture> fmt::Debug for SpawnFn<F, Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpawnFn").finish_non_exhaustive()
+    }
+}
+
+/// A future which calls a closure with an argument to create the future to
+/// run, on its first poll.
+///
+/// This type is constructed by the [`spawn_with`][crate::spawn_with] function.
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg(feature = "std")]
+pub struct SpawnWith<A, F, Fut: IntoFuture> {
+    f: Option<(A, F)>,
+    #[pin]
+    fut: Option<Fut::IntoFuture>,
+}
+
+#[cfg(feature = "std")]
+impl<A, F, Fut: IntoFuture> SpawnWith<A, F, Fut> {
+    pub(crate) fn new(arg: A, f: F) -> Self {
+        Self {
+            f: Some((arg, f)),
+            fut: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A, F, Fut> Future for SpawnWith<A, F, Fut>
+where
+    F: FnOnce(A) -> Fut,
+    Fut: IntoFuture,
+{
+    type Output = Fut::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if let Some((arg, f)) = this.f.take() {
+            this.fut.set(Some(f(arg).into_future()));
+        }
+        this.fut
+            .as_pin_mut()
+            .expect("`SpawnWith` polled after completion")
+            .poll(cx)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A, F, Fut: IntoFuture> fmt::Debug for SpawnWith<A, F, Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpawnWith").finish_non_exhaustive()
+    }
+}
+
+/// A parallel future which is cancelled if it doesn't complete in time.
+///
+/// This type is constructed by the [`timeout`][crate::ParallelFuture::timeout]
+/// and [`deadline`][crate::ParallelFuture::deadline] methods on
+/// [`ParallelFuture`], and the
+/// [`par_timeout`][crate::IntoFutureExt::par_timeout] and
+/// [`par_deadline`][crate::IntoFutureExt::par_deadline] methods on
+/// [`IntoFutureExt`][crate::IntoFutureExt].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg(feature = "std")]
+pub struct Timeout<Fut: IntoFuture> {
+    inner: Option<ParallelFuture<Fut>>,
+    limit: Limit,
+    sleep: Option<Sleep>,
+}
+
+/// When a [`Timeout`] expires.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+enum Limit {
+    /// A duration after the future is first polled.
+    After(Duration),
+    /// A fixed point in time.
+    At(Instant),
+}
+
+#[cfg(feature = "std")]
+impl<Fut: IntoFuture> Timeout<Fut> {
+    pub(crate) fn new(inner: ParallelFuture<Fut>, dur: Duration) -> Self {
+        Self {
+            inner: Some(inner),
+            limit: Limit::After(dur),
+            sleep: None,
+        }
+    }
+
+    pub(crate) fn at(inner: ParallelFuture<Fut>, deadline: Instant) -> Self {
+        Self {
+            inner: Some(inner),
+            limit: Limit::At(deadline),
+            sleep: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Fut> Future for Timeout<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = Result<Fut::Output, TimeoutError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let inner = this
+            .inner
+            .as_mut()
+            .expect("`Timeout` polled after completion");
+
+        // Poll the inner future first, so its output wins if it completes in
+        // the same poll as the timer expires.
+        if let Poll::Ready(output) = Pin::new(inner).poll(cx) {
+            this.inner = None;
+            return Poll::Ready(Ok(output));
+        }
+
+        // Relative timers only start once the future is spawned.
+        let limit = this.limit;
+        let sleep = this.sleep.get_or_insert_with(|| match limit {
+            Limit::After(dur) => runtime::sleep(dur),
+            Limit::At(deadline) => runtime::sleep_until(deadline),
+        });
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                // Dropping the inner future cancels the spawned task.
+                this.inner = None;
+                Poll::Ready(Err(TimeoutError { _priv: () }))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Fut> fmt::Debug for Timeout<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timeout")
+            .field("inner", &self.inner)
+            .field("limit", &self.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A parallel future which is handed back if it doesn't complete in time.
+///
+/// This type is constructed by the
+/// [`try_timeout`][crate::ParallelFuture::try_timeout] method on
+/// [`ParallelFuture`], and the
+/// [`try_par_timeout`][crate::IntoFutureExt::try_par_timeout] method on
+/// [`IntoFutureExt`][crate::IntoFutureExt].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg(feature = "std")]
+pub struct TryTimeout<Fut: IntoFuture> {
+    inner: Option<ParallelFuture<Fut>>,
+    dur: Duration,
+    sleep: Option<Sleep>,
+}
+
+#[cfg(feature = "std")]
+impl<Fut: IntoFuture> TryTimeout<Fut> {
+    pub(crate) fn new(inner: ParallelFuture<Fut>, dur: Duration) -> Self {
+        Self {
+            inner: Some(inner),
+            dur,
+            sleep: None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Fut> Future for TryTimeout<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = Result<Fut::Output, ParallelFuture<Fut>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let inner = this
+            .inner
+            .as_mut()
+            .expect("`TryTimeout` polled after completion");
+
+        // Poll the inner future first, so its output wins if it completes in
+        // the same poll as the timer expires.
+        if let Poll::Ready(output) = Pin::new(inner).poll(cx) {
+            this.inner = None;
+            return Poll::Ready(Ok(output));
+        }
+
+        // The timer only starts once the future is spawned.
+        let dur = this.dur;
+        let sleep = this.sleep.get_or_insert_with(|| runtime::sleep(dur));
+        match sleep.as_mut().poll(cx) {
+            // The inner future has been spawned by now, so it keeps running
+            // while the caller decides what to do with it.
+            Poll::Ready(()) => Poll::Ready(Err(this.inner.take().unwrap())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Fut> fmt::Debug for TryTimeout<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TryTimeout")
+            .field("inner", &self.inner)
+            .field("dur", &self.dur)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The error returned when a parallel future times out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg(feature = "std")]
+pub struct TimeoutError {
+    _priv: (),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        "future timed out".fmt(f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for TimeoutError {}
+
+/// A future which runs two parallel futures, and pairs their outputs.
+///
+/// This type is constructed by the [`par_zip`][crate::IntoFutureExt::par_zip]
+/// method on [`IntoFutureExt`][crate::IntoFutureExt]. Dropping it cancels both
+/// spawned futures.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[cfg(feature = "std")]
+pub struct Zip<A: IntoFuture, B: IntoFuture> {
+    a: ParallelFuture<A>,
+    b: ParallelFuture<B>,
+    a_output: Option<A::Output>,
+    b_output: Option<B::Output>,
+    done: bool,
+}
+
+// The futures and outputs are never pinned, so `Zip` can be moved regardless
+// of their types.
+#[cfg(feature = "std")]
+impl<A: IntoFuture, B: IntoFuture> Unpin for Zip<A, B> {}
+
+#[cfg(feature = "std")]
+impl<A: IntoFuture, B: IntoFuture> Zip<A, B> {
+    pub(crate) fn new(a: ParallelFuture<A>, b: ParallelFuture<B>) -> Self {
+        Self {
+            a,
+            b,
+            a_output: None,
+            b_output: None,
+            done: false,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A, B> Future for Zip<A, B>
+where
+    A: IntoFuture,
+    A::IntoFuture: Send + 'static,
+    A::Output: Send + 'static,
+    B: IntoFuture,
+    B::IntoFuture: Send + 'static,
+    B::Output: Send + 'static,
+{
+    type Output = (A::Output, B::Output);
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        assert!(!this.done, "`Zip` polled after completion");
+
+        // Poll both futures, so both are spawned on the first poll.
+        if this.a_output.is_none() {
+            if let Poll::Ready(output) = Pin::new(&mut this.a).poll(cx) {
+                this.a_output = Some(output);
+            }
+        }
+        if this.b_output.is_none() {
+            if let Poll::Ready(output) = Pin::new(&mut this.b).poll(cx) {
+                this.b_output = Some(output);
+            }
+        }
+        match (this.a_output.take(), this.b_output.take()) {
+            (Some(a), Some(b)) => {
+                this.done = true;
+                Poll::Ready((a, b))
+            }
+            (a, b) => {
+                this.a_output = a;
+                this.b_output = b;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A, B> fmt::Debug for Zip<A, B>
+where
+    A: IntoFuture + fmt::Debug,
+    B: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Zip")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .finish_non_exhaustive()
+    }
+}
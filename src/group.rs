@@ -0,0 +1,618 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::future::{poll_fn, IntoFuture};
+use std::panic;
+use std::task::{Context, Poll};
+
+use crate::executor::{self, Config, JoinHandle, TaskResult};
+
+/// A dynamically sized group of parallel futures.
+///
+/// Futures are spawned as soon as they're added to the group, and their
+/// outputs can be retrieved in the order they complete using
+/// [`join_next`][ParallelGroup::join_next]. All futures still in the group
+/// are cancelled when the group is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::ParallelGroup;
+///
+/// parallel_future::par_block_on(async {
+///     let mut group = ParallelGroup::new();
+///     for n in 0..4 {
+///         group.spawn(async move { n * 2 });
+///     }
+///
+///     let mut sum = 0;
+///     while let Some(n) = group.join_next().await {
+///         sum += n;
+///     }
+///     assert_eq!(sum, 12);
+/// })
+/// ```
+pub struct ParallelGroup<T> {
+    handles: Vec<JoinHandle<T>>,
+}
+
+impl<T> ParallelGroup<T> {
+    /// Create a new, empty group.
+    pub fn new() -> Self {
+        Self {
+            handles: Vec::new(),
+        }
+    }
+
+    /// Returns the number of futures in the group which haven't been joined
+    /// yet.
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    /// Returns `true` if there are no futures in the group.
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+}
+
+impl<T: Send + 'static> ParallelGroup<T> {
+    /// Spawn a future and add it to the group.
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn spawn<Fut>(&mut self, fut: Fut)
+    where
+        Fut: IntoFuture<Output = T>,
+        Fut::IntoFuture: Send + 'static,
+    {
+        self.handles
+            .push(executor::spawn(None, Config::default(), fut.into_future()));
+    }
+
+    /// Wait for the next future in the group to complete, returning its
+    /// output.
+    ///
+    /// Returns `None` once the group is empty.
+    ///
+    /// # Panics
+    ///
+    /// If the future panicked, the panic is resumed in the calling task.
+    pub async fn join_next(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    /// Wait for all futures in the group to complete, returning their outputs
+    /// in the order they completed.
+    ///
+    /// Dropping the returned future before it completes cancels the futures
+    /// which are still running.
+    ///
+    /// # Panics
+    ///
+    /// If any of the futures panicked, the panic is resumed in the calling
+    /// task.
+    pub async fn collect(mut self) -> Vec<T> {
+        let mut outputs = Vec::with_capacity(self.len());
+        while let Some(output) = self.join_next().await {
+            outputs.push(output);
+        }
+        outputs
+    }
+
+    /// Wait for all futures in the group to complete, discarding their
+    /// outputs, and return how many there were.
+    ///
+    /// Dropping the returned future before it completes cancels the futures
+    /// which are still running.
+    ///
+    /// # Panics
+    ///
+    /// If any of the futures panicked, the panic is resumed in the calling
+    /// task.
+    pub async fn count(mut self) -> usize {
+        let mut count = 0;
+        while self.join_next().await.is_some() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Poll for the output of the next future in the group to complete.
+    pub(crate) fn poll_join_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if self.handles.is_empty() {
+            return Poll::Ready(None);
+        }
+        for i in 0..self.handles.len() {
+            if let Poll::Ready(output) = self.handles[i].poll_output(cx) {
+                self.handles.swap_remove(i);
+                return match output {
+                    Ok(output) => Poll::Ready(Some(output)),
+                    Err(payload) => panic::resume_unwind(payload),
+                };
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Spawn every future in an iterator, and add them to the group.
+///
+/// Like with [`spawn`][ParallelGroup::spawn], each future is spawned as soon as
+/// it's added, rather than on the next call to
+/// [`join_next`][ParallelGroup::join_next].
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::ParallelGroup;
+///
+/// parallel_future::par_block_on(async {
+///     let mut group = ParallelGroup::new();
+///     group.extend((0..4).map(|n| async move { n * 2 }));
+///     assert_eq!(group.len(), 4);
+///     assert_eq!(group.count().await, 4);
+/// })
+/// ```
+impl<T, Fut> Extend<Fut> for ParallelGroup<T>
+where
+    T: Send + 'static,
+    Fut: IntoFuture<Output = T>,
+    Fut::IntoFuture: Send + 'static,
+{
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn extend<I: IntoIterator<Item = Fut>>(&mut self, futs: I) {
+        for fut in futs {
+            self.spawn(fut);
+        }
+    }
+}
+
+impl<T> Default for ParallelGroup<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for ParallelGroup<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelGroup")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Cancel all futures in the group when dropped.
+impl<T> Drop for ParallelGroup<T> {
+    fn drop(&mut self) {
+        for handle in self.handles.drain(..) {
+            handle.cancel();
+        }
+    }
+}
+
+/// A dynamically sized group of fallible parallel futures.
+///
+/// This works like [`ParallelGroup`], except that as soon as one of the futures
+/// returns an error, all other futures in the group are cancelled.
+///
+/// Joining a fixed set of parallel futures with `futures_concurrency`'s
+/// `try_join` has the same effect: the remaining futures are dropped on the
+/// first error, which cancels their spawned tasks.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::ParallelTryGroup;
+///
+/// parallel_future::par_block_on(async {
+///     let mut group = ParallelTryGroup::new();
+///     group.spawn(async { Ok(1) });
+///     group.spawn(async { Err("oh no") });
+///     group.spawn(async { Ok(3) });
+///
+///     assert_eq!(group.try_join().await, Err("oh no"));
+/// })
+/// ```
+pub struct ParallelTryGroup<T, E> {
+    group: ParallelGroup<Result<T, E>>,
+}
+
+impl<T, E> ParallelTryGroup<T, E> {
+    /// Create a new, empty group.
+    pub fn new() -> Self {
+        Self {
+            group: ParallelGroup::new(),
+        }
+    }
+
+    /// Returns the number of futures in the group which haven't been joined
+    /// yet.
+    pub fn len(&self) -> usize {
+        self.group.len()
+    }
+
+    /// Returns `true` if there are no futures in the group.
+    pub fn is_empty(&self) -> bool {
+        self.group.is_empty()
+    }
+
+    /// Cancel all futures in the group.
+    fn cancel(&mut self) {
+        for handle in self.group.handles.drain(..) {
+            handle.cancel();
+        }
+    }
+}
+
+impl<T: Send + 'static, E: Send + 'static> ParallelTryGroup<T, E> {
+    /// Spawn a future and add it to the group.
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn spawn<Fut>(&mut self, fut: Fut)
+    where
+        Fut: IntoFuture<Output = Result<T, E>>,
+        Fut::IntoFuture: Send + 'static,
+    {
+        self.group.spawn(fut);
+    }
+
+    /// Wait for the next future in the group to complete, returning its
+    /// output.
+    ///
+    /// If the future returned an error, all other futures in the group are
+    /// cancelled before the error is returned. Returns `None` once the group is
+    /// empty.
+    ///
+    /// # Panics
+    ///
+    /// If the future panicked, the panic is resumed in the calling task.
+    pub async fn try_join_next(&mut self) -> Option<Result<T, E>> {
+        let output = self.group.join_next().await;
+        if let Some(Err(_)) = output {
+            self.cancel();
+        }
+        output
+    }
+
+    /// Wait for all futures in the group to complete, returning their outputs
+    /// in the order they completed.
+    ///
+    /// On the first error all remaining futures are cancelled, and the error is
+    /// returned.
+    ///
+    /// # Panics
+    ///
+    /// If any of the futures panicked, the panic is resumed in the calling
+    /// task.
+    pub async fn try_join(mut self) -> Result<Vec<T>, E> {
+        let mut outputs = Vec::with_capacity(self.len());
+        while let Some(output) = self.try_join_next().await {
+            outputs.push(output?);
+        }
+        Ok(outputs)
+    }
+}
+
+impl<T, E> Default for ParallelTryGroup<T, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, E> fmt::Debug for ParallelTryGroup<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelTryGroup")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A dynamically sized group of parallel futures, whose outputs are joined in
+/// order of priority.
+///
+/// This works like [`ParallelGroup`], except that every future is spawned with
+/// a priority. Whenever several futures have completed by the time
+/// [`join_next`][ParallelPriorityGroup::join_next] is called, the output of the
+/// one with the highest priority is returned first, and outputs with equal
+/// priorities are returned in the order they were seen to complete. Futures
+/// which are still running don't hold up outputs which are already available,
+/// whatever their priority. All futures still in the group are cancelled when
+/// the group is dropped.
+///
+/// The priority only affects the order outputs are joined in; the futures are
+/// all spawned the same way.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::ParallelPriorityGroup;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let mut group = ParallelPriorityGroup::new();
+///     group.spawn(1, async { "low" });
+///     group.spawn(3, async { "high" });
+///     group.spawn(2, async { "medium" });
+///     async_std::task::sleep(Duration::from_millis(50)).await; // ← all done
+///
+///     assert_eq!(group.join_next().await, Some("high"));
+///     assert_eq!(group.join_next().await, Some("medium"));
+///     assert_eq!(group.join_next().await, Some("low"));
+/// })
+/// ```
+pub struct ParallelPriorityGroup<P, T> {
+    handles: Vec<(P, JoinHandle<T>)>,
+    completed: BinaryHeap<Completed<P, T>>,
+    /// The number of outputs seen so far, to order equal priorities by.
+    seen: u64,
+}
+
+impl<P: Ord, T> ParallelPriorityGroup<P, T> {
+    /// Create a new, empty group.
+    pub fn new() -> Self {
+        Self {
+            handles: Vec::new(),
+            completed: BinaryHeap::new(),
+            seen: 0,
+        }
+    }
+
+    /// Returns the number of futures in the group which haven't been joined
+    /// yet.
+    pub fn len(&self) -> usize {
+        self.handles.len() + self.completed.len()
+    }
+
+    /// Returns `true` if there are no futures in the group.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<P: Ord, T: Send + 'static> ParallelPriorityGroup<P, T> {
+    /// Spawn a future with the given priority, and add it to the group.
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn spawn<Fut>(&mut self, priority: P, fut: Fut)
+    where
+        Fut: IntoFuture<Output = T>,
+        Fut::IntoFuture: Send + 'static,
+    {
+        let handle = executor::spawn(None, Config::default(), fut.into_future());
+        self.handles.push((priority, handle));
+    }
+
+    /// Wait for the next future in the group to complete, returning the
+    /// output with the highest priority among those which have completed.
+    ///
+    /// Returns `None` once the group is empty.
+    ///
+    /// # Panics
+    ///
+    /// If the future panicked, the panic is resumed in the calling task.
+    pub async fn join_next(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    fn poll_join_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        // Collect every output which is available right now, so the one with
+        // the highest priority can be picked from all of them.
+        let mut i = 0;
+        while i < self.handles.len() {
+            match self.handles[i].1.poll_output(cx) {
+                Poll::Ready(output) => {
+                    let (priority, _) = self.handles.swap_remove(i);
+                    self.completed.push(Completed {
+                        priority,
+                        seen: Reverse(self.seen),
+                        output,
+                    });
+                    self.seen += 1;
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+        match self.completed.pop() {
+            Some(Completed {
+                output: Ok(output), ..
+            }) => Poll::Ready(Some(output)),
+            Some(Completed {
+                output: Err(payload),
+                ..
+            }) => panic::resume_unwind(payload),
+            None if self.handles.is_empty() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<P: Ord, T> Default for ParallelPriorityGroup<P, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Ord, T> fmt::Debug for ParallelPriorityGroup<P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelPriorityGroup")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Cancel all futures in the group when dropped.
+impl<P, T> Drop for ParallelPriorityGroup<P, T> {
+    fn drop(&mut self) {
+        for (_, handle) in self.handles.drain(..) {
+            handle.cancel();
+        }
+    }
+}
+
+/// The output of a future in a [`ParallelPriorityGroup`] which hasn't been
+/// joined yet, ordered by priority first, and then by when it was seen.
+struct Completed<P, T> {
+    priority: P,
+    seen: Reverse<u64>,
+    output: TaskResult<T>,
+}
+
+impl<P: Ord, T> Ord for Completed<P, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.priority, self.seen).cmp(&(&other.priority, other.seen))
+    }
+}
+
+impl<P: Ord, T> PartialOrd for Completed<P, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<P: Ord, T> PartialEq for Completed<P, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<P: Ord, T> Eq for Completed<P, T> {}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn join_next() {
+        runtime::block_on(async {
+            let mut group = ParallelGroup::new();
+            group.spawn(async { 1 });
+            group.spawn(async { 2 });
+            assert_eq!(group.len(), 2);
+
+            let mut outputs = vec![];
+            while let Some(output) = group.join_next().await {
+                outputs.push(output);
+            }
+            outputs.sort();
+            assert_eq!(outputs, vec![1, 2]);
+            assert!(group.is_empty());
+        })
+    }
+
+    #[test]
+    fn collect() {
+        runtime::block_on(async {
+            let mut group = ParallelGroup::new();
+            for n in 0..4 {
+                group.spawn(async move { n * 2 });
+            }
+            let mut outputs = group.collect().await;
+            outputs.sort();
+            assert_eq!(outputs, vec![0, 2, 4, 6]);
+
+            let mut group = ParallelGroup::new();
+            group.spawn(async {});
+            group.extend((0..2).map(|_| async {}));
+            assert_eq!(group.len(), 3);
+            assert_eq!(group.count().await, 3);
+        })
+    }
+
+    #[test]
+    fn collect_cancels_on_drop() {
+        runtime::block_on(async {
+            let counter = Arc::new(AtomicUsize::new(0));
+            let counter_2 = counter.clone();
+            let (done, is_done) = drop_signal();
+            let (guard, dropped) = drop_signal();
+            let mut group = ParallelGroup::new();
+            group.spawn(async move {
+                let _done = done;
+                counter_2.fetch_add(1, Ordering::SeqCst);
+            });
+            group.spawn(async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            });
+            let collect = async { Some(group.collect().await) };
+            let timeout = async {
+                is_done.await;
+                None
+            };
+            assert!(collect.race(timeout).await.is_none());
+
+            dropped.await;
+            assert_eq!(counter.load(Ordering::SeqCst), 1);
+        })
+    }
+
+    #[test]
+    fn cancel_on_drop() {
+        runtime::block_on(async {
+            let mut group = ParallelGroup::new();
+            let mut dropped = vec![];
+            for _ in 0..4 {
+                let (guard, is_dropped) = drop_signal();
+                dropped.push(is_dropped);
+                group.spawn(async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                });
+            }
+            drop(group);
+            dropped.join().await;
+        })
+    }
+
+    #[test]
+    fn join_by_priority() {
+        runtime::block_on(async {
+            let mut group = ParallelPriorityGroup::new();
+            group.spawn(1, async { "nori" });
+            group.spawn(2, async { "chashu" });
+            group.spawn(1, async { "tamago" });
+            group.spawn(0, async {
+                runtime::sleep(Duration::from_millis(200)).await;
+                "menma"
+            });
+            runtime::sleep(Duration::from_millis(50)).await;
+            assert_eq!(group.len(), 4);
+
+            // Completed outputs don't wait for the slower, more urgent future.
+            assert_eq!(group.join_next().await, Some("chashu"));
+            let next = (group.join_next().await, group.join_next().await);
+            assert!(matches!(
+                next,
+                (Some("nori"), Some("tamago")) | (Some("tamago"), Some("nori"))
+            ));
+            assert_eq!(group.join_next().await, Some("menma"));
+            assert_eq!(group.join_next().await, None);
+        })
+    }
+
+    #[test]
+    fn try_join_cancels_on_error() {
+        runtime::block_on(async {
+            let mut group = ParallelTryGroup::new();
+            let mut dropped = vec![];
+            for _ in 0..4 {
+                let (guard, is_dropped) = drop_signal();
+                dropped.push(is_dropped);
+                group.spawn(async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                    Ok(())
+                });
+            }
+            group.spawn(async { Err("oh no") });
+            assert_eq!(group.try_join().await, Err("oh no"));
+            dropped.join().await;
+        })
+    }
+}
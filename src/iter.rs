@@ -0,0 +1,1248 @@
+//! Parallel operations over iterators.
+
+use std::collections::VecDeque;
+use std::convert::identity;
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use pin_project::pin_project;
+
+use crate::panic::CatchPanic;
+use crate::{IntoFutureExt, PanicError, ParallelFuture};
+
+/// Extend the `IntoIterator` trait with parallel operations.
+pub trait ParallelIteratorExt: IntoIterator + Sized {
+    /// Map each item to a future, and run the futures in parallel.
+    ///
+    /// The returned future resolves to a `Vec` of outputs, in the same order
+    /// as the items they were created from. By default all futures are
+    /// spawned at once; use [`ParMap::limit`] to bound how many run at the same
+    /// time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let items = vec![1, 2, 3];
+    ///     let outputs = items.par_map(|n| async move { n * 2 }).await;
+    ///     assert_eq!(outputs, vec![2, 4, 6]);
+    /// })
+    /// ```
+    fn par_map<F, Fut>(self, f: F) -> ParMap<Self::IntoIter, F, Fut>
+    where
+        F: FnMut(Self::Item) -> Fut,
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        ParMap {
+            iter: self.into_iter(),
+            f,
+            limit: None,
+            in_flight: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Map each item to a future, and run up to `concurrency` of the futures
+    /// in parallel, yielding their outputs as a stream in the order they
+    /// complete.
+    ///
+    /// Completed outputs are held in a buffer of `buffer` outputs until the
+    /// stream is polled for them. Once the buffer is full no new futures are
+    /// spawned, so a slow consumer pauses the producer rather than letting
+    /// outputs pile up in memory. At most `concurrency` futures run at the same
+    /// time. Futures which are already running when the buffer fills up still
+    /// complete, so up to `concurrency + buffer` outputs can wait to be
+    /// consumed. Dropping the returned stream cancels all futures which are
+    /// still running.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `concurrency` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use async_std::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let mut outputs: Vec<_> = (1..=3)
+    ///         .par_map_bounded(|n| async move { n * 2 }, 2, 1)
+    ///         .collect()
+    ///         .await;
+    ///     outputs.sort();
+    ///     assert_eq!(outputs, vec![2, 4, 6]);
+    /// })
+    /// ```
+    fn par_map_bounded<F, Fut>(
+        self,
+        f: F,
+        concurrency: usize,
+        buffer: usize,
+    ) -> ParMapBounded<Self::IntoIter, F, Fut>
+    where
+        F: FnMut(Self::Item) -> Fut,
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        assert!(
+            concurrency > 0,
+            "the concurrency limit must be greater than zero"
+        );
+        ParMapBounded {
+            iter: self.into_iter(),
+            f,
+            concurrency,
+            buffer,
+            done: false,
+            in_flight: Vec::new(),
+            outputs: VecDeque::new(),
+        }
+    }
+
+    /// Split the items into chunks of `chunk_size`, and map each chunk to a
+    /// future which runs in parallel with the others.
+    ///
+    /// Spawning a task per item is wasteful when the work per item is small.
+    /// This spawns a task per chunk instead, which amortizes the cost of
+    /// spawning over `chunk_size` items. The closure receives the items of a
+    /// chunk, and decides itself whether to process them sequentially or
+    /// concurrently within its task. The last chunk may be shorter than
+    /// `chunk_size`.
+    ///
+    /// The returned future resolves to a `Vec` of the outputs of all chunks,
+    /// flattened in the same order as the items they were created from. Use
+    /// [`ParChunksMap::limit`] to bound how many chunks run at the same time.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `chunk_size` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let outputs = (0..1000)
+    ///         .par_chunks_map(100, |chunk| async move {
+    ///             chunk.into_iter().map(|n| n * 2).collect::<Vec<_>>()
+    ///         })
+    ///         .await; // ← spawns 10 tasks rather than 1000
+    ///     assert_eq!(outputs, (0..1000).map(|n| n * 2).collect::<Vec<_>>());
+    /// })
+    /// ```
+    fn par_chunks_map<F, Fut>(self, chunk_size: usize, f: F) -> ParChunksMap<Self::IntoIter, F, Fut>
+    where
+        F: FnMut(Vec<Self::Item>) -> Fut,
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: IntoIterator + Send + 'static,
+    {
+        assert!(chunk_size > 0, "the chunk size must be greater than zero");
+        let chunks = Chunks {
+            iter: self.into_iter(),
+            size: chunk_size,
+        };
+        ParChunksMap {
+            inner: chunks.par_map(f),
+        }
+    }
+
+    /// Run a future for each item in parallel, waiting for all of them to
+    /// complete.
+    ///
+    /// This is like [`par_map`][ParallelIteratorExt::par_map] for futures which
+    /// are only run for their side effects. Use [`ParForEach::limit`] to bound
+    /// how many run at the same time. Dropping the returned future cancels all
+    /// futures which are still running.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let sum = Arc::new(AtomicUsize::new(0));
+    ///     vec![1, 2, 3]
+    ///         .par_for_each(|n| {
+    ///             let sum = sum.clone();
+    ///             async move { sum.fetch_add(n, Ordering::SeqCst); }
+    ///         })
+    ///         .await;
+    ///     assert_eq!(sum.load(Ordering::SeqCst), 6);
+    /// })
+    /// ```
+    fn par_for_each<F, Fut>(self, f: F) -> ParForEach<Self::IntoIter, F, Fut>
+    where
+        F: FnMut(Self::Item) -> Fut,
+        Fut: IntoFuture<Output = ()>,
+        Fut::IntoFuture: Send + 'static,
+    {
+        ParForEach {
+            inner: self.par_map(f),
+        }
+    }
+
+    /// Run each future in parallel, and collect their outputs into a
+    /// collection.
+    ///
+    /// Outputs are passed to [`FromIterator`] in the same order as the futures
+    /// they were created from, regardless of the order in which they complete.
+    /// So collecting into a `HashMap` keeps the last of any duplicate keys in
+    /// the source. Use [`ParCollect::limit`] to bound how many run at the same
+    /// time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::collections::HashMap;
+    ///
+    /// let futs = vec!["nori", "chashu"]
+    ///     .into_iter()
+    ///     .map(|name| async move { (name, name.len()) });
+    /// let lengths: HashMap<_, _> = parallel_future::par_block_on(futs.par_collect());
+    /// assert_eq!(lengths["chashu"], 6);
+    /// ```
+    fn par_collect<C>(self) -> ParCollect<Self::IntoIter, C>
+    where
+        Self::Item: IntoFuture,
+        <Self::Item as IntoFuture>::IntoFuture: Send + 'static,
+        <Self::Item as IntoFuture>::Output: Send + 'static,
+        C: FromIterator<<Self::Item as IntoFuture>::Output>,
+    {
+        ParCollect {
+            inner: self.par_map(identity as fn(_) -> _),
+            _collection: PhantomData,
+        }
+    }
+
+    /// Spawn each future right away, and return a stream of their outputs in
+    /// the order they complete.
+    ///
+    /// This works like collecting parallel futures into a `FuturesUnordered`
+    /// from the `futures` crate, except that the futures start running
+    /// immediately rather than on the first poll of the stream. Dropping the
+    /// stream cancels all futures which haven't completed yet.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use async_std::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let futs = vec![1, 2, 3].into_iter().map(|n| async move { n * 2 });
+    ///     let mut outputs: Vec<_> = futs.par_unordered().collect().await;
+    ///     outputs.sort();
+    ///     assert_eq!(outputs, vec![2, 4, 6]);
+    /// })
+    /// ```
+    fn par_unordered(self) -> ParUnordered<Self::Item>
+    where
+        Self::Item: IntoFuture,
+        <Self::Item as IntoFuture>::IntoFuture: Send + 'static,
+        <Self::Item as IntoFuture>::Output: Send + 'static,
+    {
+        let in_flight = self
+            .into_iter()
+            .map(|fut| {
+                let mut fut = fut.par().cancel_on_drop();
+                fut.start();
+                fut
+            })
+            .collect();
+        ParUnordered { in_flight }
+    }
+}
+
+impl<I: IntoIterator> ParallelIteratorExt for I {}
+
+/// Spawn each future in parallel, and wait for all of them to complete.
+///
+/// Joining a `Vec` of plain futures with `futures_concurrency`'s `join` polls
+/// them concurrently, but on a single thread: only one of them makes progress
+/// at any moment. `par_join_all` instead spawns every future as a separate
+/// task on the runtime, so they run in parallel across all cores.
+///
+/// The returned future resolves to a `Vec` of outputs, in the same order as the
+/// futures they were created from. Dropping it cancels all futures which are
+/// still running. This is a shorthand for
+/// [`par_collect`][ParallelIteratorExt::par_collect] into a `Vec`, so
+/// [`ParCollect::limit`] can bound how many run at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_join_all;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let futs = [30, 10, 20].map(|ms| async move {
+///         async_std::task::sleep(Duration::from_millis(ms)).await;
+///         ms
+///     });
+///     let outputs = par_join_all(futs).await;
+///     assert_eq!(outputs, vec![30, 10, 20]);
+/// })
+/// ```
+pub fn par_join_all<I>(futs: I) -> ParCollect<I::IntoIter, Vec<<I::Item as IntoFuture>::Output>>
+where
+    I: IntoIterator,
+    I::Item: IntoFuture,
+    <I::Item as IntoFuture>::IntoFuture: Send + 'static,
+    <I::Item as IntoFuture>::Output: Send + 'static,
+{
+    futs.par_collect()
+}
+
+/// Spawn each parallel future right away, and return them without waiting for
+/// any of them to complete.
+///
+/// Parallel futures are lazy, so a batch of them normally starts one by one as
+/// they're first polled. This spawns all of them up front instead, making them
+/// [eager][crate::IntoFutureExt::par_eager], so their startup overlaps with
+/// each other and with whatever the caller does next. The returned futures are
+/// in the same order as the given ones. Awaiting them retrieves their outputs,
+/// and dropping them cancels them, like any other parallel future.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use parallel_future::start_all;
+///
+/// parallel_future::par_block_on(async {
+///     let futs = start_all((1..=3).map(|n| async move { n * 2 }.par()));
+///     // ← all three are running in the background here
+///     let mut sum = 0;
+///     for fut in futs {
+///         sum += fut.await;
+///     }
+///     assert_eq!(sum, 12);
+/// })
+/// ```
+pub fn start_all<I, Fut>(futs: I) -> Vec<ParallelFuture<Fut>>
+where
+    I: IntoIterator<Item = ParallelFuture<Fut>>,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    futs.into_iter()
+        .map(|mut fut| {
+            fut.start();
+            fut
+        })
+        .collect()
+}
+
+/// Spawn each future in parallel, and wait for all of them to complete or
+/// panic.
+///
+/// Unlike [`par_join_all`], a panic in one of the futures doesn't discard the
+/// outputs of the others: the returned future resolves to a `Vec` of results,
+/// in the same order as the futures they were created from, with a
+/// [`PanicError`] in place of each future which panicked. Use
+/// [`ParJoinAllResults::limit`] to bound how many run at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_join_all_results;
+///
+/// parallel_future::par_block_on(async {
+///     let futs = [1, 2, 3].map(|n| async move {
+///         if n == 2 {
+///             panic!("oh no");
+///         }
+///         n
+///     });
+///     let results = par_join_all_results(futs).await;
+///     assert_eq!(*results[0].as_ref().unwrap(), 1);
+///     assert_eq!(results[1].as_ref().unwrap_err().message(), Some("oh no"));
+///     assert_eq!(*results[2].as_ref().unwrap(), 3);
+/// })
+/// ```
+pub fn par_join_all_results<I>(futs: I) -> ParJoinAllResults<I::IntoIter>
+where
+    I: IntoIterator,
+    I::Item: IntoFuture,
+    <I::Item as IntoFuture>::IntoFuture: Send + 'static,
+    <I::Item as IntoFuture>::Output: Send + 'static,
+{
+    let catch_panic: CatchPanicFn<I::Item> = |fut| CatchPanic::new(fut.into_future());
+    ParJoinAllResults {
+        inner: futs.par_map(catch_panic),
+    }
+}
+
+/// Spawn each future in parallel, and fold their outputs into a single value
+/// as they complete.
+///
+/// Starting from `identity`, each output is combined into the accumulated
+/// value with `combine` as soon as its future completes, so outputs don't
+/// wait on slower futures which were created before them. That also means
+/// outputs are folded in completion order rather than the order of the
+/// futures, which differs from run to run. For the result to be
+/// deterministic, `combine` must be associative and commutative, like summing
+/// numbers or merging sets, and `identity` must not change the result, like
+/// `0` for a sum.
+///
+/// All futures are spawned when the returned future is first polled.
+/// Dropping it cancels the futures which are still running.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_reduce;
+///
+/// parallel_future::par_block_on(async {
+///     let futs = (1..=4).map(|n| async move { n * 2 });
+///     let sum = par_reduce(futs, 0, |sum, n| sum + n).await;
+///     assert_eq!(sum, 20);
+/// })
+/// ```
+pub fn par_reduce<I, T, F>(futs: I, identity: T, combine: F) -> ParReduce<I::Item, T, F>
+where
+    I: IntoIterator,
+    I::Item: IntoFuture,
+    <I::Item as IntoFuture>::IntoFuture: Send + 'static,
+    <I::Item as IntoFuture>::Output: Send + 'static,
+    F: FnMut(T, <I::Item as IntoFuture>::Output) -> T,
+{
+    ParReduce {
+        in_flight: futs
+            .into_iter()
+            .map(|fut| fut.par().cancel_on_drop())
+            .collect(),
+        acc: Some(identity),
+        combine,
+    }
+}
+
+/// A future which maps items to parallel futures and collects their outputs.
+///
+/// This type is constructed by the
+/// [`par_map`][ParallelIteratorExt::par_map] method on
+/// [`ParallelIteratorExt`].
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ParMap<I, F, Fut: IntoFuture> {
+    iter: I,
+    f: F,
+    limit: Option<usize>,
+    in_flight: Vec<(usize, ParallelFuture<Fut>)>,
+    outputs: Vec<Option<Fut::Output>>,
+}
+
+impl<I, F, Fut: IntoFuture> ParMap<I, F, Fut> {
+    /// Limit the number of futures running at the same time.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    pub fn limit(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "the concurrency limit must be greater than zero");
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl<I, F, Fut> Future for ParMap<I, F, Fut>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = Vec<Fut::Output>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        loop {
+            // Spawn as many futures as the limit allows.
+            while this.limit.is_none_or(|limit| this.in_flight.len() < limit) {
+                match this.iter.next() {
+                    Some(item) => {
+                        let index = this.outputs.len();
+                        this.outputs.push(None);
+                        this.in_flight
+                            .push((index, (this.f)(item).par().cancel_on_drop()));
+                    }
+                    None => break,
+                }
+            }
+
+            if this.in_flight.is_empty() {
+                let outputs = this.outputs.drain(..);
+                return Poll::Ready(outputs.map(Option::unwrap).collect());
+            }
+
+            let in_flight = this.in_flight.len();
+            let outputs = &mut *this.outputs;
+            this.in_flight
+                .retain_mut(|(index, fut)| match Pin::new(fut).poll(cx) {
+                    Poll::Ready(output) => {
+                        outputs[*index] = Some(output);
+                        false
+                    }
+                    Poll::Pending => true,
+                });
+
+            // Completed futures may have freed up room for new ones.
+            if this.in_flight.len() == in_flight {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<I, F, Fut> fmt::Debug for ParMap<I, F, Fut>
+where
+    I: fmt::Debug,
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParMap")
+            .field("iter", &self.iter)
+            .field("limit", &self.limit)
+            .field("in_flight", &self.in_flight)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A stream which maps items to parallel futures, with a bounded buffer for
+/// their outputs.
+///
+/// This type is constructed by the
+/// [`par_map_bounded`][ParallelIteratorExt::par_map_bounded] method on
+/// [`ParallelIteratorExt`].
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+pub struct ParMapBounded<I, F, Fut: IntoFuture> {
+    iter: I,
+    f: F,
+    concurrency: usize,
+    buffer: usize,
+    /// Whether the iterator has been exhausted.
+    done: bool,
+    in_flight: Vec<ParallelFuture<Fut>>,
+    outputs: VecDeque<Fut::Output>,
+}
+
+impl<I, F, Fut> Stream for ParMapBounded<I, F, Fut>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Item = Fut::Output;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        loop {
+            // Spawn as many futures as the limit allows, as long as there's
+            // room in the buffer. The output we're about to yield doesn't
+            // count towards the buffer.
+            while !*this.done
+                && this.in_flight.len() < *this.concurrency
+                && this.outputs.len() <= *this.buffer
+            {
+                match this.iter.next() {
+                    Some(item) => this.in_flight.push((this.f)(item).par().cancel_on_drop()),
+                    None => *this.done = true,
+                }
+            }
+
+            let in_flight = this.in_flight.len();
+            let outputs = &mut *this.outputs;
+            this.in_flight
+                .retain_mut(|fut| match Pin::new(fut).poll(cx) {
+                    Poll::Ready(output) => {
+                        outputs.push_back(output);
+                        false
+                    }
+                    Poll::Pending => true,
+                });
+
+            // Completed futures may have freed up room for new ones.
+            if this.in_flight.len() == in_flight {
+                break;
+            }
+        }
+
+        match this.outputs.pop_front() {
+            Some(output) => Poll::Ready(Some(output)),
+            None if *this.done && this.in_flight.is_empty() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<I, F, Fut> fmt::Debug for ParMapBounded<I, F, Fut>
+where
+    I: fmt::Debug,
+    Fut: IntoFuture + fmt::Debug,
+    Fut::Output: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParMapBounded")
+            .field("iter", &self.iter)
+            .field("concurrency", &self.concurrency)
+            .field("buffer", &self.buffer)
+            .field("in_flight", &self.in_flight)
+            .field("outputs", &self.outputs)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A future which runs a parallel future for each item of an iterator.
+///
+/// This type is constructed by the
+/// [`par_for_each`][ParallelIteratorExt::par_for_each] method on
+/// [`ParallelIteratorExt`].
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ParForEach<I, F, Fut: IntoFuture> {
+    #[pin]
+    inner: ParMap<I, F, Fut>,
+}
+
+impl<I, F, Fut: IntoFuture> ParForEach<I, F, Fut> {
+    /// Limit the number of futures running at the same time.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            inner: self.inner.limit(limit),
+        }
+    }
+}
+
+impl<I, F, Fut> Future for ParForEach<I, F, Fut>
+where
+    I: Iterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: IntoFuture<Output = ()>,
+    Fut::IntoFuture: Send + 'static,
+{
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx).map(drop)
+    }
+}
+
+impl<I, F, Fut> fmt::Debug for ParForEach<I, F, Fut>
+where
+    I: fmt::Debug,
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParForEach")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// An iterator over the items of another iterator, in chunks of up to `size`
+/// items.
+#[derive(Debug)]
+struct Chunks<I> {
+    iter: I,
+    size: usize,
+}
+
+impl<I: Iterator> Iterator for Chunks<I> {
+    type Item = Vec<I::Item>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk: Vec<_> = self.iter.by_ref().take(self.size).collect();
+        match chunk.is_empty() {
+            true => None,
+            false => Some(chunk),
+        }
+    }
+}
+
+/// A future which maps chunks of items to parallel futures, and flattens their
+/// outputs.
+///
+/// This type is constructed by the
+/// [`par_chunks_map`][ParallelIteratorExt::par_chunks_map] method on
+/// [`ParallelIteratorExt`].
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ParChunksMap<I, F, Fut: IntoFuture> {
+    #[pin]
+    inner: ParMap<Chunks<I>, F, Fut>,
+}
+
+impl<I, F, Fut: IntoFuture> ParChunksMap<I, F, Fut> {
+    /// Limit the number of chunks running at the same time.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            inner: self.inner.limit(limit),
+        }
+    }
+}
+
+impl<I, F, Fut> Future for ParChunksMap<I, F, Fut>
+where
+    I: Iterator,
+    F: FnMut(Vec<I::Item>) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: IntoIterator + Send + 'static,
+{
+    type Output = Vec<<Fut::Output as IntoIterator>::Item>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let outputs = ready!(self.project().inner.poll(cx));
+        Poll::Ready(outputs.into_iter().flatten().collect())
+    }
+}
+
+impl<I, F, Fut> fmt::Debug for ParChunksMap<I, F, Fut>
+where
+    I: fmt::Debug,
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParChunksMap")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// The identity function, as used by [`ParCollect`] to map items.
+type Identity<T> = fn(T) -> T;
+
+/// A future which catches the panics of `T`, as used by [`ParJoinAllResults`].
+type CatchPanicFut<T> = CatchPanic<<T as IntoFuture>::IntoFuture>;
+
+/// Wraps a future to catch its panics, as used by [`ParJoinAllResults`].
+type CatchPanicFn<T> = fn(T) -> CatchPanicFut<T>;
+
+/// A future which runs futures in parallel and collects their outputs, or the
+/// panics they raised.
+///
+/// This type is constructed by the [`par_join_all_results`] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ParJoinAllResults<I>
+where
+    I: Iterator,
+    I::Item: IntoFuture,
+{
+    inner: ParMap<I, CatchPanicFn<I::Item>, CatchPanicFut<I::Item>>,
+}
+
+impl<I> ParJoinAllResults<I>
+where
+    I: Iterator,
+    I::Item: IntoFuture,
+{
+    /// Limit the number of futures running at the same time.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            inner: self.inner.limit(limit),
+        }
+    }
+}
+
+impl<I> Future for ParJoinAllResults<I>
+where
+    I: Iterator,
+    I::Item: IntoFuture,
+    <I::Item as IntoFuture>::IntoFuture: Send + 'static,
+    <I::Item as IntoFuture>::Output: Send + 'static,
+{
+    type Output = Vec<Result<<I::Item as IntoFuture>::Output, PanicError>>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.inner).poll(cx)
+    }
+}
+
+impl<I> fmt::Debug for ParJoinAllResults<I>
+where
+    I: Iterator + fmt::Debug,
+    I::Item: IntoFuture,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParJoinAllResults")
+            .field("iter", &self.inner.iter)
+            .field("limit", &self.inner.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A future which runs futures in parallel and collects their outputs.
+///
+/// This type is constructed by the
+/// [`par_collect`][ParallelIteratorExt::par_collect] method on
+/// [`ParallelIteratorExt`].
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ParCollect<I, C>
+where
+    I: Iterator,
+    I::Item: IntoFuture,
+{
+    #[pin]
+    inner: ParMap<I, Identity<I::Item>, I::Item>,
+    _collection: PhantomData<fn() -> C>,
+}
+
+impl<I, C> ParCollect<I, C>
+where
+    I: Iterator,
+    I::Item: IntoFuture,
+{
+    /// Limit the number of futures running at the same time.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            inner: self.inner.limit(limit),
+            _collection: PhantomData,
+        }
+    }
+}
+
+impl<I, C> Future for ParCollect<I, C>
+where
+    I: Iterator,
+    I::Item: IntoFuture,
+    <I::Item as IntoFuture>::IntoFuture: Send + 'static,
+    <I::Item as IntoFuture>::Output: Send + 'static,
+    C: FromIterator<<I::Item as IntoFuture>::Output>,
+{
+    type Output = C;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.poll(cx).map(C::from_iter)
+    }
+}
+
+impl<I, C> fmt::Debug for ParCollect<I, C>
+where
+    I: Iterator + fmt::Debug,
+    I::Item: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParCollect")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// A future which folds the outputs of parallel futures as they complete.
+///
+/// This type is constructed by the [`par_reduce`] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ParReduce<Fut: IntoFuture, T, F> {
+    in_flight: Vec<ParallelFuture<Fut>>,
+    acc: Option<T>,
+    combine: F,
+}
+
+// The futures and the accumulated value are never pinned, so `ParReduce` can
+// be moved regardless of their types.
+impl<Fut: IntoFuture, T, F> Unpin for ParReduce<Fut, T, F> {}
+
+impl<Fut, T, F> Future for ParReduce<Fut, T, F>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+    F: FnMut(T, Fut::Output) -> T,
+{
+    type Output = T;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        // Poll every future, so they're all spawned on the first poll.
+        let mut i = 0;
+        while i < this.in_flight.len() {
+            match Pin::new(&mut this.in_flight[i]).poll(cx) {
+                Poll::Ready(output) => {
+                    drop(this.in_flight.swap_remove(i));
+                    let acc = this
+                        .acc
+                        .take()
+                        .expect("`ParReduce` polled after completion");
+                    this.acc = Some((this.combine)(acc, output));
+                }
+                Poll::Pending => i += 1,
+            }
+        }
+        if !this.in_flight.is_empty() {
+            return Poll::Pending;
+        }
+        let acc = this
+            .acc
+            .take()
+            .expect("`ParReduce` polled after completion");
+        Poll::Ready(acc)
+    }
+}
+
+impl<Fut, T, F> fmt::Debug for ParReduce<Fut, T, F>
+where
+    Fut: IntoFuture + fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParReduce")
+            .field("in_flight", &self.in_flight)
+            .field("acc", &self.acc)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A stream of the outputs of parallel futures, in the order they complete.
+///
+/// This type is constructed by the
+/// [`par_unordered`][ParallelIteratorExt::par_unordered] method on
+/// [`ParallelIteratorExt`].
+#[must_use = "streams do nothing unless polled"]
+pub struct ParUnordered<Fut: IntoFuture> {
+    in_flight: Vec<ParallelFuture<Fut>>,
+}
+
+impl<Fut: IntoFuture> ParUnordered<Fut> {
+    /// Returns the number of futures which haven't completed yet.
+    pub fn len(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Returns `true` if all futures have completed.
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
+
+// The futures are never pinned, so `ParUnordered` can be moved regardless of
+// their type.
+impl<Fut: IntoFuture> Unpin for ParUnordered<Fut> {}
+
+impl<Fut> Stream for ParUnordered<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Item = Fut::Output;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.in_flight.is_empty() {
+            return Poll::Ready(None);
+        }
+        for i in 0..self.in_flight.len() {
+            if let Poll::Ready(output) = Pin::new(&mut self.in_flight[i]).poll(cx) {
+                drop(self.in_flight.swap_remove(i));
+                return Poll::Ready(Some(output));
+            }
+        }
+        Poll::Pending
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.in_flight.len(), Some(self.in_flight.len()))
+    }
+}
+
+impl<Fut> fmt::Debug for ParUnordered<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParUnordered")
+            .field("in_flight", &self.in_flight)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, VecDeque};
+    use std::future::{self, poll_fn};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Waker;
+    use std::time::Duration;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::testing::drop_signal;
+    use crate::{par_join_all_results, runtime};
+
+    #[test]
+    fn preserves_order() {
+        runtime::block_on(async {
+            let outputs = vec![30, 10, 20]
+                .par_map(|ms| async move {
+                    runtime::sleep(Duration::from_millis(ms)).await;
+                    ms
+                })
+                .await;
+            assert_eq!(outputs, vec![30, 10, 20]);
+        })
+    }
+
+    #[test]
+    fn limit() {
+        runtime::block_on(async {
+            let running = Arc::new(AtomicUsize::new(0));
+            let max = Arc::new(AtomicUsize::new(0));
+            let outputs = (0..8)
+                .par_map(|n| {
+                    let running = running.clone();
+                    let max = max.clone();
+                    async move {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max.fetch_max(now, Ordering::SeqCst);
+                        runtime::sleep(Duration::from_millis(20)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        n
+                    }
+                })
+                .limit(2)
+                .await;
+            assert_eq!(outputs, (0..8).collect::<Vec<_>>());
+            assert!(max.load(Ordering::SeqCst) <= 2);
+        })
+    }
+
+    #[test]
+    fn map_bounded() {
+        runtime::block_on(async {
+            let spawned = Arc::new(AtomicUsize::new(0));
+            let spawned_2 = spawned.clone();
+            let mut stream = (0..10).par_map_bounded(
+                move |n| {
+                    spawned_2.fetch_add(1, Ordering::SeqCst);
+                    async move { n }
+                },
+                2,
+                1,
+            );
+            let first = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+            assert!(first.is_some());
+
+            // Without a consumer polling the stream, no new futures are spawned.
+            assert!(spawned.load(Ordering::SeqCst) < 10);
+
+            let mut outputs = vec![first.unwrap()];
+            while let Some(output) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                outputs.push(output);
+            }
+            outputs.sort();
+            assert_eq!(outputs, (0..10).collect::<Vec<_>>());
+        })
+    }
+
+    #[test]
+    fn chunks_map() {
+        runtime::block_on(async {
+            let chunks = Arc::new(AtomicUsize::new(0));
+            let chunks_2 = chunks.clone();
+            let outputs = (0..10)
+                .par_chunks_map(4, move |chunk| {
+                    chunks_2.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        let len = chunk.len() as u64;
+                        runtime::sleep(Duration::from_millis(10 * (4 - len))).await;
+                        chunk
+                    }
+                })
+                .limit(2)
+                .await;
+            assert_eq!(outputs, (0..10).collect::<Vec<_>>());
+            assert_eq!(chunks.load(Ordering::SeqCst), 3);
+        })
+    }
+
+    #[test]
+    fn for_each() {
+        runtime::block_on(async {
+            let sum = Arc::new(AtomicUsize::new(0));
+            (1..=4)
+                .par_for_each(|n| {
+                    let sum = sum.clone();
+                    async move {
+                        runtime::sleep(Duration::from_millis(10)).await;
+                        sum.fetch_add(n, Ordering::SeqCst);
+                    }
+                })
+                .limit(2)
+                .await;
+            assert_eq!(sum.load(Ordering::SeqCst), 10);
+        })
+    }
+
+    #[test]
+    fn collect() {
+        runtime::block_on(async {
+            let futs = [30, 10, 20].map(|ms| async move {
+                runtime::sleep(Duration::from_millis(ms)).await;
+                ms
+            });
+            let outputs: VecDeque<_> = futs.par_collect().limit(2).await;
+            assert_eq!(outputs, [30, 10, 20]);
+
+            let futs = [("nori", 1), ("nori", 2)].map(|pair| async move { pair });
+            let map: HashMap<_, _> = futs.par_collect().await;
+            assert_eq!(map["nori"], 2);
+        })
+    }
+
+    #[test]
+    fn join_all() {
+        runtime::block_on(async {
+            let futs = [30, 10, 20].map(|ms| async move {
+                runtime::sleep(Duration::from_millis(ms)).await;
+                ms
+            });
+            assert_eq!(par_join_all(futs).await, vec![30, 10, 20]);
+
+            let (guards, dropped): (Vec<_>, Vec<_>) = (0..3).map(|_| drop_signal()).unzip();
+            let futs = guards.into_iter().map(|guard| async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            });
+            let mut fut = par_join_all(futs);
+            let poll = Pin::new(&mut fut).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+            dropped.join().await;
+        })
+    }
+
+    #[test]
+    fn join_all_results() {
+        runtime::block_on(async {
+            let futs = [30, 10, 20].map(|ms| async move {
+                runtime::sleep(Duration::from_millis(ms)).await;
+                if ms == 10 {
+                    panic!("oh no");
+                }
+                ms
+            });
+            let results = par_join_all_results(futs).limit(2).await;
+            assert_eq!(results.len(), 3);
+            assert_eq!(*results[0].as_ref().unwrap(), 30);
+            let err = results[1].as_ref().unwrap_err();
+            assert_eq!(err.message(), Some("oh no"));
+            assert_eq!(err.to_string(), "the parallel future panicked: oh no");
+            assert_eq!(*results[2].as_ref().unwrap(), 20);
+        })
+    }
+
+    #[test]
+    fn reduce() {
+        runtime::block_on(async {
+            let futs = [30, 10, 20].map(|ms| async move {
+                runtime::sleep(Duration::from_millis(ms)).await;
+                vec![ms]
+            });
+            let mut merged = super::par_reduce(futs, Vec::new(), |mut acc, output| {
+                acc.extend(output);
+                acc
+            })
+            .await;
+            merged.sort();
+            assert_eq!(merged, vec![10, 20, 30]);
+
+            let empty = super::par_reduce(Vec::<std::future::Ready<u32>>::new(), 12, |a, b| a + b);
+            assert_eq!(empty.await, 12);
+        })
+    }
+
+    #[test]
+    fn start_all() {
+        runtime::block_on(async {
+            let started = Arc::new(AtomicUsize::new(0));
+            let (guards, dropped): (Vec<_>, Vec<_>) = (0..3).map(|_| drop_signal()).unzip();
+            let futs = guards.into_iter().zip(0..3).map(|(guard, n)| {
+                let started = started.clone();
+                async move {
+                    let _guard = guard;
+                    started.fetch_add(1, Ordering::SeqCst);
+                    n
+                }
+                .par()
+            });
+            let futs = super::start_all(futs);
+
+            // All futures run before any of them is awaited.
+            dropped.join().await;
+            assert_eq!(started.load(Ordering::SeqCst), 3);
+            let mut outputs = Vec::new();
+            for fut in futs {
+                outputs.push(fut.await);
+            }
+            assert_eq!(outputs, vec![0, 1, 2]);
+        })
+    }
+
+    #[test]
+    fn unordered() {
+        runtime::block_on(async {
+            let (started, is_started): (Vec<_>, Vec<_>) = (0..3).map(|_| drop_signal()).unzip();
+            let futs = started
+                .into_iter()
+                .zip([30, 10, 20])
+                .map(|(started, ms)| async move {
+                    drop(started);
+                    runtime::sleep(Duration::from_millis(ms)).await;
+                    ms
+                });
+            let mut stream = futs.par_unordered();
+
+            // The futures run before the stream is first polled.
+            is_started.join().await;
+
+            let mut outputs = vec![];
+            while let Some(output) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                outputs.push(output);
+            }
+            assert_eq!(outputs, vec![10, 20, 30]);
+        })
+    }
+
+    #[test]
+    fn unordered_cancels_on_drop() {
+        runtime::block_on(async {
+            let (guards, dropped): (Vec<_>, Vec<_>) = (0..3).map(|_| drop_signal()).unzip();
+            let futs = guards.into_iter().map(|guard| async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            });
+            drop(futs.par_unordered());
+            dropped.join().await;
+        })
+    }
+}
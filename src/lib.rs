@@ -13,7 +13,7 @@
 //! use parallel_future::prelude::*;
 //! use futures_concurrency::prelude::*;
 //!
-//! async_std::task::block_on(async {
+//! parallel_future::par_block_on(async {
 //!     let a = async { 1 }.par();        // ← returns `ParallelFuture`
 //!     let b = async { 2 }.par();        // ← returns `ParallelFuture`
 //!
@@ -30,25 +30,276 @@
 //! limitation with no existing workarounds possible. `ParallelFuture` is designed to
 //! work with async destructors once they land.
 //!
-//! `ParallelFuture` starts lazily and does not provide a manual `detach`
-//! method. However it can be manually polled once and then passed to
-//! `mem::forget`, which will keep the future running on another thread. In the
+//! `ParallelFuture` starts lazily and is cancelled when dropped. To keep it
+//! running in the background instead it can be converted into a
+//! [`DetachedHandle`] using [`detach`][ParallelFuture::detach], which spawns
+//! the future right away. This replaces the older workaround of manually
+//! polling a `ParallelFuture` once and passing it to `mem::forget`. In the
 //! absence of unforgettable types (linear types), Rust cannot prevent
-//! `ParallelFuture`s from becoming unmanaged (dangling).
+//! `ParallelFuture`s from becoming unmanaged (dangling) either way.
+//!
+//! # Runtimes
+//!
+//! Futures are spawned on [async-std](https://docs.rs/async-std) by default.
+//! To spawn on [Tokio](https://docs.rs/tokio) instead, disable the default
+//! features and enable the `tokio` feature:
+//!
+//! ```toml
+//! [dependencies]
+//! parallel-future = { version = "6", default-features = false, features = ["tokio"] }
+//! ```
+//!
+//! The `smol` feature spawns futures on the global [smol](https://docs.rs/smol)
+//! executor in the same way. Exactly one runtime feature must be enabled. When
+//! using Tokio, `ParallelFuture` must be polled from within a Tokio runtime.
+//!
+//! Futures can also be spawned on any other executor by implementing the
+//! [`Executor`] trait for it, and converting futures with
+//! [`par_on`][IntoFutureExt::par_on] rather than [`par`][IntoFutureExt::par].
+//! Applications can also install an executor for every call to `par` through
+//! [`set_default_executor`][executor::set_default_executor].
+//!
+//! # Nesting
+//!
+//! Parallel futures can be created and awaited inside other parallel futures,
+//! to any depth. The nested futures are spawned on the same executor as any
+//! other, and awaiting them suspends the outer task rather than blocking its
+//! thread, so this works on single-threaded executors too. When nested
+//! futures hang, it's usually because a task blocks its thread while waiting
+//! on another task, such as by calling a `block_on` function or locking a
+//! `std` mutex which that task holds. On a single-threaded executor the other
+//! task then never gets to run. Await the nested future instead, and for work
+//! which has to block, use [`par_blocking`]. Futures which aren't `Send` can
+//! be nested with `par_local`.
+//!
+//! # Tracing
+//!
+//! With the `tracing` feature enabled, the [tracing](https://docs.rs/tracing)
+//! span which is current when a future is spawned is entered inside the
+//! spawned task as well. That way events logged from parallel futures are
+//! correlated with the work which started them.
+//!
+//! # Logging
+//!
+//! With the `log` feature enabled, tasks log a debug line through the
+//! [log](https://docs.rs/log) crate when they're spawned, and when they
+//! complete, panic, or are cancelled. Each line includes the task's id, and its
+//! name if it has one. That's a lighter alternative to `tracing` for
+//! applications which only use `log`.
+//!
+//! # Metrics
+//!
+//! With the `metrics` feature enabled, tasks record how long they took from
+//! being spawned to completing. Once a task has completed, its run time can be
+//! read through `elapsed` on its `ParallelFuture` or `DetachedHandle`. The
+//! events passed to the metrics hook also report how long each task waited to
+//! be polled for the first time, which points out an overloaded executor.
+//!
+//! # Debugging
+//!
+//! With the `debug` feature enabled, parallel futures record the location in
+//! the source code they were created at, such as the call to `par`. When a
+//! task panics, that location is printed after the panic message, and it can
+//! be read through `location` on a `ParallelFuture`, on the `Task` handed to
+//! an executor, and on the events passed to the metrics hook.
+//!
+//! # Allocation tracking
+//!
+//! With the `track-allocations` feature enabled, the events passed to the
+//! metrics hook report how many bytes each task allocated. This needs a global
+//! allocator shim, `TrackingAllocator`, to be installed by the application,
+//! and only counts allocations made while the task is being polled. It's meant
+//! to point out which tasks allocate the most, rather than to give exact
+//! numbers.
+//!
+//! # Draining
+//!
+//! With the `drain` feature enabled, `drain` waits for the tasks which are
+//! still in flight at the end of `main` to finish, and cancels the ones nobody
+//! is waiting on once a timeout passes. Every task is tracked in a global
+//! registry for this, which costs a lock on every spawn and completion, so
+//! it's opt-in.
+//!
+//! # Channels
+//!
+//! With the `async-channel` feature enabled, `par_pipeline` processes the
+//! items of an [async-channel](https://docs.rs/async-channel) receiver on a
+//! pool of parallel workers, sending their outputs to another channel.
+//!
+//! # Detaching on drop
+//!
+//! Dropping a `ParallelFuture` cancels the spawned future by default. With the
+//! `detach-on-drop` feature enabled, dropped futures are detached and keep
+//! running in the background instead, as if every future was created with
+//! [`keep_alive_on_drop`][ParallelFuture::keep_alive_on_drop]. Individual
+//! futures can opt back into being cancelled through
+//! [`cancel_on_drop`][ParallelFuture::cancel_on_drop]. This only applies to
+//! futures you create yourself: the futures which this crate spawns on your
+//! behalf, such as those of `par_timeout`, `par_first_n`, `par_map` and
+//! `Limiter`, are still cancelled when they're no longer needed.
+//!
+//! This is a crate-wide policy switch, and since cargo features are additive
+//! it applies to every crate in the dependency graph which uses
+//! `parallel-future`, including libraries which were written assuming
+//! futures are cancelled on drop. That has consequences worth weighing before
+//! enabling it:
+//!
+//! - Work is no longer bounded by its owner. Timeouts, races and early
+//!   returns through `?` stop waiting for a future, but the future keeps
+//!   running to completion, and its output is discarded.
+//! - Tasks can outlive the state they were meant to be scoped to, and keep
+//!   holding on to resources such as connections, locks and memory until they
+//!   complete. A task which never completes is leaked for the lifetime of the
+//!   runtime.
+//! - Side effects still happen after the caller has given up on them, so a
+//!   retried or raced operation may run more than once.
+//! - [`on_cancel`][ParallelFuture::on_cancel] closures aren't called for
+//!   dropped futures, since they're no longer cancelled. Graceful futures
+//!   still have their token cancelled, but aren't forcibly cancelled after the
+//!   grace period set through [`with_shutdown`][ParallelFuture::with_shutdown].
+//!
+//! Futures which are dropped before they were ever polled are still never
+//! spawned, and [`ParallelGroup`] still cancels the futures spawned on it.
+//!
+//! # `no_std`
+//!
+//! Disabling the default features and leaving out a runtime feature makes this
+//! crate `no_std`, though it still requires `alloc`. What's left is the core:
+//! [`ParallelFuture`] and its combinators, and the
+//! [`Executor`] trait. There's no default executor in that
+//! case, so futures have to be spawned on an executor explicitly through
+//! [`par_on`][IntoFutureExt::par_on]. Everything which needs threads, locks or
+//! timers requires the `std` feature, which each of the runtime features enable.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_debug_implementations, nonstandard_style)]
 #![warn(missing_docs, unreachable_pub)]
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::fmt;
+use core::future::{Future, IntoFuture};
+use core::pin::Pin;
+use core::task::{Context, Poll};
 use pin_project::{pin_project, pinned_drop};
-use std::future::{Future, IntoFuture};
-use std::pin::Pin;
-use std::task::{Context, Poll};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use executor::{Config, Executor, JoinHandle, SpawnError, TaskId};
+#[cfg(feature = "std")]
+use future::{
+    Abortable, Blocking, CatchUnwind, Shared, SpawnFn, SpawnWith, Timeout, TryTimeout, Zip,
+};
+use future::{Buffered, Builder, Flatten, Inspect, Map, MapErr, Then};
+
+#[cfg(feature = "std")]
+pub use abort::AbortHandle;
+#[cfg(feature = "std")]
+pub use actor::par_actor;
+#[cfg(feature = "track-allocations")]
+pub use allocations::TrackingAllocator;
+#[cfg(feature = "drain")]
+pub use drain::drain;
+#[cfg(feature = "std")]
+pub use factory::ParFactory;
+#[cfg(feature = "std")]
+pub use first_n::par_first_n;
+#[cfg(feature = "std")]
+pub use group::{ParallelGroup, ParallelPriorityGroup, ParallelTryGroup};
+#[cfg(feature = "std")]
+pub use iter::{par_join_all, par_join_all_results, par_reduce, start_all};
+#[cfg(feature = "std")]
+pub use limiter::Limiter;
+#[cfg(feature = "async-std")]
+pub use local::{IntoLocalFutureExt, LocalBuilder, LocalParallelFuture};
+#[cfg(feature = "std")]
+pub use metrics::{active_task_count, set_metrics_hook, TaskEvent, TaskEventKind};
+#[cfg(feature = "std")]
+pub use nursery::{nursery, Nursery};
+#[cfg(feature = "std")]
+pub use panic::PanicError;
+#[cfg(feature = "async-channel")]
+pub use pipeline::par_pipeline;
+#[cfg(feature = "std")]
+pub use rate_limiter::RateLimiter;
+#[cfg(feature = "std")]
+pub use result::par_result;
+#[cfg(feature = "std")]
+pub use retry::{par_retry, RetryPolicy};
+#[cfg(feature = "std")]
+pub use scope::{blocking_scope, BlockingScope, ScopedJoinHandle};
+pub use select::{par_select, par_select_all};
+#[cfg(feature = "std")]
+pub use token::CancellationToken;
+#[cfg(feature = "std")]
+pub use yield_now::yield_now;
 
-use async_std::task;
+#[cfg(feature = "std")]
+pub mod actor;
+pub mod executor;
+pub mod future;
+#[cfg(feature = "std")]
+pub mod iter;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod testing;
+
+#[cfg(feature = "std")]
+mod abort;
+#[cfg(feature = "track-allocations")]
+mod allocations;
+#[cfg(feature = "drain")]
+mod drain;
+#[cfg(feature = "std")]
+mod factory;
+#[cfg(feature = "std")]
+mod first_n;
+#[cfg(feature = "std")]
+mod group;
+#[cfg(feature = "std")]
+mod limiter;
+#[cfg(feature = "async-std")]
+mod local;
+#[cfg(feature = "std")]
+mod metrics;
+#[cfg(feature = "std")]
+mod nursery;
+mod oneshot;
+#[cfg(feature = "std")]
+mod panic;
+#[cfg(feature = "async-channel")]
+mod pipeline;
+#[cfg(feature = "std")]
+mod rate_limiter;
+#[cfg(feature = "std")]
+mod result;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+mod runtime;
+#[cfg(feature = "std")]
+mod scope;
+mod select;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+mod token;
+#[cfg(feature = "std")]
+mod yield_now;
 
 /// The `parallel-future` prelude.
 pub mod prelude {
+    pub use super::future::ParallelFutureExt as _;
+    #[cfg(feature = "std")]
+    pub use super::iter::ParallelIteratorExt as _;
+    #[cfg(feature = "std")]
+    pub use super::stream::ParallelStreamExt as _;
     pub use super::IntoFutureExt as _;
+    #[cfg(feature = "async-std")]
+    pub use super::IntoLocalFutureExt as _;
 }
 
 /// A parallelizable future.
@@ -61,7 +312,7 @@ pub mod prelude {
 /// use parallel_future::prelude::*;
 /// use futures_concurrency::prelude::*;
 ///
-/// async_std::task::block_on(async {
+/// parallel_future::par_block_on(async {
 ///     let a = async { 1 }.par();        // ← returns `ParallelFuture`
 ///     let b = async { 2 }.par();        // ← returns `ParallelFuture`
 ///
@@ -69,15 +320,52 @@ pub mod prelude {
 ///     assert_eq!(a + b, 3);
 /// })
 /// ```
-#[derive(Debug)]
+///
+/// # Panics
+///
+/// If the spawned future panics, the panic is caught on the thread it ran on
+/// and resumed in the task awaiting the `ParallelFuture`. Use
+/// [`catch_unwind`][ParallelFuture::catch_unwind] to handle the panic instead.
+///
+/// # Cancellation
+///
+/// Dropping a `ParallelFuture` cancels the spawned task, rather than detaching
+/// it. That makes it safe to use with combinators like `race`, which drop the
+/// futures which lose: their tasks stop at their next await point. Dropping a
+/// `ParallelFuture` before it was first polled never spawns the task at all.
+///
+/// # Polling by hand
+///
+/// `ParallelFuture` is always `Unpin`, regardless of the future it wraps, since
+/// that future is moved into the spawned task rather than polled in place. So
+/// it can be stored in a hand-written future and polled through
+/// [`poll_unpin`][ParallelFuture::poll_unpin] or `Pin::new`, or awaited by
+/// reference as `(&mut fut).await`, without boxing it. The first poll spawns
+/// the task, and every poll after that only checks on its output and
+/// registers the waker. Once the output has been returned, polling again
+/// panics.
 #[pin_project(PinnedDrop)]
 #[must_use = "futures do nothing unless you `.await` or poll them"]
 pub struct ParallelFuture<Fut: IntoFuture> {
     into_future: Option<Fut>,
+    executor: Option<Arc<dyn Executor>>,
+    config: Config,
+    #[cfg(feature = "std")]
+    token: Option<CancellationToken>,
+    /// Whether to detach the task rather than cancel it when dropped.
+    keep_alive: bool,
+    /// Called when dropping the future cancels the task.
+    on_cancel: Option<OnCancel>,
+    /// How long a graceful future may keep running once it's been dropped.
+    #[cfg(feature = "std")]
+    shutdown: Option<Shutdown<Fut::Output>>,
     #[pin]
-    handle: Option<task::JoinHandle<Fut::Output>>,
+    handle: Option<JoinHandle<Fut::Output>>,
 }
 
+/// A closure which is called when a `ParallelFuture` is cancelled.
+type OnCancel = Box<dyn FnOnce() + Send>;
+
 impl<Fut> Future for ParallelFuture<Fut>
 where
     Fut: IntoFuture,
@@ -85,97 +373,2341 @@ where
     Fut::Output: Send + 'static,
 {
     type Output = <Fut as IntoFuture>::Output;
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let mut this = self.project();
-        if this.handle.is_none() {
-            let into_fut = this.into_future.take().unwrap().into_future();
-            let handle = task::spawn(into_fut.into_future());
-            *this.handle = Some(handle);
-        }
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.start();
+        let this = self.project();
         Pin::new(&mut this.handle.as_pin_mut().unwrap()).poll(cx)
     }
 }
 
-/// Cancel the `ParallelFuture` when dropped.
-#[pinned_drop]
-impl<Fut: IntoFuture> PinnedDrop for ParallelFuture<Fut> {
-    fn drop(self: Pin<&mut Self>) {
-        let mut this = self.project();
-        if let Some(handle) = this.handle.take() {
-            let _ = handle.cancel();
+impl<Fut> ParallelFuture<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    fn new(into_future: Fut, executor: Option<Arc<dyn Executor>>, config: Config) -> Self {
+        Self {
+            into_future: Some(into_future),
+            executor,
+            config,
+            #[cfg(feature = "std")]
+            token: None,
+            keep_alive: cfg!(feature = "detach-on-drop"),
+            on_cancel: None,
+            #[cfg(feature = "std")]
+            shutdown: None,
+            handle: None,
         }
     }
-}
 
-/// Extend the `Future` trait.
-pub trait IntoFutureExt: IntoFuture + Sized
-where
-    <Self as IntoFuture>::IntoFuture: Send + 'static,
-    <Self as IntoFuture>::Output: Send + 'static,
-{
-    /// Convert this future into a parallelizable future.
+    /// Spawn the future, unless it has already been spawned.
+    fn start(&mut self) {
+        if self.handle.is_none() {
+            let into_fut = self
+                .into_future
+                .take()
+                .expect("`ParallelFuture` polled after it failed to spawn")
+                .into_future();
+            let config = self.config.clone();
+            let handle = executor::spawn(self.executor.as_deref(), config, into_fut);
+            self.handle = Some(handle);
+        }
+    }
+
+    /// Spawn the future now, rather than when it's first polled, returning an
+    /// error if the executor can't spawn tasks.
+    ///
+    /// Polling a `ParallelFuture` panics if the future can't be spawned, for
+    /// example because the runtime has been shut down. Calling this first
+    /// surfaces that as an error instead. Nothing happens if the future has
+    /// already been spawned. If spawning fails, the future is dropped without
+    /// having run, and the `ParallelFuture` panics if polled.
     ///
     /// # Examples
     ///
     /// ```
     /// use parallel_future::prelude::*;
-    /// use futures_concurrency::prelude::*;
-    ///
-    /// async_std::task::block_on(async {
-    ///     let a = async { 1 }.par();        // ← returns `ParallelFuture`
-    ///     let b = async { 2 }.par();        // ← returns `ParallelFuture`
     ///
-    ///     let (a, b) = (a, b).join().await; // ← concurrent `.await`
-    ///     assert_eq!(a + b, 3);
+    /// parallel_future::par_block_on(async {
+    ///     let mut fut = async { 12 }.par();
+    ///     fut.try_spawn().expect("the runtime is running");
+    ///     assert!(fut.has_started());
+    ///     assert_eq!(fut.await, 12);
     /// })
     /// ```
-    fn par(self) -> ParallelFuture<Self> {
-        ParallelFuture {
-            into_future: Some(self),
-            handle: None,
+    pub fn try_spawn(&mut self) -> Result<(), SpawnError> {
+        if self.handle.is_none() {
+            let into_fut = match self.into_future.take() {
+                Some(into_fut) => into_fut.into_future(),
+                None => return Err(SpawnError::new()),
+            };
+            let config = self.config.clone();
+            let handle = executor::try_spawn(self.executor.as_deref(), config, into_fut)?;
+            self.handle = Some(handle);
         }
+        Ok(())
     }
-}
 
-impl<Fut> IntoFutureExt for Fut
-where
-    Fut: IntoFuture,
-    <Fut as IntoFuture>::IntoFuture: Send + 'static,
-    <Fut as IntoFuture>::Output: Send + 'static,
-{
-}
+    /// Poll the future without pinning it first.
+    ///
+    /// This is a shorthand for `Pin::new(&mut fut).poll(cx)`, for driving a
+    /// `ParallelFuture` from a hand-written future. The first call spawns the
+    /// task, like the first poll of any `ParallelFuture` does. See
+    /// [Polling by hand](ParallelFuture#polling-by-hand) for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::future::poll_fn;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let mut fut = async { 12 }.par();
+    ///     let res = poll_fn(|cx| fut.poll_unpin(cx)).await;
+    ///     assert_eq!(res, 12);
+    /// })
+    /// ```
+    pub fn poll_unpin(&mut self, cx: &mut Context<'_>) -> Poll<Fut::Output> {
+        Pin::new(self).poll(cx)
+    }
 
-#[cfg(test)]
-mod test {
-    use std::{
-        sync::{Arc, Mutex},
-        time::Duration,
-    };
+    /// Returns a future which resolves once the spawned task has finished,
+    /// after this `ParallelFuture` has been dropped. Returns `None` if the
+    /// future hasn't been spawned.
+    #[cfg(feature = "std")]
+    pub(crate) fn task_closed(&self) -> Option<oneshot::Closed<executor::TaskResult<Fut::Output>>> {
+        self.handle.as_ref().map(JoinHandle::closed)
+    }
 
-    use async_std::task;
+    /// Poll for the output of the future, without resuming panics.
+    #[cfg(feature = "std")]
+    pub(crate) fn poll_output(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<executor::TaskResult<Fut::Output>> {
+        self.start();
+        self.handle.as_mut().unwrap().poll_output(cx)
+    }
 
-    use super::prelude::*;
+    /// Returns the name of the task, if one was set through
+    /// [`par_builder`][IntoFutureExt::par_builder].
+    pub fn name(&self) -> Option<&str> {
+        self.config.name.as_deref()
+    }
 
-    #[test]
-    fn spawn() {
-        async_std::task::block_on(async {
-            let res = async { "nori is a horse" }.par().await;
-            assert_eq!(res, "nori is a horse");
-        })
+    /// Returns where this future was created, such as the call to
+    /// [`par`][IntoFutureExt::par]. This requires the `debug` feature.
+    #[cfg(feature = "debug")]
+    pub fn location(&self) -> Option<&'static core::panic::Location<'static>> {
+        self.config.location
     }
 
-    #[test]
-    fn is_lazy() {
-        async_std::task::block_on(async {
-            let polled = Arc::new(Mutex::new(false));
-            let polled_2 = polled.clone();
-            let _res = async move {
-                *polled_2.lock().unwrap() = true;
-            }
-            .par();
+    /// Returns the id of the spawned task.
+    ///
+    /// Ids are allocated when the future is spawned, so this returns `None`
+    /// until the future has been polled at least once.
+    pub fn id(&self) -> Option<TaskId> {
+        self.handle.as_ref().map(JoinHandle::id)
+    }
 
-            task::sleep(Duration::from_millis(500)).await;
-            assert_eq!(*polled.lock().unwrap(), false);
-        })
+    /// Instrument the spawned future with the given span.
+    ///
+    /// By default the span which is current when the future is spawned is
+    /// entered inside the spawned task. This overrides it with another span.
+    /// It has no effect once the future has been spawned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let span = tracing::info_span!("compute");
+    ///     let res = async { 12 }.par().instrument(span).await;
+    ///     assert_eq!(res, 12);
+    /// })
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn instrument(mut self, span: tracing::Span) -> Self {
+        self.config.span = Some(span);
+        self
+    }
+
+    /// Returns `true` once the future has been spawned.
+    ///
+    /// Parallel futures are lazy, so this returns `false` until the future has
+    /// been polled at least once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::future::Future;
+    /// use std::pin::pin;
+    /// use std::task::{Context, Waker};
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let mut fut = pin!(async { 12 }.par());
+    ///     assert!(!fut.has_started());
+    ///
+    ///     let _ = fut.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+    ///     assert!(fut.has_started());
+    /// })
+    /// ```
+    pub fn has_started(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Returns `true` once the spawned future has completed.
+    ///
+    /// This does not poll the future, so it returns `false` until the future
+    /// has been spawned by polling it at least once. A future which was
+    /// cancelled by its executor is also considered finished.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_some_and(JoinHandle::is_finished)
+    }
+
+    /// Returns how long the spawned future took to complete, measured from
+    /// when it was spawned rather than when the `ParallelFuture` was created.
+    ///
+    /// Returns `None` until the spawned future has completed. This requires the
+    /// `metrics` feature.
+    #[cfg(feature = "metrics")]
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.handle.as_ref().and_then(JoinHandle::elapsed)
+    }
+
+    /// Cancel the given token when dropped, rather than cancelling the spawned
+    /// future.
+    ///
+    /// The spawned future keeps running in the background after the
+    /// `ParallelFuture` is dropped, and is expected to observe the token and
+    /// wind down on its own. See [`par_with_token`] for a shorthand which
+    /// passes the token to the future.
+    #[cfg(feature = "std")]
+    pub fn graceful(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Cancel the spawned future if it's still running a grace period after
+    /// its token was cancelled.
+    ///
+    /// This combines cooperative and hard cancellation for futures made
+    /// [`graceful`][ParallelFuture::graceful], or created through
+    /// [`par_with_token`]. When the `ParallelFuture` is dropped the token is
+    /// cancelled first, giving the spawned future a chance to clean up. If it
+    /// hasn't completed once the grace period has elapsed, it's cancelled at
+    /// its next await point. Without a token this has no effect, and dropping
+    /// the `ParallelFuture` cancels the spawned future right away.
+    ///
+    /// Rust doesn't have async drop, so the grace period is enforced by a
+    /// small watchdog task, spawned on the same executor when the
+    /// `ParallelFuture` is dropped. That's an extra task and timer per dropped
+    /// future, which is why this is opt-in. If the watchdog can't be spawned,
+    /// the spawned future is cancelled right away instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::par_with_token;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = par_with_token(|token| async move {
+    ///         token.cancelled().await;
+    ///         // ← clean up, within 100ms
+    ///     })
+    ///     .with_shutdown(Duration::from_millis(100));
+    ///     drop(fut);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn with_shutdown(mut self, grace: Duration) -> Self {
+        self.shutdown = Some(Shutdown {
+            grace,
+            watchdog: spawn_watchdog::<Fut::Output>,
+        });
+        self
+    }
+
+    /// Let the spawned future run to completion when this `ParallelFuture` is
+    /// dropped, rather than cancelling it.
+    ///
+    /// Unlike [`detach`][ParallelFuture::detach], this only changes what
+    /// happens on drop: the future is still lazy, and can be combined and
+    /// awaited like any other `ParallelFuture`. A future which is dropped
+    /// before it was ever polled is never spawned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = async { 12 }.par().keep_alive_on_drop();
+    ///     assert_eq!(fut.await, 12); // ← can still be awaited
+    /// })
+    /// ```
+    pub fn keep_alive_on_drop(mut self) -> Self {
+        self.keep_alive = true;
+        self
+    }
+
+    /// Cancel the spawned future when this `ParallelFuture` is dropped.
+    ///
+    /// This is the default, unless the `detach-on-drop` feature is enabled. In
+    /// that case this opts individual futures back into being cancelled, such
+    /// as the losing side of a race. It undoes
+    /// [`keep_alive_on_drop`][ParallelFuture::keep_alive_on_drop].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = async { 12 }.par().cancel_on_drop();
+    ///     assert_eq!(fut.await, 12);
+    /// })
+    /// ```
+    pub fn cancel_on_drop(mut self) -> Self {
+        self.keep_alive = false;
+        self
+    }
+
+    /// Call a closure when this `ParallelFuture` is cancelled.
+    ///
+    /// The closure is called once, from the drop path, if dropping the
+    /// `ParallelFuture` cancels the spawned future or prevents it from being
+    /// spawned at all. It's not called if the spawned future completed, nor if
+    /// it's left running through [`keep_alive_on_drop`], [`graceful`], or
+    /// [`detach`]. Registering multiple closures calls them in order. A
+    /// graceful future which outlives the grace period set through
+    /// [`with_shutdown`] is cancelled by a watchdog, which calls the closure
+    /// instead.
+    ///
+    /// [`keep_alive_on_drop`]: ParallelFuture::keep_alive_on_drop
+    /// [`graceful`]: ParallelFuture::graceful
+    /// [`with_shutdown`]: ParallelFuture::with_shutdown
+    /// [`detach`]: ParallelFuture::detach
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let cancelled = Arc::new(AtomicBool::new(false));
+    /// let cancelled_2 = cancelled.clone();
+    /// let fut = async { 12 }
+    ///     .par()
+    ///     .on_cancel(move || cancelled_2.store(true, Ordering::SeqCst));
+    /// drop(fut);
+    /// assert!(cancelled.load(Ordering::SeqCst));
+    /// ```
+    pub fn on_cancel<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.on_cancel = Some(match self.on_cancel.take() {
+            Some(prev) => Box::new(move || {
+                prev();
+                f();
+            }),
+            None => Box::new(f),
+        });
+        self
+    }
+
+    /// Spawn the future and detach it from this handle.
+    ///
+    /// Unlike `ParallelFuture`, the returned [`DetachedHandle`] does not cancel
+    /// the task when dropped. It can still be awaited to retrieve the output.
+    ///
+    /// The future is spawned without being polled by the caller, so this is the
+    /// supported alternative to polling a `ParallelFuture` once and then
+    /// passing it to `mem::forget`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let handle = async { 1 + 1 }.par().detach(); // ← starts running now
+    ///     assert_eq!(handle.await, 2);
+    /// })
+    /// ```
+    #[doc(alias = "kickoff")]
+    #[doc(alias = "forget")]
+    pub fn detach(mut self) -> DetachedHandle<Fut::Output> {
+        self.start();
+        DetachedHandle {
+            handle: self.handle.take().unwrap(),
+        }
+    }
+
+    /// Spawn the future directly on the runtime, and return the runtime's own
+    /// join handle.
+    ///
+    /// This is an escape hatch for runtime-specific handle methods which this
+    /// crate doesn't expose. The returned [`RawJoinHandle`] is the async-std,
+    /// Tokio or smol handle, depending on the selected runtime feature, and
+    /// the caller takes over responsibility for the task's lifecycle: it's no
+    /// longer cancelled on drop by this crate, panics are reported however
+    /// the runtime reports them, and the options set through
+    /// [`par_builder`][IntoFutureExt::par_builder], metrics, and tracing don't
+    /// apply. The future is always spawned on the runtime, even if another
+    /// executor was installed through
+    /// [`set_default_executor`][executor::set_default_executor].
+    ///
+    /// [`RawJoinHandle`]: executor::RawJoinHandle
+    ///
+    /// # Panics
+    ///
+    /// This panics if the future has already been spawned, or if it was
+    /// created through [`par_on`][IntoFutureExt::par_on] to be spawned on
+    /// another executor.
+    ///
+    /// # Examples
+    ///
+    /// This example uses async-std's handle, so it requires the `async-std`
+    /// feature.
+    #[cfg_attr(feature = "async-std", doc = "```")]
+    #[cfg_attr(not(feature = "async-std"), doc = "```ignore")]
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let handle = async { 12 }.par().into_inner_handle();
+    ///     println!("spawned task {}", handle.task().id()); // ← async-std API
+    ///     assert_eq!(handle.await, 12);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn into_inner_handle(mut self) -> executor::RawJoinHandle<Fut::Output> {
+        assert!(
+            self.executor.is_none(),
+            "`into_inner_handle` called on a future with a custom executor"
+        );
+        let into_future = self
+            .into_future
+            .take()
+            .expect("`into_inner_handle` called after the future was spawned");
+        runtime::spawn_raw(into_future.into_future())
+    }
+
+    /// Erase the type of the future, returning a [`BoxParallelFuture`].
+    ///
+    /// The returned handle is `Unpin` and only generic over the output, which
+    /// makes it easy to store alongside parallel futures of other types. It
+    /// wraps this future rather than spawning it again: the task is spawned
+    /// when the handle is first polled, and cancelled when it's dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use parallel_future::BoxParallelFuture;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let futs: Vec<BoxParallelFuture<u8>> = vec![
+    ///         async { 1 }.par().boxed(),
+    ///         async { 2 }.par().on_cancel(|| println!("cancelled")).boxed(),
+    ///     ];
+    ///     let mut sum = 0;
+    ///     for fut in futs {
+    ///         sum += fut.await;
+    ///     }
+    ///     assert_eq!(sum, 3);
+    /// })
+    /// ```
+    pub fn boxed(self) -> BoxParallelFuture<Fut::Output>
+    where
+        Fut: Send + 'static,
+    {
+        BoxParallelFuture::new(self)
+    }
+
+    /// Map the output of the spawned future with a closure.
+    ///
+    /// The closure runs in the awaiting task once the spawned future has
+    /// completed, so no additional task is spawned. Dropping the returned
+    /// future still cancels the spawned future.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { 1 }.par().map(|n| n + 1).await;
+    ///     assert_eq!(res, 2);
+    /// })
+    /// ```
+    pub fn map<F, U>(self, f: F) -> Map<Self, F>
+    where
+        F: FnOnce(Fut::Output) -> U,
+    {
+        Map::new(self, f)
+    }
+
+    /// Map the error of the spawned future's fallible output with a closure.
+    ///
+    /// Like [`map`][ParallelFuture::map], the closure runs in the awaiting task
+    /// without spawning an additional task, and is only called if the spawned
+    /// future returned an error. Dropping the returned future still cancels
+    /// the spawned future.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { "nori".parse::<u8>() }
+    ///         .par()
+    ///         .map_err(|err| err.to_string())
+    ///         .await;
+    ///     assert_eq!(res, Err("invalid digit found in string".to_string()));
+    /// })
+    /// ```
+    pub fn map_err<F, T, E, E2>(self, f: F) -> MapErr<Self, F>
+    where
+        Fut: IntoFuture<Output = Result<T, E>>,
+        F: FnOnce(E) -> E2,
+    {
+        MapErr::new(self, f)
+    }
+
+    /// Call a closure with a reference to the output of the spawned future,
+    /// before passing the output on.
+    ///
+    /// Like [`map`][ParallelFuture::map], the closure runs in the awaiting task
+    /// without spawning an additional task. If the future is cancelled, the
+    /// closure is never called.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { 12 }
+    ///         .par()
+    ///         .inspect(|n| println!("completed with {n}"))
+    ///         .await;
+    ///     assert_eq!(res, 12);
+    /// })
+    /// ```
+    pub fn inspect<F>(self, f: F) -> Inspect<Self, F>
+    where
+        F: FnOnce(&Fut::Output),
+    {
+        Inspect::new(self, f)
+    }
+
+    /// Pass the output of the spawned future to an async continuation.
+    ///
+    /// The closure is called, and the future it returns is awaited, in the
+    /// awaiting task once the spawned future has completed. Unlike spawning
+    /// the continuation with [`par`][IntoFutureExt::par], that doesn't hop to
+    /// another thread. Dropping the returned future cancels the spawned future,
+    /// or the continuation if it's already running.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { 1 }.par().then(|n| async move { n + 1 }).await;
+    ///     assert_eq!(res, 2);
+    /// })
+    /// ```
+    pub fn then<F, Fut2>(self, f: F) -> Then<Self, F, Fut2>
+    where
+        F: FnOnce(Fut::Output) -> Fut2,
+        Fut2: IntoFuture,
+    {
+        Then::new(self, f)
+    }
+
+    /// Await the future which the spawned future resolves to.
+    ///
+    /// Once the spawned future completes, its output is converted into a
+    /// future and awaited in the awaiting task. If that's a `ParallelFuture`
+    /// itself, it's spawned in turn, so both stages run in parallel. Dropping
+    /// the returned future cancels whichever stage is currently running.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::future::ready;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let inner = async { 1 + 1 }.par();
+    ///     let res = ready(inner).par().flatten().await;
+    ///     assert_eq!(res, 2);
+    /// })
+    /// ```
+    pub fn flatten(self) -> Flatten<Fut>
+    where
+        Fut::Output: IntoFuture,
+    {
+        Flatten::new(self)
+    }
+
+    /// Share the output of the spawned future between multiple consumers.
+    ///
+    /// The returned future can be cloned, and every clone resolves to a clone
+    /// of the same output. The future is spawned only once, and is cancelled
+    /// once the last clone is dropped rather than the first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use futures_concurrency::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let a = async { 12 }.par().shared();
+    ///     let b = a.clone();
+    ///     let (a, b) = (a, b).join().await; // ← spawned only once
+    ///     assert_eq!(a + b, 24);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn shared(self) -> Shared<Fut>
+    where
+        Fut::Output: Clone,
+    {
+        Shared::new(self)
+    }
+
+    /// Keep the output of the spawned future around, so it can be awaited
+    /// more than once.
+    ///
+    /// Unlike [`shared`][ParallelFuture::shared], the returned future has a
+    /// single owner, which can await it by reference as often as it likes.
+    /// Every await resolves to a clone of the output, which is stored once the
+    /// future first completes. Until then, dropping it cancels the spawned
+    /// task like dropping the `ParallelFuture` would.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let mut fut = async { String::from("nori") }.par().buffered();
+    ///     assert_eq!((&mut fut).await, "nori");
+    ///     assert_eq!((&mut fut).await, "nori"); // ← no second spawn
+    ///     assert_eq!(fut.get().map(String::as_str), Some("nori"));
+    /// })
+    /// ```
+    pub fn buffered(self) -> Buffered<Fut>
+    where
+        Fut::Output: Clone,
+    {
+        Buffered::new(self)
+    }
+
+    /// Catch panics in the spawned future.
+    ///
+    /// Rather than resuming a panic in the awaiting task, the returned future
+    /// resolves to `Err` with the panic payload. The panic is caught inside the
+    /// spawned task, so it never crosses the thread boundary uncaught.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { panic!("oh no") }.par().catch_unwind().await;
+    ///     assert!(res.is_err());
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn catch_unwind(self) -> CatchUnwind<Fut> {
+        CatchUnwind::new(self)
+    }
+
+    /// Cancel the spawned future if it doesn't complete within the given
+    /// duration.
+    ///
+    /// The timer starts when the future is first polled, and is driven by the
+    /// runtime's own timer. If the future completes in the same poll as the
+    /// timer expires, the output of the future takes precedence.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = async_std::task::sleep(Duration::from_secs(10)).par();
+    ///     let res = fut.timeout(Duration::from_millis(10)).await;
+    ///     assert!(res.is_err());
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn timeout(self, dur: Duration) -> Timeout<Fut> {
+        Timeout::new(self, dur)
+    }
+
+    /// Cancel the spawned future if it doesn't complete before the given
+    /// deadline.
+    ///
+    /// Unlike [`timeout`][ParallelFuture::timeout], the deadline is a fixed
+    /// point in time rather than relative to when the future is first polled.
+    /// That makes it possible to share a single deadline between many futures,
+    /// which all expire at the same moment. A deadline which has already passed
+    /// expires on the first poll, unless the future completes right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let deadline = Instant::now() + Duration::from_millis(10);
+    ///     let fut = async_std::task::sleep(Duration::from_secs(10)).par();
+    ///     let res = fut.deadline(deadline).await;
+    ///     assert!(res.is_err());
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn deadline(self, deadline: Instant) -> Timeout<Fut> {
+        Timeout::at(self, deadline)
+    }
+
+    /// Hand the parallel future back if it doesn't complete within the given
+    /// duration, rather than cancelling it.
+    ///
+    /// The returned future resolves to `Err` with the unfinished
+    /// `ParallelFuture` once the timer expires. Its task keeps running in the
+    /// background, so the caller can decide whether to keep awaiting it, or
+    /// drop it to cancel it. That's useful when the timeout is only advisory,
+    /// such as to report slow progress, and the work done so far shouldn't be
+    /// thrown away. Like with [`timeout`][ParallelFuture::timeout], the timer
+    /// starts when the future is first polled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = async {
+    ///         async_std::task::sleep(Duration::from_millis(50)).await;
+    ///         12
+    ///     }
+    ///     .par();
+    ///     let fut = match fut.try_timeout(Duration::from_millis(10)).await {
+    ///         Ok(_) => unreachable!(),
+    ///         Err(fut) => fut, // ← still running
+    ///     };
+    ///     assert_eq!(fut.await, 12);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn try_timeout(self, dur: Duration) -> TryTimeout<Fut> {
+        TryTimeout::new(self, dur)
+    }
+}
+
+impl<Fut> fmt::Debug for ParallelFuture<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("ParallelFuture");
+        f.field("into_future", &self.into_future)
+            .field("executor", &self.executor)
+            .field("config", &self.config);
+        #[cfg(feature = "std")]
+        f.field("token", &self.token).field(
+            "shutdown",
+            &self.shutdown.as_ref().map(|shutdown| shutdown.grace),
+        );
+        f.field("keep_alive", &self.keep_alive)
+            .field("handle", &self.handle)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Cancel the `ParallelFuture` when dropped, or cancel its token if it's
+/// graceful.
+#[pinned_drop]
+impl<Fut: IntoFuture> PinnedDrop for ParallelFuture<Fut> {
+    fn drop(self: Pin<&mut Self>) {
+        let mut this = self.project();
+        let handle = match this.handle.take() {
+            Some(handle) => handle,
+            None => {
+                // Unless it was detached, the future was never spawned.
+                if this.into_future.is_some() {
+                    if let Some(on_cancel) = this.on_cancel.take() {
+                        on_cancel();
+                    }
+                }
+                return;
+            }
+        };
+        let finished = handle.is_finished();
+        #[cfg(feature = "std")]
+        if let Some(token) = this.token.take() {
+            // Dropping the handle detaches the task, so it can observe the
+            // cancelled token and clean up.
+            if !finished {
+                token.cancel();
+                if let Some(shutdown) = this.shutdown.take() {
+                    if !*this.keep_alive {
+                        (shutdown.watchdog)(
+                            handle,
+                            shutdown.grace,
+                            this.executor.as_deref(),
+                            this.on_cancel.take(),
+                        );
+                    }
+                }
+                return;
+            }
+        }
+        if *this.keep_alive {
+            // Dropping the handle detaches the task.
+            return;
+        }
+        handle.cancel();
+        if !finished {
+            if let Some(on_cancel) = this.on_cancel.take() {
+                on_cancel();
+            }
+        }
+    }
+}
+
+/// A grace period, and the function which enforces it once the
+/// `ParallelFuture` has been dropped.
+///
+/// The function is instantiated by `with_shutdown`, where the output is known
+/// to be `Send`. The drop impl can't require that itself.
+#[cfg(feature = "std")]
+struct Shutdown<T> {
+    grace: Duration,
+    watchdog: Watchdog<T>,
+}
+
+/// Spawns a watchdog for a task, given its grace period, executor, and
+/// `on_cancel` closure.
+#[cfg(feature = "std")]
+type Watchdog<T> = fn(JoinHandle<T>, Duration, Option<&dyn Executor>, Option<OnCancel>);
+
+/// Spawn a detached task which cancels the given task, unless it completes
+/// within the grace period.
+#[cfg(feature = "std")]
+fn spawn_watchdog<T: Send + 'static>(
+    handle: JoinHandle<T>,
+    grace: Duration,
+    executor: Option<&dyn Executor>,
+    on_cancel: Option<OnCancel>,
+) {
+    let mut guard = CancelGuard {
+        handle: Some(handle),
+        on_cancel,
+    };
+    let watchdog = async move {
+        let mut sleep = runtime::sleep(grace);
+        let finished = core::future::poll_fn(|cx| {
+            let handle = guard.handle.as_mut().unwrap();
+            if handle.poll_finished(cx).is_ready() {
+                return Poll::Ready(true);
+            }
+            sleep.as_mut().poll(cx).map(|()| false)
+        })
+        .await;
+        if finished {
+            guard.handle.take();
+        }
+    };
+    // If the watchdog can't be spawned it's dropped right away, and so the
+    // guard cancels the task without a grace period. Otherwise the watchdog's
+    // handle is dropped, which detaches it.
+    let _ = executor::try_spawn(executor, Config::default(), watchdog);
+}
+
+/// Cancel a task when dropped, unless it has been taken out.
+#[cfg(feature = "std")]
+struct CancelGuard<T> {
+    handle: Option<JoinHandle<T>>,
+    on_cancel: Option<OnCancel>,
+}
+
+#[cfg(feature = "std")]
+impl<T> Drop for CancelGuard<T> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.cancel();
+            if let Some(on_cancel) = self.on_cancel.take() {
+                on_cancel();
+            }
+        }
+    }
+}
+
+/// A handle to a detached parallel future.
+///
+/// This type is constructed by the [`detach`][crate::ParallelFuture::detach]
+/// method on [`ParallelFuture`]. Dropping it lets the task run to completion in
+/// the background.
+#[derive(Debug)]
+pub struct DetachedHandle<T> {
+    handle: JoinHandle<T>,
+}
+
+impl<T> DetachedHandle<T> {
+    /// Returns the id of the detached task.
+    pub fn id(&self) -> TaskId {
+        self.handle.id()
+    }
+
+    /// Returns the name of the task, if one was set through
+    /// [`par_builder`][IntoFutureExt::par_builder].
+    pub fn name(&self) -> Option<&str> {
+        self.handle.name()
+    }
+
+    /// Returns `true` once the detached future has completed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let handle = async { 12 }.par().detach();
+    ///     while !handle.is_finished() {
+    ///         async_std::task::sleep(Duration::from_millis(10)).await;
+    ///     }
+    ///     assert_eq!(handle.await, 12);
+    /// })
+    /// ```
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Returns how long the detached task took from spawn to completion.
+    ///
+    /// Returns `None` until the task has completed. This requires the
+    /// `metrics` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let mut handle = async_std::task::sleep(Duration::from_millis(10))
+    ///         .par()
+    ///         .detach();
+    ///     (&mut handle).await;
+    ///     assert!(handle.elapsed().unwrap() >= Duration::from_millis(10));
+    /// })
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.handle.elapsed()
+    }
+}
+
+impl<T> Future for DetachedHandle<T> {
+    type Output = T;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.handle).poll(cx)
+    }
+}
+
+/// A type-erased parallel future.
+///
+/// This type is constructed by the [`boxed`][crate::ParallelFuture::boxed]
+/// method on [`ParallelFuture`], or by
+/// [`boxed_par`][crate::IntoFutureExt::boxed_par]. Unlike `ParallelFuture` it's
+/// `Unpin` and only generic over its output, so parallel futures of different
+/// types can be stored in the same collection. Dropping it cancels the spawned
+/// task, just like dropping a `ParallelFuture` does.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct BoxParallelFuture<T> {
+    inner: Pin<Box<dyn Future<Output = T> + Send>>,
+}
+
+impl<T> BoxParallelFuture<T> {
+    fn new<Fut>(fut: Fut) -> Self
+    where
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        Self {
+            inner: Box::pin(fut),
+        }
+    }
+}
+
+impl<T> Future for BoxParallelFuture<T> {
+    type Output = T;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl<T> fmt::Debug for BoxParallelFuture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxParallelFuture").finish_non_exhaustive()
+    }
+}
+
+/// Run a blocking closure on the runtime's blocking thread pool.
+///
+/// The returned `ParallelFuture` behaves like any other: it starts lazily, can
+/// be joined with other parallel futures, and is cancelled when dropped. A
+/// closure which has already started running can't be interrupted however;
+/// cancelling it only discards its output.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use futures_concurrency::prelude::*;
+///
+/// parallel_future::par_block_on(async {
+///     let a = parallel_future::par_blocking(|| (0..100).sum::<u32>());
+///     let b = async { 50 }.par();
+///
+///     let (a, b) = (a, b).join().await;
+///     assert_eq!(a + b, 5000);
+/// })
+/// ```
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_blocking<F, T>(f: F) -> ParallelFuture<Blocking<F, T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Blocking::new(f).par()
+}
+
+/// Create a parallel future from a closure which is called inside the spawned
+/// task.
+///
+/// Rather than creating the future up front and converting it with
+/// [`par`][IntoFutureExt::par], the closure is moved into the task and called
+/// there on its first poll. That's convenient for calling async functions
+/// without wrapping them in an `async` block, and means the future is created
+/// in the context of the task: anything it looks up on creation, such as
+/// thread-locals or the current runtime, belongs to the worker thread it runs
+/// on. The closure and the future it returns still have to be `Send`, since
+/// the task may move between threads.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::spawn_fn;
+///
+/// async fn double(n: u32) -> u32 {
+///     n * 2
+/// }
+///
+/// parallel_future::par_block_on(async {
+///     let res = spawn_fn(|| double(6)).await;
+///     assert_eq!(res, 12);
+/// })
+/// ```
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn spawn_fn<F, Fut>(f: F) -> ParallelFuture<SpawnFn<F, Fut>>
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: IntoFuture + 'static,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    SpawnFn::new(f).par()
+}
+
+/// Create a parallel future from a closure which is called with an argument
+/// inside the spawned task.
+///
+/// This works like [`spawn_fn`], except that `arg` is moved into the task and
+/// passed to the closure by value. That saves moving the argument into a
+/// closure by hand, and matches the shape of async closures which take an
+/// argument. The argument, the closure, and the future it returns have to be
+/// `Send`.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::spawn_with;
+///
+/// async fn total(numbers: Vec<u32>) -> u32 {
+///     numbers.iter().sum()
+/// }
+///
+/// parallel_future::par_block_on(async {
+///     let res = spawn_with(vec![1, 2, 3], total).await;
+///     assert_eq!(res, 6);
+/// })
+/// ```
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn spawn_with<A, F, Fut>(arg: A, f: F) -> ParallelFuture<SpawnWith<A, F, Fut>>
+where
+    A: Send + 'static,
+    F: FnOnce(A) -> Fut + Send + 'static,
+    Fut: IntoFuture + 'static,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    SpawnWith::new(arg, f).par()
+}
+
+/// Create a parallel future which is cancelled cooperatively through a
+/// [`CancellationToken`].
+///
+/// The closure is passed a token, and returns the future to spawn. When the
+/// returned `ParallelFuture` is dropped before completing, the token is
+/// cancelled rather than the spawned future. That lets the future finish its
+/// current work and clean up before returning. See
+/// [`graceful`][ParallelFuture::graceful] for more details.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_with_token;
+///
+/// parallel_future::par_block_on(async {
+///     let fut = par_with_token(|token| async move {
+///         let mut count = 0;
+///         while !token.is_cancelled() && count < 10 {
+///             count += 1;
+///         }
+///         count
+///     });
+///     assert_eq!(fut.await, 10);
+/// })
+/// ```
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_with_token<F, Fut>(f: F) -> ParallelFuture<Fut>
+where
+    F: FnOnce(CancellationToken) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let token = CancellationToken::new();
+    f(token.clone()).par().graceful(token)
+}
+
+/// Spawn a future in parallel, and block the current thread until it
+/// completes.
+///
+/// This bridges synchronous code, like `main` or a test helper, into parallel
+/// async work. The future is spawned as a task with
+/// [`par`][IntoFutureExt::par], and the current thread blocks on its output.
+///
+/// # Runtimes
+///
+/// No runtime needs to be running beforehand. async-std and smol start their
+/// global worker threads on first use, and keep them around afterwards. With
+/// Tokio a new multi-threaded runtime is started for the duration of the call,
+/// and shut down once the future completes, cancelling any tasks it left
+/// behind.
+///
+/// # Panics
+///
+/// This must not be called from within an async context. With Tokio it panics
+/// when called from within a Tokio runtime; with the other runtimes it blocks
+/// the worker thread it's called from. If the future panics, the panic is
+/// resumed on the calling thread.
+///
+/// # Examples
+///
+/// ```
+/// let res = parallel_future::par_block_on(async { 1 + 1 }); // ← from sync code
+/// assert_eq!(res, 2);
+/// ```
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_block_on<Fut>(fut: Fut) -> Fut::Output
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    runtime::block_on_parallel(fut.par())
+}
+
+/// Convert a boxed trait-object future into a parallelizable future.
+///
+/// Boxed futures can be converted with [`par`][IntoFutureExt::par] like any
+/// other future. Because this function takes the trait object directly,
+/// `Box::pin`ned futures of different types coerce to the same type when
+/// passed to it, so they can be stored together without spelling out the
+/// `dyn Future` type.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_boxed;
+/// use futures_concurrency::prelude::*;
+///
+/// let futs = vec![
+///     par_boxed(Box::pin(async { 1 })),
+///     par_boxed(Box::pin(async { 1 + 1 })), // ← a different future type
+/// ];
+/// assert_eq!(parallel_future::par_block_on(futs.join()), vec![1, 2]);
+/// ```
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_boxed<T>(
+    fut: Pin<Box<dyn Future<Output = T> + Send>>,
+) -> ParallelFuture<Pin<Box<dyn Future<Output = T> + Send>>>
+where
+    T: Send + 'static,
+{
+    fut.par()
+}
+
+/// Extend the `Future` trait.
+pub trait IntoFutureExt: IntoFuture + Sized
+where
+    <Self as IntoFuture>::IntoFuture: Send + 'static,
+    <Self as IntoFuture>::Output: Send + 'static,
+{
+    /// Convert this future into a parallelizable future.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use futures_concurrency::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let a = async { 1 }.par();        // ← returns `ParallelFuture`
+    ///     let b = async { 2 }.par();        // ← returns `ParallelFuture`
+    ///
+    ///     let (a, b) = (a, b).join().await; // ← concurrent `.await`
+    ///     assert_eq!(a + b, 3);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par(self) -> ParallelFuture<Self> {
+        ParallelFuture::new(self, None, Config::default())
+    }
+
+    /// Convert this future into a parallelizable future which is spawned on
+    /// the given executor.
+    ///
+    /// # Examples
+    ///
+    /// Spawning on the `AsyncStd` executor requires the `async-std` feature.
+    #[cfg_attr(feature = "async-std", doc = "```")]
+    #[cfg_attr(not(feature = "async-std"), doc = "```ignore")]
+    /// use parallel_future::prelude::*;
+    /// use parallel_future::executor::AsyncStd;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { 12 }.par_on(&AsyncStd).await;
+    ///     assert_eq!(res, 12);
+    /// })
+    /// ```
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_on<E>(self, executor: &E) -> ParallelFuture<Self>
+    where
+        E: Executor + Clone + 'static,
+    {
+        let executor: Arc<dyn Executor> = Arc::new(executor.clone());
+        ParallelFuture::new(self, Some(executor), Config::default())
+    }
+
+    /// Configure the task this future is spawned as, before converting it into
+    /// a parallelizable future.
+    ///
+    /// The builder is finished with [`build`][Builder::build], which returns a
+    /// `ParallelFuture` like [`par`][IntoFutureExt::par] does. It can also be
+    /// `.await`ed directly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = async { 12 }
+    ///         .par_builder()
+    ///         .name("chashu")
+    ///         .stack_size(4 * 1024 * 1024)
+    ///         .build();
+    ///     assert_eq!(fut.await, 12);
+    /// })
+    /// ```
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_builder(self) -> Builder<Self> {
+        Builder::new(self)
+    }
+
+    /// Convert this future into a parallelizable future which preferably runs
+    /// on the thread which spawned it.
+    ///
+    /// This is a shorthand for `fut.par_builder().prefer_local().build()`. See
+    /// [`Builder::prefer_local`] for how each runtime treats it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { 12 }.par_prefer_local().await;
+    ///     assert_eq!(res, 12);
+    /// })
+    /// ```
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_prefer_local(self) -> ParallelFuture<Self> {
+        self.par_builder().prefer_local().build()
+    }
+
+    /// Convert this future into a parallelizable future, and spawn it right
+    /// away.
+    ///
+    /// Futures returned by [`par`][IntoFutureExt::par] are lazy: they're only
+    /// spawned once they're first polled, which means they can be passed to
+    /// combinators or dropped without ever running. This instead spawns the
+    /// future before returning, so it makes progress while the caller does
+    /// other work before awaiting it. Everything else works the same: the
+    /// returned `ParallelFuture` is awaited like any other, and dropping it
+    /// cancels the spawned future. Options which only apply when spawning,
+    /// like the ones set through [`par_builder`][IntoFutureExt::par_builder],
+    /// can't be changed afterwards.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = async { 12 }.par_eager(); // ← starts running now
+    ///     let setup = 1 + 1;                  // ← overlaps with the future
+    ///     assert_eq!(fut.await + setup, 14);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_eager(self) -> ParallelFuture<Self> {
+        let mut fut = self.par();
+        fut.start();
+        fut
+    }
+
+    /// Convert this future into a parallelizable future and spawn it right
+    /// away, returning an error if the runtime can't spawn tasks.
+    ///
+    /// Unlike [`par`][IntoFutureExt::par] the future isn't lazy: it's spawned
+    /// before this returns, so a runtime which has been shut down is reported
+    /// here rather than by panicking on the first poll. See
+    /// [`ParallelFuture::try_spawn`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut = async { 12 }.try_par().expect("the runtime is running");
+    ///     assert_eq!(fut.await, 12);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn try_par(self) -> Result<ParallelFuture<Self>, SpawnError> {
+        let mut fut = self.par();
+        fut.try_spawn()?;
+        Ok(fut)
+    }
+
+    /// Convert this future into a type-erased parallelizable future.
+    ///
+    /// This is a shorthand for `fut.par().boxed()`. See
+    /// [`ParallelFuture::boxed`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use parallel_future::BoxParallelFuture;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let fut: BoxParallelFuture<u8> = async { 12 }.boxed_par();
+    ///     assert_eq!(fut.await, 12);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn boxed_par(self) -> BoxParallelFuture<Self::Output>
+    where
+        Self: Send + 'static,
+    {
+        self.par().boxed()
+    }
+
+    /// Convert this future into a parallelizable future which is cancelled if
+    /// it doesn't complete within the given duration.
+    ///
+    /// This is a shorthand for `fut.par().timeout(dur)`. See
+    /// [`ParallelFuture::timeout`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { 12 }.par_timeout(Duration::from_secs(1)).await;
+    ///     assert_eq!(res.unwrap(), 12);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_timeout(self, dur: Duration) -> Timeout<Self> {
+        self.par().cancel_on_drop().timeout(dur)
+    }
+
+    /// Convert this future into a parallelizable future which is cancelled if
+    /// it doesn't complete before the given deadline.
+    ///
+    /// This is a shorthand for `fut.par().deadline(when)`. See
+    /// [`ParallelFuture::deadline`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let deadline = Instant::now() + Duration::from_secs(1);
+    ///     let res = async { 12 }.par_deadline(deadline).await;
+    ///     assert_eq!(res.unwrap(), 12);
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_deadline(self, when: Instant) -> Timeout<Self> {
+        self.par().cancel_on_drop().deadline(when)
+    }
+
+    /// Convert this future into a parallelizable future which is handed back
+    /// if it doesn't complete within the given duration.
+    ///
+    /// This is a shorthand for `fut.par().try_timeout(dur)`. See
+    /// [`ParallelFuture::try_timeout`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let res = async { 12 }.try_par_timeout(Duration::from_secs(1)).await;
+    ///     assert_eq!(res.ok(), Some(12));
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn try_par_timeout(self, dur: Duration) -> TryTimeout<Self> {
+        self.par().try_timeout(dur)
+    }
+
+    /// Convert this future into a parallelizable future which can be aborted
+    /// through the returned [`AbortHandle`].
+    ///
+    /// Aborting the future cancels the spawned task at its next await point,
+    /// and makes the parallel future resolve to `Err(Aborted)`. Unlike
+    /// dropping the `ParallelFuture`, this can be done from anywhere the
+    /// handle is shared with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let (fut, handle) = async_std::task::sleep(Duration::from_secs(10)).par_with_abort();
+    ///     handle.abort();
+    ///     assert!(fut.await.is_err());
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_with_abort(self) -> (ParallelFuture<Abortable<Self::IntoFuture>>, AbortHandle) {
+        let handle = AbortHandle::new();
+        let fut = Abortable::new(self.into_future(), handle.clone());
+        (fut.par(), handle)
+    }
+
+    /// Spawn this future and another in parallel, and pair their outputs.
+    ///
+    /// This is a shorthand for `(a.par(), b.par()).join()`, without needing a
+    /// library of concurrency combinators. Both futures are spawned when the
+    /// returned future is first polled, and dropping it cancels both.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let (a, b) = async { 1 }.par_zip(async { "two" }).await;
+    ///     assert_eq!((a, b), (1, "two"));
+    /// })
+    /// ```
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "debug", track_caller)]
+    fn par_zip<B>(self, other: B) -> Zip<Self, B>
+    where
+        B: IntoFuture,
+        B::IntoFuture: Send + 'static,
+        B::Output: Send + 'static,
+    {
+        Zip::new(self.par().cancel_on_drop(), other.par().cancel_on_drop())
+    }
+}
+
+impl<Fut> IntoFutureExt for Fut
+where
+    Fut: IntoFuture,
+    <Fut as IntoFuture>::IntoFuture: Send + 'static,
+    <Fut as IntoFuture>::Output: Send + 'static,
+{
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::{
+        future::{self, Future},
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Waker},
+        time::{Duration, Instant},
+    };
+
+    use futures_concurrency::prelude::*;
+
+    use super::prelude::*;
+    use super::runtime;
+    use crate::testing::{drop_signal, TestExecutor};
+
+    #[test]
+    fn spawn() {
+        runtime::block_on(async {
+            let res = async { "nori is a horse" }.par().await;
+            assert_eq!(res, "nori is a horse");
+        })
+    }
+
+    #[test]
+    fn is_lazy() {
+        let executor = TestExecutor::new();
+        let polled = Arc::new(Mutex::new(false));
+        let polled_2 = polled.clone();
+        let _res = async move {
+            *polled_2.lock().unwrap() = true;
+        }
+        .par_on(&executor);
+
+        executor.run_until_stalled();
+        assert_eq!(executor.pending(), 0);
+        assert!(!*polled.lock().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "oh no")]
+    fn propagates_panics() {
+        runtime::block_on(async {
+            async { panic!("oh no") }.par().await;
+        })
+    }
+
+    #[test]
+    fn catch_unwind() {
+        runtime::block_on(async {
+            let err = async { panic!("oh no") }.par().catch_unwind().await;
+            assert_eq!(*err.unwrap_err().downcast::<&str>().unwrap(), "oh no");
+        })
+    }
+
+    #[test]
+    fn timeout_cancels() {
+        runtime::block_on(async {
+            let (guard, dropped) = drop_signal();
+            let res = async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            }
+            .par_timeout(Duration::from_millis(10))
+            .await;
+            assert!(res.is_err());
+            dropped.await;
+        })
+    }
+
+    #[test]
+    fn try_timeout() {
+        runtime::block_on(async {
+            let (release, released) = drop_signal();
+            let res = async move {
+                released.await;
+                12
+            }
+            .try_par_timeout(Duration::from_millis(10))
+            .await;
+            let Err(fut) = res else {
+                panic!("the future completed before the timeout");
+            };
+
+            // The task keeps running while it's handed back.
+            drop(release);
+            assert_eq!(fut.await, 12);
+        })
+    }
+
+    #[test]
+    fn deadline() {
+        runtime::block_on(async {
+            let deadline = Instant::now() + Duration::from_millis(50);
+            let slow = runtime::sleep(Duration::from_secs(10)).par_deadline(deadline);
+            let fast = async { 12 }.par_deadline(deadline);
+            let (slow, fast) = (slow, fast).join().await;
+            assert!(slow.is_err());
+            assert_eq!(fast.unwrap(), 12);
+            assert!(Instant::now() >= deadline);
+
+            // A deadline which has already passed expires right away.
+            let res = runtime::sleep(Duration::from_secs(10))
+                .par_deadline(deadline)
+                .await;
+            assert!(res.is_err());
+        })
+    }
+
+    #[test]
+    fn par_blocking() {
+        runtime::block_on(async {
+            let res = super::par_blocking(|| "nori is a horse").await;
+            assert_eq!(res, "nori is a horse");
+        })
+    }
+
+    #[test]
+    fn boxed_par() {
+        runtime::block_on(async {
+            let futs: Vec<Pin<Box<dyn Future<Output = u32> + Send>>> = vec![
+                Box::pin(async { 1 }),
+                Box::pin(async {
+                    runtime::sleep(Duration::from_millis(10)).await;
+                    2
+                }),
+            ];
+            let futs: Vec<_> = futs.into_iter().map(|fut| fut.par()).collect();
+            assert_eq!(futs.join().await, vec![1, 2]);
+
+            let fut = crate::par_boxed(Box::pin(async { 3 }));
+            assert_eq!(fut.await, 3);
+        })
+    }
+
+    #[test]
+    fn spawn_with() {
+        runtime::block_on(async {
+            let name = String::from("nori");
+            let res = crate::spawn_with(name, |name| async move { name.len() }).await;
+            assert_eq!(res, 4);
+        })
+    }
+
+    #[test]
+    fn spawn_fn() {
+        runtime::block_on(async {
+            let called = Arc::new(Mutex::new(false));
+            let called_2 = called.clone();
+            let fut = crate::spawn_fn(move || {
+                *called_2.lock().unwrap() = true;
+                future::ready(12)
+            });
+
+            // The closure isn't called until the task is spawned.
+            runtime::sleep(Duration::from_millis(10)).await;
+            assert!(!*called.lock().unwrap());
+            assert_eq!(fut.await, 12);
+            assert!(*called.lock().unwrap());
+        })
+    }
+
+    #[test]
+    fn par_block_on() {
+        let res = crate::par_block_on(async {
+            let a = async { 1 }.par();
+            let b = async { 2 }.par();
+            a.await + b.await
+        });
+        assert_eq!(res, 3);
+    }
+
+    #[test]
+    fn detach() {
+        runtime::block_on(async {
+            let polled = Arc::new(Mutex::new(false));
+            let polled_2 = polled.clone();
+            let (release, released) = drop_signal();
+            let (guard, dropped) = drop_signal();
+            let handle = async move {
+                let _guard = guard;
+                released.await;
+                *polled_2.lock().unwrap() = true;
+            }
+            .par()
+            .detach();
+            drop(handle);
+
+            drop(release);
+            dropped.await;
+            assert!(*polled.lock().unwrap());
+        })
+    }
+
+    #[test]
+    fn keep_alive_on_drop() {
+        runtime::block_on(async {
+            let polled = Arc::new(Mutex::new(false));
+            let polled_2 = polled.clone();
+            let (release, released) = drop_signal();
+            let (guard, dropped) = drop_signal();
+            let mut fut = async move {
+                let _guard = guard;
+                released.await;
+                *polled_2.lock().unwrap() = true;
+            }
+            .par()
+            .keep_alive_on_drop();
+            fut.start();
+            drop(fut);
+
+            drop(release);
+            dropped.await;
+            assert!(*polled.lock().unwrap());
+        })
+    }
+
+    #[test]
+    fn cancel_on_drop() {
+        runtime::block_on(async {
+            let (guard, dropped) = drop_signal();
+            let mut fut = async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            }
+            .par()
+            .keep_alive_on_drop()
+            .cancel_on_drop();
+            fut.start();
+            drop(fut);
+            dropped.await;
+        })
+    }
+
+    #[test]
+    fn on_cancel() {
+        runtime::block_on(async {
+            let cancelled = Arc::new(Mutex::new(0));
+            let on_cancel = |cancelled: &Arc<Mutex<usize>>| {
+                let cancelled = cancelled.clone();
+                move || *cancelled.lock().unwrap() += 1
+            };
+
+            // Not called when the future completes.
+            let res = async { 12 }.par().on_cancel(on_cancel(&cancelled)).await;
+            assert_eq!(res, 12);
+            let mut fut = async { 12 }.par().on_cancel(on_cancel(&cancelled));
+            fut.start();
+            while !fut.is_finished() {
+                crate::yield_now().await;
+            }
+            drop(fut);
+            assert_eq!(*cancelled.lock().unwrap(), 0);
+
+            // Called exactly once when a running future is cancelled.
+            let mut fut = future::pending::<()>()
+                .par()
+                .cancel_on_drop()
+                .on_cancel(on_cancel(&cancelled));
+            fut.start();
+            drop(fut);
+            assert_eq!(*cancelled.lock().unwrap(), 1);
+        })
+    }
+
+    #[test]
+    #[cfg(not(feature = "detach-on-drop"))]
+    fn race_cancels_loser() {
+        runtime::block_on(async {
+            let (started, is_started) = drop_signal();
+            let (guard, dropped) = drop_signal();
+            let loser = async move {
+                let _guard = guard;
+                drop(started);
+                future::pending::<()>().await;
+            }
+            .par();
+            let winner = is_started.par();
+            (loser, winner).race().await;
+            dropped.await;
+        })
+    }
+
+    #[test]
+    fn map() {
+        runtime::block_on(async {
+            let res = async { "nori" }.par().map(|s| s.len()).await;
+            assert_eq!(res, 4);
+        })
+    }
+
+    #[test]
+    fn map_err() {
+        runtime::block_on(async {
+            let res = async { Err::<(), _>("nori") }
+                .par()
+                .map_err(|s| s.len())
+                .map(|res| res.unwrap_err() + 1)
+                .await;
+            assert_eq!(res, 5);
+
+            // Dropping the future cancels the spawned future.
+            let (guard, dropped) = drop_signal();
+            let mut fut = Box::pin(
+                async move {
+                    let _guard = guard;
+                    future::pending::<Result<(), ()>>().await
+                }
+                .par()
+                .cancel_on_drop()
+                .map_err(|()| "error"),
+            );
+            let poll = fut.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+            dropped.await;
+        })
+    }
+
+    #[test]
+    fn then() {
+        runtime::block_on(async {
+            let res = async { "nori" }
+                .par()
+                .then(|s| async move { s.len() })
+                .await;
+            assert_eq!(res, 4);
+
+            // Dropping the future cancels the continuation.
+            let (started, is_started) = drop_signal();
+            let (guard, dropped) = drop_signal();
+            async {}
+                .par()
+                .then(|()| async move {
+                    let _guard = guard;
+                    drop(started);
+                    future::pending::<()>().await;
+                })
+                .race(is_started)
+                .await;
+            dropped.await;
+        })
+    }
+
+    #[test]
+    fn chained_combinators() {
+        runtime::block_on(async {
+            let res = async { "nori" }
+                .par()
+                .map(|s| s.len())
+                .inspect(|n| assert_eq!(*n, 4))
+                .then(|n| async move { n * 2 })
+                .map(|n| n + 1)
+                .await;
+            assert_eq!(res, 9);
+
+            // Dropping the chain while the spawned future runs cancels it.
+            let (guard, dropped) = drop_signal();
+            let mut fut = Box::pin(
+                async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                }
+                .par()
+                .cancel_on_drop()
+                .map(|()| ())
+                .then(|()| async {}),
+            );
+            let poll = fut.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+            dropped.await;
+
+            // Dropping the chain during the continuation cancels that instead.
+            let (started, is_started) = drop_signal();
+            let (guard, dropped) = drop_signal();
+            async {}
+                .par()
+                .map(|()| (guard, started))
+                .then(|(guard, started)| async move {
+                    let _guard = guard;
+                    drop(started);
+                    future::pending::<()>().await;
+                })
+                .map(|()| ())
+                .race(is_started)
+                .await;
+            dropped.await;
+        })
+    }
+
+    #[test]
+    fn flatten() {
+        runtime::block_on(async {
+            let (started, is_started) = drop_signal();
+            let (guard, dropped) = drop_signal();
+            let inner = async move {
+                let _guard = guard;
+                drop(started);
+                future::pending::<()>().await;
+            }
+            .par()
+            .cancel_on_drop();
+            let fut = future::ready(inner).par().flatten();
+
+            // Cancelling the second stage cancels the inner parallel future.
+            fut.race(is_started).await;
+            dropped.await;
+
+            let inner = async { "nori" }.par();
+            let res = future::ready(inner).par().flatten().await;
+            assert_eq!(res, "nori");
+        })
+    }
+
+    #[test]
+    fn inspect() {
+        runtime::block_on(async {
+            let seen = Arc::new(Mutex::new(None));
+            let seen_2 = seen.clone();
+            let res = async { 12 }
+                .par()
+                .inspect(move |n| *seen_2.lock().unwrap() = Some(*n))
+                .await;
+            assert_eq!(res, 12);
+            assert_eq!(*seen.lock().unwrap(), Some(12));
+
+            // The closure isn't called for cancelled futures.
+            let seen_2 = seen.clone();
+            let (guard, dropped) = drop_signal();
+            let mut fut = Box::pin(
+                async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                }
+                .par()
+                .cancel_on_drop()
+                .inspect(move |_| *seen_2.lock().unwrap() = None),
+            );
+            let poll = fut.as_mut().poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+            dropped.await;
+            assert_eq!(*seen.lock().unwrap(), Some(12));
+        })
+    }
+
+    #[test]
+    fn name() {
+        runtime::block_on(async {
+            let fut = async { 12 }.par_builder().name("chashu").build();
+            assert_eq!(fut.name(), Some("chashu"));
+            let handle = fut.detach();
+            assert_eq!(handle.name(), Some("chashu"));
+            assert_eq!(handle.await, 12);
+            assert_eq!(async { 12 }.par().name(), None);
+        })
+    }
+
+    #[test]
+    fn id() {
+        runtime::block_on(async {
+            let fut = async { 12 }.par();
+            assert_eq!(fut.id(), None);
+            let a = fut.detach();
+            let b = async { 12 }.par().detach();
+            assert!(a.id() < b.id());
+            assert_eq!(a.await, 12);
+        })
+    }
+
+    #[test]
+    fn on_panic() {
+        use crate::executor::PanicPolicy;
+
+        runtime::block_on(async {
+            let res = async { panic!("oh no") }.par_builder().catch_panics().await;
+            assert!(res.is_err());
+            assert_eq!(
+                async { 12 }.par_builder().catch_panics().await.ok(),
+                Some(12)
+            );
+        });
+
+        // Aborting takes down the process, so that runs in a child process
+        // which re-runs just this test.
+        if std::env::var_os("PARALLEL_FUTURE_ABORT").is_some() {
+            runtime::block_on(async {
+                let fut = async { panic!("oh no") }
+                    .par_builder()
+                    .on_panic(PanicPolicy::Abort)
+                    .build();
+                drop(fut.detach());
+                runtime::sleep(Duration::from_secs(5)).await;
+            });
+            return;
+        }
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "test::on_panic", "--nocapture"])
+            .env("PARALLEL_FUTURE_ABORT", "1")
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+    }
+
+    #[test]
+    fn stagger() {
+        runtime::block_on(async {
+            let begin = Instant::now();
+            let futs: Vec<_> = (0..20)
+                .map(|_| {
+                    async move { begin.elapsed() }
+                        .par_builder()
+                        .stagger(Duration::from_millis(200))
+                        .build()
+                })
+                .collect();
+            let mut started = futs.join().await;
+            started.sort();
+
+            // The tasks don't all start at once. How far apart they are depends
+            // on the scheduler, so that's left to the `jitter` test.
+            assert_ne!(started[0], started[19]);
+        })
+    }
+
+    #[test]
+    fn builder_into_parallel_future() {
+        runtime::block_on(async {
+            let fut: crate::ParallelFuture<_> = async { 12 }.par_builder().name("nori").into();
+            assert_eq!(fut.name(), Some("nori"));
+            let handle = fut.detach();
+            assert_eq!(handle.name(), Some("nori"));
+            assert_eq!(handle.await, 12);
+        })
+    }
+
+    #[test]
+    fn await_builder() {
+        runtime::block_on(async {
+            let res = async { "nori" }.par_builder().name("nori").await;
+            assert_eq!(res, "nori");
+        })
+    }
+
+    #[test]
+    fn is_finished() {
+        runtime::block_on(async {
+            let fut = async { 12 }.par();
+            assert!(!fut.has_started());
+            assert!(!fut.is_finished());
+
+            let (release, released) = drop_signal();
+            let handle = async move {
+                released.await;
+                12
+            }
+            .par()
+            .detach();
+            assert!(!handle.is_finished());
+
+            drop(release);
+            while !handle.is_finished() {
+                crate::yield_now().await;
+            }
+            assert_eq!(handle.await, 12);
+        })
+    }
+
+    #[test]
+    fn with_shutdown() {
+        runtime::block_on(async {
+            let completed = Arc::new(Mutex::new(Vec::new()));
+
+            // A future which cleans up within the grace period completes.
+            let completed_2 = completed.clone();
+            let (guard, dropped) = drop_signal();
+            let mut fut = crate::par_with_token(|token| async move {
+                let _guard = guard;
+                token.cancelled().await;
+                completed_2.lock().unwrap().push("cleaned up");
+            })
+            .with_shutdown(Duration::from_secs(10));
+            fut.start();
+            drop(fut);
+            dropped.await;
+            assert_eq!(*completed.lock().unwrap(), ["cleaned up"]);
+
+            // A future which ignores its token is cancelled after it.
+            #[cfg(not(feature = "detach-on-drop"))]
+            {
+                let completed_2 = completed.clone();
+                let (guard, dropped) = drop_signal();
+                let (cancel_guard, on_cancelled) = drop_signal();
+                let on_cancel = {
+                    let completed = completed.clone();
+                    move || {
+                        completed.lock().unwrap().push("cancelled");
+                        drop(cancel_guard);
+                    }
+                };
+                let mut fut = crate::par_with_token(|_token| async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                    completed_2.lock().unwrap().push("ignored token");
+                })
+                .with_shutdown(Duration::from_millis(50))
+                .on_cancel(on_cancel);
+                fut.start();
+                drop(fut);
+                assert_eq!(*completed.lock().unwrap(), ["cleaned up"]);
+
+                on_cancelled.await;
+                dropped.await;
+                assert_eq!(*completed.lock().unwrap(), ["cleaned up", "cancelled"]);
+            }
+        })
+    }
+
+    #[test]
+    fn poll_unpin() {
+        runtime::block_on(async {
+            let mut fut = async { 12 }.par();
+            let res = future::poll_fn(|cx| fut.poll_unpin(cx)).await;
+            assert_eq!(res, 12);
+
+            // Awaiting by reference keeps ownership with the caller.
+            let mut fut = async { 12 }.par();
+            assert_eq!((&mut fut).await, 12);
+            assert!(fut.is_finished());
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "polled after completion")]
+    fn poll_after_completion() {
+        runtime::block_on(async {
+            let mut fut = async { 12 }.par();
+            assert_eq!((&mut fut).await, 12);
+            let _ = future::poll_fn(|cx| std::task::Poll::Ready(fut.poll_unpin(cx))).await;
+        })
+    }
+
+    #[test]
+    fn buffered() {
+        runtime::block_on(async {
+            let count = Arc::new(Mutex::new(0));
+            let count_2 = count.clone();
+            let mut fut = async move {
+                *count_2.lock().unwrap() += 1;
+                12
+            }
+            .par()
+            .buffered();
+            assert_eq!(fut.get(), None);
+            assert_eq!((&mut fut).await, 12);
+            assert_eq!((&mut fut).await, 12);
+            assert_eq!(fut.await, 12);
+            assert_eq!(*count.lock().unwrap(), 1);
+        })
+    }
+
+    #[test]
+    fn nesting() {
+        // A single-threaded executor only makes progress on the nested futures
+        // if awaiting them suspends the outer task rather than blocking it.
+        let executor = TestExecutor::new();
+        let (inner, innermost) = (executor.clone(), executor.clone());
+        let res = async move {
+            let fut = async move { async { 1 }.par_on(&innermost).await + 1 };
+            fut.par_on(&inner).await + 1
+        }
+        .par_on(&executor);
+        assert_eq!(executor.block_on(res), 3);
+        assert_eq!(executor.pending(), 0);
+    }
+
+    #[test]
+    fn par_eager() {
+        runtime::block_on(async {
+            let (guard, dropped) = drop_signal();
+            let fut = async move {
+                let _guard = guard;
+                12
+            }
+            .par_eager();
+
+            // The future runs before it's awaited.
+            dropped.await;
+            assert!(fut.is_finished());
+            assert_eq!(fut.await, 12);
+
+            // Dropping it still cancels the future.
+            #[cfg(not(feature = "detach-on-drop"))]
+            {
+                let fut = future::pending::<()>().par_eager();
+                crate::testing::assert_cancelled(fut, Duration::from_secs(1)).await;
+            }
+        })
+    }
+
+    #[test]
+    fn par_zip() {
+        runtime::block_on(async {
+            let slow = async {
+                crate::yield_now().await;
+                "nori"
+            };
+            assert_eq!(slow.par_zip(async { 12 }).await, ("nori", 12));
+
+            // Dropping it cancels both futures.
+            let task = |guard| async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            };
+            let (guard_1, dropped_1) = drop_signal();
+            let (guard_2, dropped_2) = drop_signal();
+            let mut fut = task(guard_1).par_zip(task(guard_2));
+            let poll = Pin::new(&mut fut).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+            (dropped_1, dropped_2).join().await;
+        })
+    }
+
+    #[test]
+    fn into_inner_handle() {
+        runtime::block_on(async {
+            let handle = async { 12 }.par().into_inner_handle();
+            #[cfg(not(feature = "tokio"))]
+            assert_eq!(handle.await, 12);
+            #[cfg(feature = "tokio")]
+            assert_eq!(handle.await.unwrap(), 12);
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "after the future was spawned")]
+    fn into_inner_handle_after_spawn() {
+        runtime::block_on(async {
+            drop(async { 12 }.par_eager().into_inner_handle());
+        })
+    }
+
+    #[test]
+    fn boxed_handle() {
+        runtime::block_on(async {
+            let (guard, dropped) = drop_signal();
+            let futs: Vec<super::BoxParallelFuture<usize>> = vec![
+                async { 1 }.boxed_par(),
+                future::ready(2).par().boxed(),
+                async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                    3
+                }
+                .par()
+                .cancel_on_drop()
+                .boxed(),
+            ];
+            let mut futs = futs.into_iter();
+            assert_eq!(futs.next().unwrap().await, 1);
+            assert_eq!(Pin::new(&mut futs.next().unwrap()).await, 2);
+
+            // Dropping the boxed future cancels the spawned future.
+            let mut fut = futs.next().unwrap();
+            let poll = Pin::new(&mut fut).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+            dropped.await;
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn elapsed() {
+        runtime::block_on(async {
+            let fut = runtime::sleep(Duration::from_millis(20)).par();
+            assert_eq!(fut.elapsed(), None);
+
+            // The clock starts when the future is spawned, not when it's created.
+            // The upper bound leaves the scheduler plenty of slack on busy
+            // machines.
+            runtime::sleep(Duration::from_secs(1)).await;
+            let mut handle = fut.detach();
+            assert_eq!(handle.elapsed(), None);
+            (&mut handle).await;
+            let elapsed = handle.elapsed().unwrap();
+            assert!(elapsed >= Duration::from_millis(20));
+            assert!(elapsed < Duration::from_secs(1));
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn location() {
+        let line = line!();
+        let fut = async { 12 }.par();
+        let location = fut.location().unwrap();
+        assert_eq!((location.file(), location.line()), (file!(), line + 1));
+
+        let line = line!();
+        let fut = async { 12 }.par_builder().name("nori").build();
+        assert_eq!(fut.location().unwrap().line(), line + 1);
     }
 }
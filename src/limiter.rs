@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll, Waker};
+
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// Limit how many parallel futures run at the same time.
+///
+/// Futures created through [`par`][Limiter::par] only spawn once a permit is
+/// available, and release their permit when they complete or are cancelled.
+/// Permits are handed out in the order futures started waiting for them.
+///
+/// Futures created through [`par_weighted`][Limiter::par_weighted] take up more
+/// than one permit, for work which is heavier than others.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::Limiter;
+/// use futures_concurrency::prelude::*;
+///
+/// parallel_future::par_block_on(async {
+///     let limiter = Limiter::new(2);
+///     let futs: Vec<_> = (0..8).map(|n| limiter.par(async move { n })).collect();
+///     let outputs = futs.join().await; // ← at most 2 futures run at once
+///     assert_eq!(outputs.into_iter().sum::<i32>(), 28);
+/// })
+/// ```
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    semaphore: Arc<Semaphore>,
+}
+
+impl Limiter {
+    /// Create a new limiter which allows up to `max` futures to run at the
+    /// same time.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `max` is zero.
+    pub fn new(max: usize) -> Self {
+        assert!(max > 0, "the limit must be greater than zero");
+        Self {
+            semaphore: Arc::new(Semaphore {
+                state: Mutex::new(State {
+                    max,
+                    available: max,
+                    waiters: VecDeque::new(),
+                    next_id: 0,
+                }),
+            }),
+        }
+    }
+
+    /// Convert a future into a parallelizable future which only spawns once
+    /// the limiter has a permit available.
+    ///
+    /// Like [`par`][crate::IntoFutureExt::par], the permit is only requested
+    /// once the returned future is first polled.
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn par<Fut>(&self, fut: Fut) -> Limited<Fut>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        self.par_weighted(fut, 1)
+    }
+
+    /// Convert a future into a parallelizable future which only spawns once
+    /// the limiter has `weight` permits available.
+    ///
+    /// The future holds on to all of its permits until it completes or is
+    /// cancelled. A weight greater than the limiter's total number of permits
+    /// is lowered to that total, so the future waits until it has the limiter
+    /// to itself rather than waiting forever.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::Limiter;
+    /// use futures_concurrency::prelude::*;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let limiter = Limiter::new(4);
+    ///     let light = limiter.par_weighted(async { 1 }, 1);
+    ///     let heavy = limiter.par_weighted(async { 2 }, 3); // ← runs alongside `light`
+    ///     let huge = limiter.par_weighted(async { 3 }, 8); // ← runs on its own
+    ///     let (a, b, c) = (light, heavy, huge).join().await;
+    ///     assert_eq!(a + b + c, 6);
+    /// })
+    /// ```
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn par_weighted<Fut>(&self, fut: Fut, weight: usize) -> Limited<Fut>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let max = self.semaphore.state.lock().unwrap().max;
+        Limited {
+            acquire: Acquire {
+                semaphore: self.semaphore.clone(),
+                weight: weight.min(max),
+                id: None,
+            },
+            permit: None,
+            inner: fut.par().cancel_on_drop(),
+        }
+    }
+}
+
+/// A parallel future which waits for a permit from a [`Limiter`] before it
+/// spawns.
+///
+/// This type is constructed by the [`par`][Limiter::par] method on
+/// [`Limiter`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Limited<Fut: IntoFuture> {
+    acquire: Acquire,
+    permit: Option<Permit>,
+    inner: ParallelFuture<Fut>,
+}
+
+impl<Fut> Future for Limited<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = Fut::Output;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if this.permit.is_none() {
+            this.permit = Some(ready!(this.acquire.poll(cx)));
+        }
+        let output = ready!(Pin::new(&mut this.inner).poll(cx));
+        this.permit = None;
+        Poll::Ready(output)
+    }
+}
+
+impl<Fut> fmt::Debug for Limited<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Limited")
+            .field("permit", &self.permit)
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A fair, async weighted semaphore.
+#[derive(Debug)]
+struct Semaphore {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    max: usize,
+    available: usize,
+    /// The id, weight and waker of every waiter.
+    waiters: VecDeque<(u64, usize, Waker)>,
+    next_id: u64,
+}
+
+impl State {
+    /// Wake the first waiter in line, if the semaphore has room for it.
+    fn wake_next(&mut self) {
+        if let Some((_, weight, waker)) = self.waiters.front() {
+            if *weight <= self.available {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+/// Acquire a permit from a semaphore.
+#[derive(Debug)]
+struct Acquire {
+    semaphore: Arc<Semaphore>,
+    weight: usize,
+    /// Our place in the queue of waiters, once we're in it.
+    id: Option<u64>,
+}
+
+impl Acquire {
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Permit> {
+        let mut state = self.semaphore.state.lock().unwrap();
+        let first_in_line = match self.id {
+            Some(id) => state.waiters.front().map(|(front, ..)| *front) == Some(id),
+            None => state.waiters.is_empty(),
+        };
+        if first_in_line && state.available >= self.weight {
+            state.available -= self.weight;
+            if self.id.take().is_some() {
+                state.waiters.pop_front();
+                state.wake_next();
+            }
+            return Poll::Ready(Permit {
+                semaphore: self.semaphore.clone(),
+                weight: self.weight,
+            });
+        }
+
+        match self.id {
+            Some(id) => {
+                let (.., waker) = state.waiters.iter_mut().find(|(i, ..)| *i == id).unwrap();
+                waker.clone_from(cx.waker());
+            }
+            None => {
+                let id = state.next_id;
+                state.next_id += 1;
+                state
+                    .waiters
+                    .push_back((id, self.weight, cx.waker().clone()));
+                self.id = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Leave the queue of waiters when dropped.
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.semaphore.state.lock().unwrap();
+            state.waiters.retain(|(i, ..)| *i != id);
+            state.wake_next();
+        }
+    }
+}
+
+/// One or more permits acquired from a semaphore.
+#[derive(Debug)]
+struct Permit {
+    semaphore: Arc<Semaphore>,
+    weight: usize,
+}
+
+/// Release the permits when dropped.
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.semaphore.state.lock().unwrap();
+        state.available += self.weight;
+        state.wake_next();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::runtime;
+
+    #[test]
+    fn limits_concurrency() {
+        runtime::block_on(async {
+            let limiter = Limiter::new(2);
+            let running = Arc::new(AtomicUsize::new(0));
+            let max = Arc::new(AtomicUsize::new(0));
+            let futs: Vec<_> = (0..6)
+                .map(|_| {
+                    let running = running.clone();
+                    let max = max.clone();
+                    limiter.par(async move {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max.fetch_max(now, Ordering::SeqCst);
+                        runtime::sleep(Duration::from_millis(20)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+                .collect();
+            futs.join().await;
+            assert_eq!(max.load(Ordering::SeqCst), 2);
+        })
+    }
+
+    #[test]
+    fn releases_on_cancel() {
+        runtime::block_on(async {
+            let limiter = Limiter::new(1);
+            let mut slow = limiter.par(future::pending::<()>());
+            let poll = Pin::new(&mut slow).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(slow);
+
+            // The permit of the cancelled future is available again.
+            let res = limiter.par(async { 12 }).await;
+            assert_eq!(res, 12);
+        })
+    }
+
+    #[test]
+    fn weighted() {
+        runtime::block_on(async {
+            let limiter = Limiter::new(4);
+            let running = Arc::new(AtomicUsize::new(0));
+            let max = Arc::new(AtomicUsize::new(0));
+            let futs: Vec<_> = vec![1, 3, 2, 10, 1]
+                .into_iter()
+                .map(|weight| {
+                    let running = running.clone();
+                    let max = max.clone();
+                    let fut = async move {
+                        let weight = weight.min(4);
+                        let now = running.fetch_add(weight, Ordering::SeqCst) + weight;
+                        max.fetch_max(now, Ordering::SeqCst);
+                        runtime::sleep(Duration::from_millis(20)).await;
+                        running.fetch_sub(weight, Ordering::SeqCst);
+                    };
+                    limiter.par_weighted(fut, weight)
+                })
+                .collect();
+            futs.join().await;
+            assert_eq!(max.load(Ordering::SeqCst), 4);
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "greater than zero")]
+    fn zero_limit() {
+        Limiter::new(0);
+    }
+}
@@ -0,0 +1,319 @@
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use pin_project::{pin_project, pinned_drop};
+
+use crate::executor::{self, JoinHandle, TaskId};
+
+/// A parallelizable future which isn't `Send`.
+///
+/// This type is constructed by the
+/// [`par_local`][crate::IntoLocalFutureExt::par_local] method on
+/// [`IntoLocalFutureExt`][crate::IntoLocalFutureExt]. The future is spawned on
+/// the thread which first polls it, and never moves between threads. For that
+/// reason the `LocalParallelFuture` itself is `!Send` too, even if its output
+/// is.
+///
+/// Like [`ParallelFuture`][crate::ParallelFuture], it starts lazily, can be
+/// joined with other futures, and is cancelled when dropped.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use futures_concurrency::prelude::*;
+/// use std::rc::Rc;
+///
+/// async_std::task::block_on(async {
+///     let a = Rc::new(1);
+///     let b = Rc::new(2);
+///     let a = async move { *a }.par_local(); // ← `Rc` isn't `Send`
+///     let b = async move { *b }.par_local();
+///
+///     let (a, b) = (a, b).join().await;
+///     assert_eq!(a + b, 3);
+/// })
+/// ```
+#[pin_project(PinnedDrop)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct LocalParallelFuture<Fut: IntoFuture> {
+    into_future: Option<Fut>,
+    name: Option<Arc<str>>,
+    handle: Option<JoinHandle<Fut::Output>>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<Fut> LocalParallelFuture<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+    Fut::Output: 'static,
+{
+    /// Returns the name of the task, if one was set through
+    /// [`par_local_builder`][crate::IntoLocalFutureExt::par_local_builder].
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the id of the spawned task.
+    ///
+    /// Ids are allocated when the future is spawned, so this returns `None`
+    /// until the future has been polled at least once.
+    pub fn id(&self) -> Option<TaskId> {
+        self.handle.as_ref().map(JoinHandle::id)
+    }
+
+    /// Returns `true` once the spawned future has completed.
+    ///
+    /// This does not poll the future, so it returns `false` until the future
+    /// has been spawned by polling it at least once.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_some_and(JoinHandle::is_finished)
+    }
+}
+
+impl<Fut> Future for LocalParallelFuture<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+    Fut::Output: 'static,
+{
+    type Output = Fut::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if this.handle.is_none() {
+            let into_fut = this.into_future.take().unwrap().into_future();
+            *this.handle = Some(executor::spawn_local(into_fut, this.name.clone()));
+        }
+        Pin::new(this.handle.as_mut().unwrap()).poll(cx)
+    }
+}
+
+impl<Fut> fmt::Debug for LocalParallelFuture<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalParallelFuture")
+            .field("into_future", &self.into_future)
+            .field("name", &self.name)
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+/// Cancel the `LocalParallelFuture` when dropped.
+#[pinned_drop]
+impl<Fut: IntoFuture> PinnedDrop for LocalParallelFuture<Fut> {
+    fn drop(self: Pin<&mut Self>) {
+        if let Some(handle) = self.project().handle.take() {
+            handle.cancel();
+        }
+    }
+}
+
+/// Extend the `Future` trait with parallelism for non-`Send` futures.
+pub trait IntoLocalFutureExt: IntoFuture + Sized
+where
+    <Self as IntoFuture>::IntoFuture: 'static,
+    <Self as IntoFuture>::Output: 'static,
+{
+    /// Convert this future into a parallelizable future which runs on the
+    /// current thread.
+    ///
+    /// Unlike [`par`][crate::IntoFutureExt::par], the future doesn't need to
+    /// be `Send`. It's spawned on the async-std executor local to the thread
+    /// which first polls it. See [`LocalParallelFuture`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::rc::Rc;
+    ///
+    /// async_std::task::block_on(async {
+    ///     let n = Rc::new(12);
+    ///     let res = async move { *n }.par_local().await;
+    ///     assert_eq!(res, 12);
+    /// })
+    /// ```
+    fn par_local(self) -> LocalParallelFuture<Self> {
+        LocalParallelFuture {
+            into_future: Some(self),
+            name: None,
+            handle: None,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Configure the task this future is spawned as, before converting it into
+    /// a parallelizable future which runs on the current thread.
+    ///
+    /// This mirrors [`par_builder`][crate::IntoFutureExt::par_builder] for
+    /// non-`Send` futures. The builder is finished with
+    /// [`build`][LocalBuilder::build], which returns a `LocalParallelFuture`
+    /// like [`par_local`][IntoLocalFutureExt::par_local] does. It can also be
+    /// `.await`ed directly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use std::rc::Rc;
+    ///
+    /// async_std::task::block_on(async {
+    ///     let n = Rc::new(12);
+    ///     let fut = async move { *n }.par_local_builder().name("chashu").build();
+    ///     assert_eq!(fut.name(), Some("chashu"));
+    ///     assert_eq!(fut.await, 12);
+    /// })
+    /// ```
+    fn par_local_builder(self) -> LocalBuilder<Self> {
+        LocalBuilder {
+            into_future: self,
+            name: None,
+        }
+    }
+}
+
+impl<Fut> IntoLocalFutureExt for Fut
+where
+    Fut: IntoFuture,
+    <Fut as IntoFuture>::IntoFuture: 'static,
+    <Fut as IntoFuture>::Output: 'static,
+{
+}
+
+/// Configure the task a non-`Send` future is spawned as.
+///
+/// This type is constructed by the
+/// [`par_local_builder`][crate::IntoLocalFutureExt::par_local_builder] method
+/// on [`IntoLocalFutureExt`][crate::IntoLocalFutureExt].
+#[derive(Debug)]
+#[must_use = "builders do nothing unless you `.await` them or call `build`"]
+pub struct LocalBuilder<Fut> {
+    into_future: Fut,
+    name: Option<Arc<str>>,
+}
+
+impl<Fut> LocalBuilder<Fut> {
+    /// Set the name of the task.
+    ///
+    /// The name is passed on to async-std, and reported to the metrics hook.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into().into());
+        self
+    }
+}
+
+impl<Fut> LocalBuilder<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+    Fut::Output: 'static,
+{
+    /// Convert the future into a parallelizable future with the configured
+    /// options.
+    ///
+    /// Builders can also be `.await`ed directly, which calls this method.
+    pub fn build(self) -> LocalParallelFuture<Fut> {
+        LocalParallelFuture {
+            into_future: Some(self.into_future),
+            name: self.name,
+            handle: None,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<Fut> IntoFuture for LocalBuilder<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+    Fut::Output: 'static,
+{
+    type Output = Fut::Output;
+    type IntoFuture = LocalParallelFuture<Fut>;
+    fn into_future(self) -> Self::IntoFuture {
+        self.build()
+    }
+}
+
+/// Convert a configured builder into a parallelizable future.
+///
+/// This is the same as calling [`build`][LocalBuilder::build].
+impl<Fut> From<LocalBuilder<Fut>> for LocalParallelFuture<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: 'static,
+    Fut::Output: 'static,
+{
+    fn from(builder: LocalBuilder<Fut>) -> Self {
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::future::{self, Future};
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Waker};
+
+    use crate::prelude::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn par_local() {
+        runtime::block_on(async {
+            let count = Rc::new(Cell::new(0));
+            let count_2 = count.clone();
+            let res = async move {
+                count_2.set(count_2.get() + 1);
+                "nori"
+            }
+            .par_local()
+            .await;
+            assert_eq!(res, "nori");
+            assert_eq!(count.get(), 1);
+        })
+    }
+
+    #[test]
+    fn par_local_builder() {
+        runtime::block_on(async {
+            let n = Rc::new(12);
+            let fut = async move { *n }.par_local_builder().name("nori").build();
+            assert_eq!(fut.name(), Some("nori"));
+            assert_eq!(fut.await, 12);
+
+            let name = async { async_std::task::current().name().map(str::to_owned) }
+                .par_local_builder()
+                .name("chashu")
+                .await;
+            assert_eq!(name.as_deref(), Some("chashu"));
+        })
+    }
+
+    #[test]
+    fn par_local_cancels_on_drop() {
+        runtime::block_on(async {
+            let (guard, dropped) = drop_signal();
+            let mut fut = async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            }
+            .par_local();
+            let poll = Pin::new(&mut fut).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+            dropped.await;
+        })
+    }
+}
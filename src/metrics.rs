@@ -0,0 +1,510 @@
+use std::mem;
+use std::panic::Location;
+#[cfg(feature = "track-allocations")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "metrics")]
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex, RwLock};
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "track-allocations")]
+use crate::allocations::Tracked;
+#[cfg(feature = "drain")]
+use crate::drain::{self, Switch};
+use crate::executor::TaskId;
+
+type Hook = Arc<dyn Fn(TaskEvent) + Send + Sync + 'static>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns how many tasks spawned by parallel futures are currently in flight.
+///
+/// A task counts as in flight from the moment it's spawned, which for parallel
+/// futures is when they're first polled, until it completes, panics, or is
+/// cancelled. This counts tasks across every executor in the process, and
+/// works without the `metrics` feature or a hook.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let handle = async_std::task::sleep(Duration::from_millis(100))
+///         .par()
+///         .detach(); // ← spawned right away
+///     assert!(parallel_future::active_task_count() >= 1);
+///     handle.await;
+/// })
+/// ```
+pub fn active_task_count() -> usize {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Register a global callback which is called on task lifecycle events.
+///
+/// The callback is called for every task spawned by a parallel future,
+/// regardless of the executor it's spawned on. It runs synchronously on
+/// whichever thread the event happened on, so it should return quickly.
+/// Registering a new callback replaces the previous one.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use parallel_future::TaskEventKind;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static SPAWNED: AtomicUsize = AtomicUsize::new(0);
+///
+/// parallel_future::set_metrics_hook(|event| {
+///     if event.kind() == TaskEventKind::Spawned {
+///         SPAWNED.fetch_add(1, Ordering::SeqCst);
+///     }
+/// });
+///
+/// let res = parallel_future::par_block_on(async { 12 }); // ← spawns one task
+/// assert_eq!(res, 12);
+/// assert_eq!(SPAWNED.load(Ordering::SeqCst), 1);
+/// ```
+pub fn set_metrics_hook<F>(hook: F)
+where
+    F: Fn(TaskEvent) + Send + Sync + 'static,
+{
+    *HOOK.write().unwrap() = Some(Arc::new(hook));
+}
+
+/// An event in the lifecycle of a task.
+///
+/// This type is passed to the callback registered with [`set_metrics_hook`].
+#[derive(Debug, Clone)]
+pub struct TaskEvent {
+    kind: TaskEventKind,
+    id: TaskId,
+    name: Option<Arc<str>>,
+    #[cfg(feature = "debug")]
+    location: Option<&'static Location<'static>>,
+    #[cfg(feature = "track-allocations")]
+    allocated_bytes: u64,
+    #[cfg(feature = "metrics")]
+    scheduled_latency: Option<Duration>,
+}
+
+impl TaskEvent {
+    /// Returns what happened to the task.
+    pub fn kind(&self) -> TaskEventKind {
+        self.kind
+    }
+
+    /// Returns the id of the task.
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Returns the name of the task, if one was set through
+    /// [`par_builder`][crate::IntoFutureExt::par_builder].
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns where the task's parallel future was created. This requires
+    /// the `debug` feature.
+    #[cfg(feature = "debug")]
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.location
+    }
+
+    /// Returns how many bytes the task has allocated up to this event. This
+    /// requires the `track-allocations` feature, and
+    /// [`TrackingAllocator`][crate::TrackingAllocator] to be installed as the
+    /// global allocator.
+    ///
+    /// See `TrackingAllocator` for which allocations are counted.
+    #[cfg(feature = "track-allocations")]
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Returns how long the task waited from being spawned until it was first
+    /// polled by its executor. This requires the `metrics` feature.
+    ///
+    /// This is `None` for tasks which haven't been polled yet, including on
+    /// every `Spawned` event. Latencies which keep growing mean the executor
+    /// has more tasks than it can keep up with.
+    #[cfg(feature = "metrics")]
+    pub fn scheduled_latency(&self) -> Option<Duration> {
+        self.scheduled_latency
+    }
+}
+
+/// The kind of a [`TaskEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskEventKind {
+    /// The task was handed to its executor.
+    Spawned,
+    /// The task ran to completion.
+    Completed,
+    /// The task was dropped before it completed.
+    Cancelled,
+    /// The task panicked.
+    Panicked,
+}
+
+/// Tracks the lifecycle of a single task, emitting its events.
+///
+/// Nothing is reported until the task's executor has accepted it, so tasks
+/// which fail to spawn don't emit any events. If the tracker is dropped before
+/// the task finished, the task is reported as cancelled.
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    id: TaskId,
+    name: Option<Arc<str>>,
+    #[cfg(feature = "debug")]
+    location: Option<&'static Location<'static>>,
+    #[cfg(feature = "track-allocations")]
+    allocated: Arc<AtomicU64>,
+    #[cfg(feature = "drain")]
+    switch: Arc<Switch>,
+    #[cfg(feature = "metrics")]
+    spawned: Instant,
+    #[cfg(feature = "metrics")]
+    scheduled_latency: OnceLock<Duration>,
+    stage: Arc<Mutex<Stage>>,
+    done: bool,
+}
+
+/// How far a task got in being handed to its executor.
+#[derive(Debug)]
+enum Stage {
+    /// The executor hasn't accepted the task yet. Holds the `Spawned` event.
+    Pending(TaskEvent),
+    /// The task has been reported as spawned.
+    Spawned,
+    /// The task was dropped before it was reported as spawned. Holds the
+    /// `Spawned` and `Cancelled` events, which are only reported if the
+    /// executor turns out to have accepted the task.
+    Dropped(TaskEvent, TaskEvent),
+}
+
+impl Stage {
+    /// Report the task as spawned, if that hasn't happened yet.
+    fn spawned(&mut self) {
+        match mem::replace(self, Stage::Spawned) {
+            Stage::Pending(spawned) => {
+                ACTIVE.fetch_add(1, Ordering::Relaxed);
+                emit(spawned);
+            }
+            Stage::Spawned => {}
+            Stage::Dropped(spawned, cancelled) => {
+                emit(spawned);
+                emit(cancelled);
+            }
+        }
+    }
+}
+
+impl Lifecycle {
+    /// Start tracking a task which is about to be spawned.
+    ///
+    /// The task is reported as spawned once it's first polled, or once the
+    /// [`Handoff`] returned by [`Lifecycle::handoff`] is accepted, whichever
+    /// comes first.
+    pub(crate) fn new(
+        id: TaskId,
+        name: Option<Arc<str>>,
+        location: Option<&'static Location<'static>>,
+    ) -> Self {
+        // Locations are only reported with the `debug` feature enabled.
+        #[cfg(not(feature = "debug"))]
+        let _ = location;
+        let spawned = TaskEvent {
+            kind: TaskEventKind::Spawned,
+            id,
+            name: name.clone(),
+            #[cfg(feature = "debug")]
+            location,
+            #[cfg(feature = "track-allocations")]
+            allocated_bytes: 0,
+            #[cfg(feature = "metrics")]
+            scheduled_latency: None,
+        };
+        Self {
+            id,
+            name,
+            #[cfg(feature = "debug")]
+            location,
+            #[cfg(feature = "track-allocations")]
+            allocated: Arc::new(AtomicU64::new(0)),
+            #[cfg(feature = "drain")]
+            switch: drain::register(id),
+            #[cfg(feature = "metrics")]
+            spawned: Instant::now(),
+            #[cfg(feature = "metrics")]
+            scheduled_latency: OnceLock::new(),
+            stage: Arc::new(Mutex::new(Stage::Pending(spawned))),
+            done: false,
+        }
+    }
+
+    /// Returns the handle with which the spawner reports that the executor
+    /// accepted the task.
+    pub(crate) fn handoff(&self) -> Handoff {
+        Handoff(self.stage.clone())
+    }
+
+    /// Record that the task has been polled for the first time.
+    pub(crate) fn polled(&self) {
+        self.stage.lock().unwrap().spawned();
+        #[cfg(feature = "metrics")]
+        let _ = self.scheduled_latency.set(self.spawned.elapsed());
+    }
+
+    /// Report the task as having finished, either by completing or panicking.
+    pub(crate) fn finish(mut self, panicked: bool) {
+        self.done = true;
+        emit(self.event(match panicked {
+            true => TaskEventKind::Panicked,
+            false => TaskEventKind::Completed,
+        }));
+    }
+
+    /// Count the bytes allocated while polling the task's future.
+    #[cfg(feature = "track-allocations")]
+    pub(crate) fn track<F>(&self, fut: F) -> Tracked<F> {
+        Tracked::new(fut, self.allocated.clone())
+    }
+
+    /// Returns the switch which cancels the task when the program is drained.
+    #[cfg(feature = "drain")]
+    pub(crate) fn switch(&self) -> Arc<Switch> {
+        self.switch.clone()
+    }
+
+    fn event(&self, kind: TaskEventKind) -> TaskEvent {
+        TaskEvent {
+            kind,
+            id: self.id,
+            name: self.name.clone(),
+            #[cfg(feature = "debug")]
+            location: self.location,
+            #[cfg(feature = "track-allocations")]
+            allocated_bytes: self.allocated.load(Ordering::Relaxed),
+            #[cfg(feature = "metrics")]
+            scheduled_latency: self.scheduled_latency.get().copied(),
+        }
+    }
+}
+
+impl Drop for Lifecycle {
+    fn drop(&mut self) {
+        let mut stage = self.stage.lock().unwrap();
+        match mem::replace(&mut *stage, Stage::Spawned) {
+            // The executor may still accept the task, so hold on to the events
+            // until the spawner knows.
+            Stage::Pending(spawned) => {
+                *stage = Stage::Dropped(spawned, self.event(TaskEventKind::Cancelled));
+            }
+            Stage::Spawned => {
+                ACTIVE.fetch_sub(1, Ordering::Relaxed);
+                if !self.done {
+                    emit(self.event(TaskEventKind::Cancelled));
+                }
+            }
+            Stage::Dropped(..) => unreachable!("the task was dropped twice"),
+        }
+        drop(stage);
+        #[cfg(feature = "drain")]
+        drain::unregister(self.id);
+    }
+}
+
+/// Reports a task as spawned once its executor accepted it.
+///
+/// Dropping the handoff without accepting it means spawning failed, and no
+/// events are reported for the task.
+#[derive(Debug)]
+pub(crate) struct Handoff(Arc<Mutex<Stage>>);
+
+impl Handoff {
+    /// Report that the executor accepted the task.
+    pub(crate) fn accepted(self) {
+        self.0.lock().unwrap().spawned();
+    }
+}
+
+fn emit(event: TaskEvent) {
+    #[cfg(feature = "log")]
+    log(&event);
+    // Clone the hook out of the lock, so it's free to register a new one.
+    let hook = HOOK.read().unwrap().clone();
+    if let Some(hook) = hook {
+        hook(event);
+    }
+}
+
+#[cfg(feature = "log")]
+fn log(event: &TaskEvent) {
+    let kind = match event.kind {
+        TaskEventKind::Spawned => "spawned",
+        TaskEventKind::Completed => "completed",
+        TaskEventKind::Cancelled => "cancelled",
+        TaskEventKind::Panicked => "panicked",
+    };
+    match &event.name {
+        Some(name) => log::debug!("task {} ({:?}) {}", event.id, name, kind),
+        None => log::debug!("task {} {}", event.id, kind),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::executor::{BoxedHandle, Executor, SpawnError, Task};
+    use crate::prelude::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    /// An executor which refuses every task.
+    #[derive(Debug, Clone)]
+    struct Refusing;
+
+    impl Executor for Refusing {
+        fn spawn(&self, _task: Task) -> BoxedHandle {
+            panic!("the executor refuses every task")
+        }
+
+        fn try_spawn(&self, _task: Task) -> Result<BoxedHandle, SpawnError> {
+            Err(SpawnError::new())
+        }
+    }
+
+    #[test]
+    fn lifecycle_events() {
+        // Other tests spawn tasks concurrently, so only record our own.
+        static EVENTS: Mutex<Vec<TaskEvent>> = Mutex::new(Vec::new());
+        let (guard, cancelled) = drop_signal();
+        let guard = Mutex::new(Some(guard));
+        set_metrics_hook(move |event| {
+            if event
+                .name()
+                .is_some_and(|name| name.starts_with("metrics-"))
+            {
+                let kind = event.kind();
+                EVENTS.lock().unwrap().push(event);
+                if kind == TaskEventKind::Cancelled {
+                    guard.lock().unwrap().take();
+                }
+            }
+        });
+
+        runtime::block_on(async {
+            async { vec![0u8; 1 << 16].len() }
+                .par_builder()
+                .name("metrics-ok")
+                .await;
+            let res = async { panic!("oh no") }
+                .par_builder()
+                .name("metrics-panic")
+                .build()
+                .catch_unwind()
+                .await;
+            assert!(res.is_err());
+            // Tasks which fail to spawn never report any events.
+            let res = async { 12 }
+                .par_builder()
+                .name("metrics-refused")
+                .on(&Refusing)
+                .build()
+                .try_spawn();
+            assert_eq!(res, Err(SpawnError::new()));
+            future::pending::<()>()
+                .par_builder()
+                .name("metrics-cancel")
+                .build()
+                .cancel_on_drop()
+                .timeout(Duration::from_millis(10))
+                .await
+                .unwrap_err();
+            cancelled.await;
+        });
+
+        let events = EVENTS.lock().unwrap();
+        let kinds: Vec<_> = events
+            .iter()
+            .map(|event| (event.kind(), event.name().unwrap()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (TaskEventKind::Spawned, "metrics-ok"),
+                (TaskEventKind::Completed, "metrics-ok"),
+                (TaskEventKind::Spawned, "metrics-panic"),
+                (TaskEventKind::Panicked, "metrics-panic"),
+                (TaskEventKind::Spawned, "metrics-cancel"),
+                (TaskEventKind::Cancelled, "metrics-cancel"),
+            ]
+        );
+        assert!(events[0].id() < events[2].id() && events[2].id() < events[4].id());
+        assert_eq!(events[0].id(), events[1].id());
+        #[cfg(feature = "metrics")]
+        {
+            assert_eq!(events[0].scheduled_latency(), None);
+            assert!(events[1].scheduled_latency().is_some());
+        }
+        #[cfg(feature = "track-allocations")]
+        {
+            assert_eq!(events[0].allocated_bytes(), 0);
+            assert!(events[1].allocated_bytes() >= 1 << 16);
+        }
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_lines() {
+        struct Logger;
+        static LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        impl log::Log for Logger {
+            fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+                true
+            }
+            fn log(&self, record: &log::Record<'_>) {
+                let line = record.args().to_string();
+                if line.contains("\"log-") {
+                    LINES.lock().unwrap().push(line);
+                }
+            }
+            fn flush(&self) {}
+        }
+        log::set_logger(&Logger).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        runtime::block_on(async {
+            async { 12 }.par_builder().name("log-ok").await;
+        });
+        let lines = LINES.lock().unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("(\"log-ok\") spawned"));
+        assert!(lines[1].ends_with("(\"log-ok\") completed"));
+    }
+
+    #[test]
+    fn active_task_count() {
+        runtime::block_on(async {
+            let futs: Vec<_> = (0..3)
+                .map(|_| future::pending::<()>().par_eager())
+                .collect();
+            // Other tests spawn tasks concurrently, so the count may be higher.
+            assert!(super::active_task_count() >= 3);
+            drop(futs);
+        })
+    }
+}
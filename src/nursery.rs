@@ -0,0 +1,375 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use pin_project::{pin_project, pinned_drop};
+
+use crate::runtime::BoxFuture;
+use crate::ParallelGroup;
+
+/// Run an async closure which can spawn parallel child futures on a
+/// [`Nursery`], and wait for all of them to complete.
+///
+/// This is structured concurrency on top of [`ParallelGroup`]: the returned
+/// future only resolves once the body and every child spawned on the nursery
+/// have completed, so no child outlives it. Children may spawn more children
+/// through clones of the nursery.
+///
+/// If the body or any of the children returns an error, the remaining children
+/// and the body are cancelled, and the nursery resolves to that error. If a
+/// child panics, the remaining children are cancelled and the panic is resumed
+/// in the task awaiting the nursery. Dropping the returned future cancels all
+/// children too. Use [`NurseryFuture::limit`] to bound how many children run
+/// at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// parallel_future::par_block_on(async {
+///     let sum = Arc::new(AtomicUsize::new(0));
+///     let res = parallel_future::nursery(|n| async move {
+///         for i in 1..=3 {
+///             let sum = sum.clone();
+///             n.spawn(async move {
+///                 sum.fetch_add(i, Ordering::SeqCst);
+///                 Ok::<_, ()>(())
+///             });
+///         }
+///         Ok(sum)
+///     })
+///     .await;
+///     assert_eq!(res.unwrap().load(Ordering::SeqCst), 6); // ← children are done
+/// })
+/// ```
+pub fn nursery<F, Fut, T, E>(f: F) -> NurseryFuture<F, Fut, T, E>
+where
+    F: FnOnce(Nursery<E>) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Send + 'static,
+{
+    NurseryFuture {
+        f: Some(f),
+        body: None,
+        nursery: Nursery::new(),
+        output: None,
+    }
+}
+
+/// A handle to spawn child futures in a nursery.
+///
+/// This type is passed to the closure given to the [`nursery`][crate::nursery]
+/// function. It can be cloned to spawn children from within other children.
+pub struct Nursery<E> {
+    state: Arc<Mutex<State<E>>>,
+}
+
+struct State<E> {
+    group: ParallelGroup<Result<(), E>>,
+    /// Children which are waiting for room under the limit.
+    queued: VecDeque<BoxFuture<Result<(), E>>>,
+    limit: Option<usize>,
+    /// The waker of the task awaiting the nursery.
+    waker: Option<Waker>,
+    /// Whether the nursery has completed or been dropped.
+    closed: bool,
+}
+
+impl<E> State<E> {
+    /// Spawn queued children, as long as the limit allows.
+    fn fill(&mut self)
+    where
+        E: Send + 'static,
+    {
+        while self.limit.is_none_or(|limit| self.group.len() < limit) {
+            match self.queued.pop_front() {
+                Some(fut) => self.group.spawn(fut),
+                None => break,
+            }
+        }
+    }
+}
+
+impl<E> Nursery<E> {
+    fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                group: ParallelGroup::new(),
+                queued: VecDeque::new(),
+                limit: None,
+                waker: None,
+                closed: false,
+            })),
+        }
+    }
+
+    /// Lock the state. A child panic is resumed while the lock is held, so a
+    /// poisoned lock is expected.
+    fn lock(&self) -> MutexGuard<'_, State<E>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the number of children which haven't completed yet, including
+    /// ones waiting for room under the limit.
+    pub fn len(&self) -> usize {
+        let state = self.lock();
+        state.group.len() + state.queued.len()
+    }
+
+    /// Returns `true` if there are no children which haven't completed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cancel all children, and drop any which are spawned from now on.
+    fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        state.waker = None;
+        let group = mem::take(&mut state.group);
+        let queued = mem::take(&mut state.queued);
+        // Children may hold clones of the nursery, so they're only dropped once
+        // the lock has been released.
+        drop(state);
+        drop(group);
+        drop(queued);
+    }
+}
+
+impl<E: Send + 'static> Nursery<E> {
+    /// Spawn a child future in the nursery.
+    ///
+    /// The child starts running right away, unless the nursery's limit has
+    /// been reached, in which case it starts once another child completes.
+    /// Children spawned after the nursery has completed are dropped without
+    /// being run.
+    pub fn spawn<Fut>(&self, fut: Fut)
+    where
+        Fut: IntoFuture<Output = Result<(), E>>,
+        Fut::IntoFuture: Send + 'static,
+    {
+        let mut state = self.lock();
+        if state.closed {
+            return;
+        }
+        state.queued.push_back(Box::pin(fut.into_future()));
+        state.fill();
+        // The nursery needs to be polled to pick up the new child.
+        if let Some(waker) = &state.waker {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// Poll the children, resolving once they've all completed or one of them
+    /// has failed.
+    fn poll_join(&self, cx: &mut Context<'_>) -> Poll<Result<(), E>> {
+        let mut state = self.lock();
+        state.waker = Some(cx.waker().clone());
+        loop {
+            state.fill();
+            match state.group.poll_join_next(cx) {
+                Poll::Ready(Some(Ok(()))) => continue,
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<E> Clone for Nursery<E> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for Nursery<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Nursery").field("len", &self.len()).finish()
+    }
+}
+
+/// A future which runs a nursery to completion.
+///
+/// This type is constructed by the [`nursery`][crate::nursery] function.
+#[pin_project(PinnedDrop)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct NurseryFuture<F, Fut, T, E> {
+    f: Option<F>,
+    #[pin]
+    body: Option<Fut>,
+    nursery: Nursery<E>,
+    /// The output of the body, once it has completed.
+    output: Option<T>,
+}
+
+impl<F, Fut, T, E> NurseryFuture<F, Fut, T, E> {
+    /// Limit the number of children running at the same time.
+    ///
+    /// Children spawned beyond the limit are queued, and started in the order
+    /// they were spawned in as running children complete.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    pub fn limit(self, limit: usize) -> Self {
+        assert!(limit > 0, "the concurrency limit must be greater than zero");
+        self.nursery.lock().limit = Some(limit);
+        self
+    }
+}
+
+impl<F, Fut, T, E> Future for NurseryFuture<F, Fut, T, E>
+where
+    F: FnOnce(Nursery<E>) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Send + 'static,
+{
+    type Output = Result<T, E>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if let Some(f) = this.f.take() {
+            this.body.set(Some(f(this.nursery.clone())));
+        }
+
+        if let Some(body) = this.body.as_mut().as_pin_mut() {
+            if let Poll::Ready(output) = body.poll(cx) {
+                this.body.set(None);
+                match output {
+                    Ok(output) => *this.output = Some(output),
+                    Err(err) => {
+                        this.nursery.close();
+                        return Poll::Ready(Err(err));
+                    }
+                }
+            }
+        }
+
+        match this.nursery.poll_join(cx) {
+            Poll::Ready(Err(err)) => {
+                this.body.set(None);
+                this.nursery.close();
+                Poll::Ready(Err(err))
+            }
+            Poll::Ready(Ok(())) if this.output.is_some() => {
+                this.nursery.close();
+                Poll::Ready(Ok(this.output.take().unwrap()))
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl<F, Fut, T, E> fmt::Debug for NurseryFuture<F, Fut, T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NurseryFuture")
+            .field("nursery", &self.nursery)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Cancel all children when dropped.
+#[pinned_drop]
+impl<F, Fut, T, E> PinnedDrop for NurseryFuture<F, Fut, T, E> {
+    fn drop(self: Pin<&mut Self>) {
+        self.nursery.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn waits_for_children() {
+        runtime::block_on(async {
+            let completed = Arc::new(AtomicUsize::new(0));
+            let completed_2 = completed.clone();
+            let res = nursery(|n| async move {
+                for ms in [30, 10, 20] {
+                    let n_2 = n.clone();
+                    let completed = completed_2.clone();
+                    n.spawn(async move {
+                        runtime::sleep(Duration::from_millis(ms)).await;
+                        // Children can spawn children of their own.
+                        let completed_2 = completed.clone();
+                        n_2.spawn(async move {
+                            completed_2.fetch_add(1, Ordering::SeqCst);
+                            Ok::<_, ()>(())
+                        });
+                        completed.fetch_add(1, Ordering::SeqCst);
+                        Ok(())
+                    });
+                }
+                Ok("nori")
+            })
+            .limit(2)
+            .await;
+            assert_eq!(res, Ok("nori"));
+            assert_eq!(completed.load(Ordering::SeqCst), 6);
+        })
+    }
+
+    #[test]
+    fn cancels_children_on_error() {
+        runtime::block_on(async {
+            let (guard, dropped) = drop_signal();
+            let (body_guard, body_dropped) = drop_signal();
+            let res = nursery(|n| async move {
+                let _guard = body_guard;
+                n.spawn(async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                    Ok(())
+                });
+                n.spawn(async { Err("oh no") });
+                future::pending::<()>().await;
+                Ok(())
+            })
+            .await;
+            assert_eq!(res, Err("oh no"));
+            (dropped, body_dropped).join().await;
+
+            let (guard, dropped) = drop_signal();
+            let res = nursery(|n| async move {
+                n.spawn(async move {
+                    let _guard = guard;
+                    future::pending::<()>().await;
+                    Ok(())
+                });
+                Err::<(), _>("body failed")
+            })
+            .await;
+            assert_eq!(res, Err("body failed"));
+            dropped.await;
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "oh no")]
+    fn propagates_panics() {
+        runtime::block_on(async {
+            let _ = nursery(|n| async move {
+                n.spawn(async { panic!("oh no") });
+                Ok::<_, ()>(())
+            })
+            .await;
+        })
+    }
+}
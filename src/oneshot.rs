@@ -0,0 +1,113 @@
+//! A single-use channel to send a task's output back to its owner.
+
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+
+use atomic_waker::AtomicWaker;
+use concurrent_queue::ConcurrentQueue;
+
+/// Create a new oneshot channel.
+pub(crate) fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        value: ConcurrentQueue::bounded(1),
+        waker: AtomicWaker::new(),
+        closed: AtomicBool::new(false),
+    });
+    let sender = Sender {
+        inner: inner.clone(),
+    };
+    (sender, Receiver { inner })
+}
+
+/// The channel doesn't need a lock: the value is only ever written by the
+/// sender, and only read once the sender has been dropped.
+#[derive(Debug)]
+struct Inner<T> {
+    value: ConcurrentQueue<T>,
+    waker: AtomicWaker,
+    closed: AtomicBool,
+}
+
+/// The sending half of a oneshot channel.
+#[derive(Debug)]
+pub(crate) struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Send a value, waking up the receiver.
+    pub(crate) fn send(self, value: T) {
+        // The queue has room for exactly the one value we send.
+        let _ = self.inner.value.push(value);
+        // The channel is closed by our `Drop` impl.
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        self.inner.waker.wake();
+    }
+}
+
+/// The receiving half of a oneshot channel.
+#[derive(Debug)]
+pub(crate) struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Returns `true` once the sender has been dropped, with or without
+    /// sending a value.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future which resolves once the sender has been dropped.
+    ///
+    /// It shares the receiver's waker, so it's only guaranteed to be woken up
+    /// once the receiver itself has been dropped.
+    #[cfg(feature = "std")]
+    pub(crate) fn closed(&self) -> Closed<T> {
+        Closed {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    /// Resolves to `None` if the sender was dropped without sending a value.
+    type Output = Option<T>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Register the waker before checking whether we're closed, so a sender
+        // which is dropped in between still wakes us up.
+        self.inner.waker.register(cx.waker());
+        if self.is_closed() {
+            return Poll::Ready(self.inner.value.pop().ok());
+        }
+        Poll::Pending
+    }
+}
+
+/// A future which resolves once the sender of a oneshot channel has been
+/// dropped, created by [`Receiver::closed`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub(crate) struct Closed<T> {
+    inner: Arc<Inner<T>>,
+}
+
+#[cfg(feature = "std")]
+impl<T> Future for Closed<T> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner.waker.register(cx.waker());
+        if self.inner.closed.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
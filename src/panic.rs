@@ -0,0 +1,91 @@
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_project::pin_project;
+
+/// The error returned when a parallel future panicked.
+///
+/// This is returned by functions which report panics alongside the outputs of
+/// the futures which didn't panic, such as
+/// [`par_join_all_results`][crate::par_join_all_results].
+pub struct PanicError {
+    payload: Box<dyn Any + Send>,
+}
+
+impl PanicError {
+    pub(crate) fn new(payload: Box<dyn Any + Send>) -> Self {
+        Self { payload }
+    }
+
+    /// Returns the panic message, if the future panicked with a string.
+    pub fn message(&self) -> Option<&str> {
+        match self.payload.downcast_ref::<&str>() {
+            Some(message) => Some(message),
+            None => self.payload.downcast_ref::<String>().map(String::as_str),
+        }
+    }
+
+    /// Returns the payload the future panicked with.
+    pub fn into_panic(self) -> Box<dyn Any + Send> {
+        self.payload
+    }
+
+    /// Resume the panic in the current thread.
+    pub fn resume(self) -> ! {
+        panic::resume_unwind(self.payload)
+    }
+}
+
+impl fmt::Debug for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PanicError")
+            .field("message", &self.message())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message() {
+            Some(message) => write!(f, "the parallel future panicked: {message}"),
+            None => "the parallel future panicked".fmt(f),
+        }
+    }
+}
+
+impl Error for PanicError {}
+
+/// A future which catches panics raised while polling the inner future.
+#[pin_project]
+pub(crate) struct CatchPanic<Fut> {
+    #[pin]
+    inner: Fut,
+}
+
+impl<Fut> CatchPanic<Fut> {
+    pub(crate) fn new(inner: Fut) -> Self {
+        Self { inner }
+    }
+}
+
+impl<Fut: Future> Future for CatchPanic<Fut> {
+    type Output = Result<Fut::Output, PanicError>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.project().inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(PanicError::new(payload))),
+        }
+    }
+}
+
+impl<Fut> fmt::Debug for CatchPanic<Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CatchPanic").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,155 @@
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::task::Poll;
+
+use async_channel::{Receiver, Sender};
+
+use crate::IntoFutureExt;
+
+/// Process the items of a channel on a pool of parallel workers, sending the
+/// outputs to another channel.
+///
+/// This spawns `workers` parallel futures right away, which each pull items
+/// from `input`, pass them to `f`, and send the output to the returned
+/// receiver. Outputs are sent in the order they complete in, not the order the
+/// items were received in. The output channel holds up to `workers` outputs,
+/// so workers wait for the outputs to be received before picking up more work.
+///
+/// Once `input` is closed and drained, the workers shut down after finishing
+/// their last item, and the output channel closes once all of them are done.
+/// Dropping the returned receiver cancels the workers, including any items
+/// they're in the middle of processing.
+///
+/// This requires the `async-channel` feature.
+///
+/// # Panics
+///
+/// This panics if `workers` is zero.
+///
+/// # Examples
+///
+/// ```
+/// parallel_future::par_block_on(async {
+///     let (sender, input) = async_channel::unbounded();
+///     let output = parallel_future::par_pipeline(input, 4, |n: u32| async move { n * 2 });
+///     for n in 1..=3 {
+///         sender.send(n).await.unwrap();
+///     }
+///     drop(sender); // ← lets the workers shut down
+///
+///     let mut sum = 0;
+///     while let Ok(n) = output.recv().await {
+///         sum += n;
+///     }
+///     assert_eq!(sum, 12);
+/// })
+/// ```
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_pipeline<I, O, F, Fut>(input: Receiver<I>, workers: usize, f: F) -> Receiver<O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: Fn(I) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = O> + Send + 'static,
+{
+    assert!(workers > 0, "a pipeline needs at least one worker");
+    let (output, receiver) = async_channel::bounded(workers);
+    for _ in 0..workers {
+        let worker = worker(input.clone(), output.clone(), f.clone());
+        // The worker stops on its own once either channel is closed.
+        drop(worker.par().detach());
+    }
+    receiver
+}
+
+/// Process items until the input is drained, or the output receiver has been
+/// dropped.
+async fn worker<I, O, F, Fut>(input: Receiver<I>, output: Sender<O>, f: F)
+where
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = O>,
+{
+    let mut work = pin!(async {
+        // Owned by the block rather than borrowed, so `F` doesn't need to be
+        // `Sync` for the worker to be `Send`.
+        let f = f;
+        while let Ok(item) = input.recv().await {
+            if output.send(f(item).await).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut closed = pin!(output.closed());
+    poll_fn(|cx| {
+        // Stop right away once there's nobody left to send outputs to, even if
+        // we're in the middle of processing an item.
+        if closed.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(());
+        }
+        work.as_mut().poll(cx)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+    use std::time::Duration;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn processes_all_items() {
+        runtime::block_on(async {
+            let (sender, input) = async_channel::unbounded();
+            let output = par_pipeline(input, 3, |n: usize| async move {
+                runtime::sleep(Duration::from_millis(10)).await;
+                n * 2
+            });
+            for n in 0..10 {
+                sender.send(n).await.unwrap();
+            }
+            drop(sender);
+
+            let mut outputs = Vec::new();
+            while let Ok(n) = output.recv().await {
+                outputs.push(n);
+            }
+            outputs.sort();
+            assert_eq!(outputs, (0..10).map(|n| n * 2).collect::<Vec<_>>());
+        })
+    }
+
+    #[test]
+    fn dropping_output_cancels_workers() {
+        runtime::block_on(async {
+            let (sender, input) = async_channel::unbounded();
+            let output = par_pipeline(input, 2, |(guard, started)| async move {
+                let _guard = guard;
+                drop(started);
+                future::pending::<()>().await;
+            });
+            let mut dropped = vec![];
+            let mut is_started = vec![];
+            for _ in 0..2 {
+                let (guard, is_dropped) = drop_signal();
+                let (started, has_started) = drop_signal();
+                sender.send((guard, started)).await.unwrap();
+                dropped.push(is_dropped);
+                is_started.push(has_started);
+            }
+            is_started.join().await;
+            drop(output);
+
+            // The items are dropped first, and the workers right after.
+            dropped.join().await;
+            while sender.receiver_count() > 0 {
+                crate::yield_now().await;
+            }
+        })
+    }
+}
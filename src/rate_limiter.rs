@@ -0,0 +1,246 @@
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use pin_project::pin_project;
+
+use crate::runtime::{self, Sleep};
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// Limit the rate at which parallel futures are spawned.
+///
+/// Unlike [`Limiter`][crate::Limiter], which bounds how many futures run at
+/// the same time, this bounds how many futures start per interval, regardless
+/// of how long they run for. It's a token bucket: up to `burst` futures may
+/// start right away, after which futures start at a steady rate as the bucket
+/// refills. Futures take their turn in the order they're first polled.
+///
+/// Both kinds of limits can be combined by wrapping a future created by
+/// [`Limiter::par`][crate::Limiter::par] with
+/// [`throttle`][RateLimiter::throttle].
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::RateLimiter;
+/// use futures_concurrency::prelude::*;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let limiter = RateLimiter::new(100, Duration::from_secs(1));
+///     let futs: Vec<_> = (0..8).map(|n| limiter.par(async move { n })).collect();
+///     let outputs = futs.join().await; // ← at most 100 futures start per second
+///     assert_eq!(outputs.into_iter().sum::<i32>(), 28);
+/// })
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter which allows `rate` futures to start per
+    /// `interval`.
+    ///
+    /// The burst size defaults to `rate`, so a full interval's worth of futures
+    /// may start at once. Use [`burst`][RateLimiter::burst] to smooth that out.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `rate` is zero.
+    pub fn new(rate: u32, interval: Duration) -> Self {
+        assert!(rate > 0, "the rate must be greater than zero");
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                period: interval / rate,
+                burst: rate,
+                next: None,
+            })),
+        }
+    }
+
+    /// Set how many futures may start at once, before futures start at the
+    /// steady rate.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `burst` is zero.
+    pub fn burst(self, burst: u32) -> Self {
+        assert!(burst > 0, "the burst size must be greater than zero");
+        self.bucket.lock().unwrap().burst = burst;
+        self
+    }
+
+    /// Convert a future into a parallelizable future which only spawns once
+    /// the rate limiter allows it to start.
+    ///
+    /// Like [`par`][crate::IntoFutureExt::par], the turn is only taken once the
+    /// returned future is first polled.
+    #[cfg_attr(feature = "debug", track_caller)]
+    pub fn par<Fut>(&self, fut: Fut) -> RateLimited<ParallelFuture<Fut>>
+    where
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        self.throttle(fut.par())
+    }
+
+    /// Delay the first poll of a lazy future until the rate limiter allows it
+    /// to start.
+    ///
+    /// This is meant for parallel futures which aren't spawned until they're
+    /// first polled, such as those created by
+    /// [`Limiter::par`][crate::Limiter::par], to combine both kinds of limits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::{Limiter, RateLimiter};
+    /// use futures_concurrency::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let limiter = Limiter::new(2);
+    ///     let rate_limiter = RateLimiter::new(100, Duration::from_secs(1));
+    ///     let futs: Vec<_> = (0..8)
+    ///         .map(|n| rate_limiter.throttle(limiter.par(async move { n })))
+    ///         .collect();
+    ///     let outputs = futs.join().await;
+    ///     assert_eq!(outputs.into_iter().sum::<i32>(), 28);
+    /// })
+    /// ```
+    pub fn throttle<Fut: Future>(&self, fut: Fut) -> RateLimited<Fut> {
+        RateLimited {
+            bucket: self.bucket.clone(),
+            state: State::Waiting,
+            inner: fut,
+        }
+    }
+}
+
+/// A token bucket, tracked as the time at which the next token is handed out
+/// if the bucket is empty.
+#[derive(Debug)]
+struct Bucket {
+    /// How long it takes for one token to be refilled.
+    period: Duration,
+    burst: u32,
+    next: Option<Instant>,
+}
+
+impl Bucket {
+    /// Take a token, returning when it may be used.
+    ///
+    /// Tokens are handed out in order, so a future which is dropped before its
+    /// turn comes up still uses up its token. That errs on the side of
+    /// starting fewer futures, never more.
+    fn take(&mut self) -> Instant {
+        let now = Instant::now();
+        let next = self.next.map_or(now, |next| next.max(now));
+        self.next = Some(next + self.period);
+        // Up to `burst` tokens may be handed out ahead of the steady rate.
+        let ahead = self.period * (self.burst - 1);
+        next.checked_sub(ahead).map_or(now, |start| start.max(now))
+    }
+}
+
+/// A parallel future which waits for its turn from a [`RateLimiter`] before it
+/// starts.
+///
+/// This type is constructed by the [`par`][RateLimiter::par] and
+/// [`throttle`][RateLimiter::throttle] methods on [`RateLimiter`].
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct RateLimited<Fut> {
+    bucket: Arc<Mutex<Bucket>>,
+    state: State,
+    #[pin]
+    inner: Fut,
+}
+
+enum State {
+    /// Waiting to take a token.
+    Waiting,
+    /// Waiting for our token to become usable.
+    Sleeping(Sleep),
+    /// Polling the inner future.
+    Started,
+}
+
+impl<Fut: Future> Future for RateLimited<Fut> {
+    type Output = Fut::Output;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let State::Waiting = this.state {
+            let start = this.bucket.lock().unwrap().take();
+            *this.state = if start > Instant::now() {
+                State::Sleeping(runtime::sleep_until(start))
+            } else {
+                State::Started
+            };
+        }
+        if let State::Sleeping(sleep) = this.state {
+            ready!(sleep.as_mut().poll(cx));
+            *this.state = State::Started;
+        }
+        this.inner.poll(cx)
+    }
+}
+
+impl<Fut: fmt::Debug> fmt::Debug for RateLimited<Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimited")
+            .field("started", &matches!(self.state, State::Started))
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::Limiter;
+
+    #[test]
+    fn limits_rate() {
+        runtime::block_on(async {
+            let limiter = RateLimiter::new(10, Duration::from_millis(500)).burst(2);
+            let started = Arc::new(Mutex::new(Vec::new()));
+            let begin = Instant::now();
+            let futs: Vec<_> = (0..6)
+                .map(|_| {
+                    let started = started.clone();
+                    limiter.par(async move {
+                        started.lock().unwrap().push(begin.elapsed());
+                    })
+                })
+                .collect();
+            futs.join().await;
+
+            // Two futures start right away, and then one every 50ms. Only the
+            // lower bounds hold regardless of how busy the machine is.
+            let mut started = started.lock().unwrap().clone();
+            started.sort();
+            assert!(started[2] >= Duration::from_millis(40));
+            assert!(started[5] >= Duration::from_millis(190));
+        })
+    }
+
+    #[test]
+    fn combines_with_limiter() {
+        runtime::block_on(async {
+            let limiter = Limiter::new(1);
+            let rate_limiter = RateLimiter::new(100, Duration::from_secs(1));
+            let futs: Vec<_> = (0..4)
+                .map(|n| rate_limiter.throttle(limiter.par(async move { n })))
+                .collect();
+            assert_eq!(futs.join().await, vec![0, 1, 2, 3]);
+        })
+    }
+}
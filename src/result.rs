@@ -0,0 +1,116 @@
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// Convert a future which was created fallibly into a parallelizable future.
+///
+/// If `res` holds a future, it's converted with
+/// [`par`][crate::IntoFutureExt::par], and its output is wrapped in `Ok`. If
+/// `res` holds an error, nothing is ever spawned, and the returned future
+/// resolves with that error right away. This saves matching on the result
+/// before every call to `par`.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::par_result;
+///
+/// fn connect(addr: &str) -> Result<impl std::future::Future<Output = u16>, String> {
+///     let port = addr.parse().map_err(|_| format!("invalid port: {}", addr))?;
+///     Ok(async move { port })
+/// }
+///
+/// parallel_future::par_block_on(async {
+///     assert_eq!(par_result(connect("8080")).await, Ok(8080));
+///     assert!(par_result(connect("nori")).await.is_err()); // ← never spawned
+/// })
+/// ```
+pub fn par_result<Fut, E>(res: Result<Fut, E>) -> ParResult<Fut, E>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    ParResult {
+        inner: res.map(IntoFutureExt::par).map_err(Some),
+    }
+}
+
+/// A parallel future which was created fallibly.
+///
+/// This type is constructed by the [`par_result`][crate::par_result]
+/// function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ParResult<Fut: IntoFuture, E> {
+    inner: Result<ParallelFuture<Fut>, Option<E>>,
+}
+
+impl<Fut: IntoFuture, E> ParResult<Fut, E> {
+    /// Returns the parallel future, unless creating it failed.
+    pub fn as_par(&self) -> Option<&ParallelFuture<Fut>> {
+        self.inner.as_ref().ok()
+    }
+}
+
+// The future and error are never pinned, so `ParResult` can be moved
+// regardless of their types.
+impl<Fut: IntoFuture, E> Unpin for ParResult<Fut, E> {}
+
+impl<Fut, E> Future for ParResult<Fut, E>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = Result<Fut::Output, E>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.inner {
+            Ok(fut) => Pin::new(fut).poll(cx).map(Ok),
+            Err(err) => Poll::Ready(Err(err
+                .take()
+                .expect("`ParResult` polled after completion"))),
+        }
+    }
+}
+
+impl<Fut, E> fmt::Debug for ParResult<Fut, E>
+where
+    Fut: IntoFuture + fmt::Debug,
+    E: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParResult")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::runtime;
+
+    #[test]
+    fn short_circuits() {
+        runtime::block_on(async {
+            let res: Result<_, ()> = Ok(async { 12 });
+            assert_eq!(par_result(res).await, Ok(12));
+
+            let spawned = Arc::new(AtomicBool::new(false));
+            let spawned_2 = spawned.clone();
+            let res = Err::<_, &str>("oh no").map(|()| async move {
+                spawned_2.store(true, Ordering::SeqCst);
+            });
+            let fut = par_result(res);
+            assert!(fut.as_par().is_none());
+            assert_eq!(fut.await, Err("oh no"));
+            assert!(!spawned.load(Ordering::SeqCst));
+        })
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use crate::runtime::{self, Sleep};
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// Retry a fallible future until it succeeds.
+///
+/// The closure is called to create a fresh future for every attempt, and each
+/// attempt is spawned as a parallel future of its own. Once the policy's
+/// maximum number of attempts is reached, the last error is returned. Dropping
+/// the returned future cancels both the retry loop and the current attempt.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::{par_retry, RetryPolicy};
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let attempts = Arc::new(AtomicUsize::new(0));
+/// let policy = RetryPolicy::new(3).backoff(Duration::from_millis(10));
+/// let fut = par_retry(
+///     move || {
+///         let attempts = attempts.clone();
+///         async move {
+///             match attempts.fetch_add(1, Ordering::SeqCst) {
+///                 0 => Err("oh no"),
+///                 n => Ok(n),
+///             }
+///         }
+///     },
+///     policy,
+/// );
+/// assert_eq!(parallel_future::par_block_on(fut), Ok(1));
+/// ```
+pub fn par_retry<F, Fut, T, E>(f: F, policy: RetryPolicy) -> ParallelFuture<Retry<F, Fut>>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: IntoFuture<Output = Result<T, E>> + Send + 'static,
+    Fut::IntoFuture: Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    Retry {
+        f,
+        delay: policy.backoff.min(policy.max_backoff),
+        policy,
+        attempts: 0,
+        state: State::Idle,
+    }
+    .par()
+}
+
+/// How often, and how quickly, to retry a future.
+///
+/// By default attempts are retried right away. Use
+/// [`backoff`][RetryPolicy::backoff] to wait between attempts,
+/// [`exponential`][RetryPolicy::exponential] to increase the wait after every
+/// attempt, and [`max_backoff`][RetryPolicy::max_backoff] to cap how long the
+/// wait can grow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    backoff: Duration,
+    factor: u32,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a new policy which makes at most `max_attempts` attempts.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `max_attempts` is zero.
+    pub fn new(max_attempts: usize) -> Self {
+        assert!(max_attempts > 0, "at least one attempt must be made");
+        Self {
+            max_attempts,
+            backoff: Duration::ZERO,
+            factor: 1,
+            max_backoff: Duration::MAX,
+        }
+    }
+
+    /// Wait for the given duration before retrying a failed attempt.
+    pub fn backoff(mut self, delay: Duration) -> Self {
+        self.backoff = delay;
+        self
+    }
+
+    /// Multiply the backoff by `factor` after every failed attempt.
+    ///
+    /// The backoff stops growing once it reaches the
+    /// [`max_backoff`][RetryPolicy::max_backoff], rather than overflowing.
+    pub fn exponential(mut self, factor: u32) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// Never wait longer than `max` between attempts.
+    ///
+    /// This caps the backoff after it was multiplied by the
+    /// [`exponential`][RetryPolicy::exponential] factor, as well as the
+    /// initial backoff. By default the backoff is uncapped.
+    pub fn max_backoff(mut self, max: Duration) -> Self {
+        self.max_backoff = max;
+        self
+    }
+
+    /// Returns the backoff which follows `delay`.
+    fn next_backoff(&self, delay: Duration) -> Duration {
+        delay
+            .checked_mul(self.factor)
+            .unwrap_or(Duration::MAX)
+            .min(self.max_backoff)
+    }
+}
+
+/// A future which retries a fallible parallel future.
+///
+/// This type is constructed by the [`par_retry`][crate::par_retry] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Retry<F, Fut: IntoFuture> {
+    f: F,
+    policy: RetryPolicy,
+    attempts: usize,
+    delay: Duration,
+    state: State<Fut>,
+}
+
+enum State<Fut: IntoFuture> {
+    /// Ready to start the next attempt.
+    Idle,
+    // Boxed, since `ParallelFuture` is large compared to the other states.
+    Attempt(Box<ParallelFuture<Fut>>),
+    Backoff(Sleep),
+}
+
+// The closure is never pinned, so `Retry` can be moved regardless of `F`.
+impl<F, Fut: IntoFuture> Unpin for Retry<F, Fut> {}
+
+impl<F, Fut, T, E> Future for Retry<F, Fut>
+where
+    F: FnMut() -> Fut,
+    Fut: IntoFuture<Output = Result<T, E>>,
+    Fut::IntoFuture: Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    type Output = Result<T, E>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            match &mut this.state {
+                State::Idle => {
+                    let attempt = (this.f)().par().cancel_on_drop();
+                    this.state = State::Attempt(Box::new(attempt));
+                }
+                State::Attempt(fut) => match ready!(Pin::new(fut).poll(cx)) {
+                    Ok(output) => return Poll::Ready(Ok(output)),
+                    Err(err) => {
+                        this.attempts += 1;
+                        if this.attempts >= this.policy.max_attempts {
+                            this.state = State::Idle;
+                            return Poll::Ready(Err(err));
+                        }
+                        this.state = if this.delay.is_zero() {
+                            State::Idle
+                        } else {
+                            State::Backoff(runtime::sleep(this.delay))
+                        };
+                        this.delay = this.policy.next_backoff(this.delay);
+                    }
+                },
+                State::Backoff(sleep) => {
+                    ready!(sleep.as_mut().poll(cx));
+                    this.state = State::Idle;
+                }
+            }
+        }
+    }
+}
+
+impl<F, Fut> fmt::Debug for Retry<F, Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("policy", &self.policy)
+            .field("attempts", &self.attempts)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn failing(attempts: Arc<AtomicUsize>, failures: usize) -> impl FnMut() -> BoxedResult {
+        move || {
+            let attempts = attempts.clone();
+            Box::pin(async move {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    n if n < failures => Err(n),
+                    n => Ok(n),
+                }
+            })
+        }
+    }
+
+    type BoxedResult = runtime::BoxFuture<Result<usize, usize>>;
+
+    #[test]
+    fn retries_until_success() {
+        runtime::block_on(async {
+            let attempts = Arc::new(AtomicUsize::new(0));
+            let policy = RetryPolicy::new(5)
+                .backoff(Duration::from_millis(1))
+                .exponential(2);
+            let res = par_retry(failing(attempts.clone(), 2), policy).await;
+            assert_eq!(res, Ok(2));
+            assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        })
+    }
+
+    #[test]
+    fn backoff_saturates() {
+        let policy = RetryPolicy::new(usize::MAX)
+            .backoff(Duration::from_secs(1))
+            .exponential(2);
+        let mut delay = Duration::from_secs(1);
+        for _ in 0..200 {
+            delay = policy.next_backoff(delay);
+        }
+        assert_eq!(delay, Duration::MAX);
+
+        let policy = policy.max_backoff(Duration::from_secs(30));
+        let mut delay = Duration::from_secs(1);
+        for _ in 0..200 {
+            delay = policy.next_backoff(delay);
+        }
+        assert_eq!(delay, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn many_attempts() {
+        runtime::block_on(async {
+            let attempts = Arc::new(AtomicUsize::new(0));
+            let policy = RetryPolicy::new(usize::MAX)
+                .backoff(Duration::from_nanos(1))
+                .exponential(2)
+                .max_backoff(Duration::from_micros(10));
+            let res = par_retry(failing(attempts.clone(), 100), policy).await;
+            assert_eq!(res, Ok(100));
+            assert_eq!(attempts.load(Ordering::SeqCst), 101);
+        })
+    }
+
+    #[test]
+    fn returns_last_error() {
+        runtime::block_on(async {
+            let attempts = Arc::new(AtomicUsize::new(0));
+            let res = par_retry(failing(attempts.clone(), 10), RetryPolicy::new(3)).await;
+            assert_eq!(res, Err(2));
+            assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Waker};
+use std::time::{Duration, Instant};
+
+use ::async_std::task;
+
+use super::{BoxFuture, Sleep};
+use crate::executor::{BoxedHandle, Executor, RuntimeConfig, SpawnError, Task, TaskHandle};
+
+/// The default executor.
+pub(crate) static DEFAULT: &dyn Executor = &AsyncStd;
+
+/// An executor which spawns tasks on the async-std runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStd;
+
+/// Task names are passed on to async-std. async-std tasks don't have their own
+/// stack, so the stack size option is ignored.
+impl Executor for AsyncStd {
+    fn spawn(&self, task: Task) -> BoxedHandle {
+        self.try_spawn(task).expect("cannot spawn task")
+    }
+
+    fn try_spawn(&self, task: Task) -> Result<BoxedHandle, SpawnError> {
+        let mut builder = task::Builder::new();
+        if let Some(name) = task.name() {
+            builder = builder.name(name.to_owned());
+        }
+        match builder.spawn(task) {
+            Ok(handle) => Ok(Box::new(handle)),
+            Err(_) => Err(SpawnError::new()),
+        }
+    }
+}
+
+impl TaskHandle for task::JoinHandle<()> {
+    /// async-std only exposes cancellation as an async method, while dropping
+    /// its `JoinHandle` detaches the task. The task is flagged as cancelled on
+    /// the first poll of the returned future though, so we poll it exactly
+    /// once and then drop it.
+    fn cancel(self: Box<Self>) {
+        let cancel = pin!((*self).cancel());
+        let _ = cancel.poll(&mut Context::from_waker(Waker::noop()));
+    }
+}
+
+/// async-std's own handle to a spawned task. Dropping it detaches the task.
+pub type RawJoinHandle<T> = task::JoinHandle<T>;
+
+/// Spawn a future directly on the runtime.
+pub(crate) fn spawn_raw<F>(fut: F) -> RawJoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    task::spawn(fut)
+}
+
+/// Check whether the options passed to `executor::configure` can be applied.
+///
+/// async-std only reads its thread count from the `ASYNC_STD_THREAD_COUNT`
+/// environment variable, which a library can't soundly set on the
+/// application's behalf, so none of the options are supported.
+pub(crate) fn configure(config: &RuntimeConfig) -> Result<(), ()> {
+    match config.worker_threads {
+        Some(_) => Err(()),
+        None => Ok(()),
+    }
+}
+
+/// Spawn a non-`Send` future on the current thread, passing its name on to
+/// async-std.
+pub(crate) fn spawn_local<F>(fut: F, name: Option<&str>) -> BoxedHandle
+where
+    F: Future<Output = ()> + 'static,
+{
+    let mut builder = task::Builder::new();
+    if let Some(name) = name {
+        builder = builder.name(name.to_owned());
+    }
+    Box::new(builder.local(fut).expect("cannot spawn task"))
+}
+
+/// Run a closure on the blocking thread pool.
+pub(crate) fn spawn_blocking<F, T>(f: F) -> BoxFuture<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Box::pin(task::spawn_blocking(f))
+}
+
+/// Block the current thread on a future.
+pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+    task::block_on(fut)
+}
+
+/// Block the current thread on a future, while spawned tasks run on the
+/// runtime's worker threads.
+///
+/// async-std's global runtime starts its worker threads on first use, so this
+/// is the same as `block_on`.
+pub(crate) fn block_on_parallel<F: Future>(fut: F) -> F::Output {
+    block_on(fut)
+}
+
+/// Sleep for the given duration.
+pub(crate) fn sleep(dur: Duration) -> Sleep {
+    Box::pin(task::sleep(dur))
+}
+
+/// Sleep until the given deadline.
+pub(crate) fn sleep_until(deadline: Instant) -> Sleep {
+    sleep(deadline.saturating_duration_since(Instant::now()))
+}
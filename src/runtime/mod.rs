@@ -0,0 +1,39 @@
+//! Runtime-specific executors.
+//!
+//! Exactly one runtime backend is compiled in, selected through cargo
+//! features. Each backend exposes the same set of items, so the rest of the
+//! crate never needs to know which runtime it's running on.
+
+use std::future::Future;
+use std::pin::Pin;
+
+/// A boxed future, as returned by the runtime functions.
+pub(crate) type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// A boxed timer future, as returned by `sleep`.
+pub(crate) type Sleep = BoxFuture<()>;
+
+#[cfg(any(
+    all(feature = "async-std", feature = "tokio"),
+    all(feature = "async-std", feature = "smol"),
+    all(feature = "tokio", feature = "smol"),
+))]
+compile_error!("the `async-std`, `tokio` and `smol` features are mutually exclusive");
+
+#[cfg(not(any(feature = "async-std", feature = "tokio", feature = "smol")))]
+compile_error!("the `std` feature requires one of the `async-std`, `tokio` or `smol` features");
+
+#[cfg(feature = "async-std")]
+mod async_std;
+#[cfg(feature = "async-std")]
+pub use self::async_std::*;
+
+#[cfg(all(feature = "tokio", not(feature = "async-std")))]
+mod tokio;
+#[cfg(all(feature = "tokio", not(feature = "async-std")))]
+pub use self::tokio::*;
+
+#[cfg(all(feature = "smol", not(any(feature = "async-std", feature = "tokio"))))]
+mod smol;
+#[cfg(all(feature = "smol", not(any(feature = "async-std", feature = "tokio"))))]
+pub use self::smol::*;
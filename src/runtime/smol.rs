@@ -0,0 +1,153 @@
+use std::future::{self, Future};
+use std::sync::Once;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_executor::Task as SmolTask;
+
+use super::{BoxFuture, Sleep};
+use crate::executor::{self, BoxedHandle, Executor, RuntimeConfig, Task, TaskHandle};
+
+/// The default executor.
+pub(crate) static DEFAULT: &dyn Executor = &Smol;
+
+/// An executor which spawns tasks on the global smol executor.
+///
+/// This is the same executor `smol::spawn` uses: it's started lazily, and runs
+/// on one thread per available CPU core, unless configured otherwise through
+/// [`configure`][crate::executor::configure].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Smol;
+
+/// smol tasks can't be named, and don't have their own stack, so both the name
+/// and the stack size options are ignored.
+impl Executor for Smol {
+    fn spawn(&self, task: Task) -> BoxedHandle {
+        Box::new(SmolHandle(Some(global().spawn(task))))
+    }
+}
+
+/// Starts the threads of the global executor.
+static START: Once = Once::new();
+
+/// Returns the global executor, starting its threads on first use.
+fn global() -> &'static async_executor::Executor<'static> {
+    static GLOBAL: async_executor::Executor<'_> = async_executor::Executor::new();
+    START.call_once(|| {
+        let threads = executor::start_runtime()
+            .worker_threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        for n in 1..=threads {
+            thread::Builder::new()
+                .name(format!("parallel-future-smol-{n}"))
+                .spawn(|| async_io::block_on(GLOBAL.run(future::pending::<()>())))
+                .expect("cannot spawn executor thread");
+        }
+    });
+    &GLOBAL
+}
+
+/// Check whether the options passed to `executor::configure` can be applied.
+///
+/// The number of worker threads is read when the global executor starts, and
+/// `executor::configure` rejects options passed after that, so every option
+/// is supported.
+pub(crate) fn configure(_config: &RuntimeConfig) -> Result<(), ()> {
+    Ok(())
+}
+
+/// smol cancels tasks when their handle is dropped, so the task is detached
+/// on drop unless it was cancelled first.
+struct SmolHandle(Option<SmolTask<()>>);
+
+impl Drop for SmolHandle {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.detach();
+        }
+    }
+}
+
+impl TaskHandle for SmolHandle {
+    fn cancel(mut self: Box<Self>) {
+        drop(self.0.take());
+    }
+}
+
+/// smol's own handle to a spawned task. Unlike the other runtimes, dropping
+/// it cancels the task; use `detach` to let it run in the background.
+pub type RawJoinHandle<T> = SmolTask<T>;
+
+/// Spawn a future directly on the global executor.
+pub(crate) fn spawn_raw<F>(fut: F) -> RawJoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    global().spawn(fut)
+}
+
+/// Run a closure on the blocking thread pool.
+pub(crate) fn spawn_blocking<F, T>(f: F) -> BoxFuture<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Box::pin(blocking::unblock(f))
+}
+
+/// Block the current thread on a future.
+pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+    async_io::block_on(fut)
+}
+
+/// Block the current thread on a future, while spawned tasks run on the
+/// runtime's worker threads.
+///
+/// The global executor starts its threads on first use, so this is the same as
+/// `block_on`.
+pub(crate) fn block_on_parallel<F: Future>(fut: F) -> F::Output {
+    block_on(fut)
+}
+
+/// Sleep for the given duration.
+pub(crate) fn sleep(dur: Duration) -> Sleep {
+    let timer = async_io::Timer::after(dur);
+    Box::pin(async move {
+        timer.await;
+    })
+}
+
+/// Sleep until the given deadline.
+pub(crate) fn sleep_until(deadline: Instant) -> Sleep {
+    let timer = async_io::Timer::at(deadline);
+    Box::pin(async move {
+        timer.await;
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prelude::*;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn smoke() {
+        block_on(async {
+            let res = async { "chashu" }.par_on(&Smol).await;
+            assert_eq!(res, "chashu");
+
+            // Dropping a spawned future cancels the smol task.
+            let (guard, dropped) = drop_signal();
+            let fut = async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            }
+            .par()
+            .timeout(Duration::from_millis(10));
+            assert!(fut.await.is_err());
+            dropped.await;
+        })
+    }
+}
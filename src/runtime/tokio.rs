@@ -0,0 +1,113 @@
+use std::panic;
+use std::time::{Duration, Instant};
+
+use ::tokio::task;
+
+use super::{BoxFuture, Sleep};
+use crate::executor::{BoxedHandle, Executor, RuntimeConfig, SpawnError, Task, TaskHandle};
+
+/// The default executor.
+pub(crate) static DEFAULT: &dyn Executor = &Tokio;
+
+/// An executor which spawns tasks on the current Tokio runtime.
+///
+/// # Panics
+///
+/// Spawning panics when called outside of a Tokio runtime, while
+/// [`try_spawn`][Executor::try_spawn] returns an error instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokio;
+
+/// Naming tasks requires Tokio's unstable APIs, and Tokio tasks don't have their
+/// own stack, so both the name and the stack size options are ignored.
+impl Executor for Tokio {
+    fn spawn(&self, task: Task) -> BoxedHandle {
+        Box::new(task::spawn(task))
+    }
+
+    fn try_spawn(&self, task: Task) -> Result<BoxedHandle, SpawnError> {
+        match ::tokio::runtime::Handle::try_current() {
+            Ok(handle) => Ok(Box::new(handle.spawn(task))),
+            Err(_) => Err(SpawnError::new()),
+        }
+    }
+}
+
+impl TaskHandle for task::JoinHandle<()> {
+    fn cancel(self: Box<Self>) {
+        self.abort();
+    }
+}
+
+/// Check whether the options passed to `executor::configure` can be applied.
+///
+/// Tasks are spawned on the current runtime, which is configured by whoever
+/// built it, so none of the options are supported.
+pub(crate) fn configure(config: &RuntimeConfig) -> Result<(), ()> {
+    match config.worker_threads {
+        Some(_) => Err(()),
+        None => Ok(()),
+    }
+}
+
+/// Tokio's own handle to a spawned task. Dropping it detaches the task.
+pub type RawJoinHandle<T> = task::JoinHandle<T>;
+
+/// Spawn a future directly on the runtime.
+pub(crate) fn spawn_raw<F>(fut: F) -> RawJoinHandle<F::Output>
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    task::spawn(fut)
+}
+
+/// Run a closure on the blocking thread pool.
+pub(crate) fn spawn_blocking<F, T>(f: F) -> BoxFuture<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let handle = task::spawn_blocking(f);
+    Box::pin(async move {
+        match handle.await {
+            Ok(output) => output,
+            Err(err) => panic::resume_unwind(err.into_panic()),
+        }
+    })
+}
+
+/// Block the current thread on a future.
+///
+/// This runs the future on a new current-thread runtime, so it can be called
+/// from any thread which isn't already running a runtime.
+pub(crate) fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+    ::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(fut)
+}
+
+/// Block the current thread on a future, while spawned tasks run on the
+/// runtime's worker threads.
+///
+/// This runs the future on a new multi-threaded runtime, which is shut down
+/// again once the future completes.
+pub(crate) fn block_on_parallel<F: std::future::Future>(fut: F) -> F::Output {
+    ::tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(fut)
+}
+
+/// Sleep for the given duration.
+pub(crate) fn sleep(dur: Duration) -> Sleep {
+    Box::pin(::tokio::time::sleep(dur))
+}
+
+/// Sleep until the given deadline.
+pub(crate) fn sleep_until(deadline: Instant) -> Sleep {
+    Box::pin(::tokio::time::sleep_until(deadline.into()))
+}
@@ -0,0 +1,133 @@
+use std::future::IntoFuture;
+use std::panic;
+use std::thread;
+
+use crate::runtime;
+
+/// Create a blocking scope for running futures which borrow local data, each on
+/// a thread of its own.
+///
+/// This mirrors [`std::thread::scope`]: the closure is passed a
+/// [`BlockingScope`] which futures can be spawned on, and `blocking_scope` only
+/// returns once every future spawned on it has completed. Because of that the
+/// futures may borrow anything which outlives the call to `blocking_scope`.
+///
+/// This is not a parallel future, and doesn't spawn anything on the runtime.
+/// Every future is run to completion with its own `block_on`, on an OS thread
+/// started just for it, and `blocking_scope` blocks the calling thread until
+/// they're all done. Safe Rust can't guarantee that an asynchronous scope is
+/// awaited to completion rather than forgotten, so blocking is the only way to
+/// make the borrows sound. Don't call `blocking_scope` from inside an async
+/// task, where it would block the runtime's worker thread.
+///
+/// # Panics
+///
+/// If any of the spawned futures panicked and its handle wasn't joined, the
+/// panic is resumed once all futures have completed.
+///
+/// # Examples
+///
+/// ```
+/// let items = vec![1, 2, 3, 4];
+/// let (left, right) = items.split_at(2);
+///
+/// let sum = parallel_future::blocking_scope(|s| {
+///     let a = s.spawn(|| async { left.iter().sum::<i32>() });   // ← borrows `items`
+///     let b = s.spawn(|| async { right.iter().sum::<i32>() });  // ← borrows `items`
+///     a.join() + b.join()
+/// });
+/// assert_eq!(sum, 10);
+/// ```
+pub fn blocking_scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(&BlockingScope<'scope, 'env>) -> T,
+{
+    thread::scope(|inner| f(&BlockingScope { inner }))
+}
+
+/// A blocking scope to run futures which borrow local data in.
+///
+/// This type is constructed by the [`blocking_scope`][crate::blocking_scope]
+/// function.
+#[derive(Debug)]
+pub struct BlockingScope<'scope, 'env: 'scope> {
+    inner: &'scope thread::Scope<'scope, 'env>,
+}
+
+impl<'scope, 'env> BlockingScope<'scope, 'env> {
+    /// Start a thread which runs the future returned by `f` to completion.
+    ///
+    /// The closure is called on the new thread, and the future starts running
+    /// right away. Unlike with [`ParallelFuture`][crate::ParallelFuture],
+    /// dropping the returned handle doesn't cancel the future: the scope always
+    /// waits for it to complete.
+    pub fn spawn<F, Fut>(&self, f: F) -> ScopedJoinHandle<'scope, Fut::Output>
+    where
+        F: FnOnce() -> Fut + Send + 'scope,
+        Fut: IntoFuture,
+        Fut::Output: Send + 'scope,
+    {
+        let handle = self.inner.spawn(|| runtime::block_on(f().into_future()));
+        ScopedJoinHandle { handle }
+    }
+}
+
+/// A handle to a future spawned within a [`BlockingScope`].
+///
+/// This type is constructed by the [`spawn`][BlockingScope::spawn] method on
+/// [`BlockingScope`].
+#[derive(Debug)]
+pub struct ScopedJoinHandle<'scope, T> {
+    handle: thread::ScopedJoinHandle<'scope, T>,
+}
+
+impl<T> ScopedJoinHandle<'_, T> {
+    /// Block the calling thread until the future has completed, and return
+    /// its output.
+    ///
+    /// # Panics
+    ///
+    /// If the future panicked, the panic is resumed in the calling thread.
+    pub fn join(self) -> T {
+        match self.handle.join() {
+            Ok(output) => output,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Returns `true` once the future has completed.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn borrows_local_data() {
+        let counter = AtomicUsize::new(0);
+        let names = vec!["chashu", "nori"];
+        blocking_scope(|s| {
+            for name in &names {
+                let counter = &counter;
+                s.spawn(move || async move {
+                    crate::yield_now().await;
+                    counter.fetch_add(name.len(), Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(counter.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "oh no")]
+    fn propagates_panics() {
+        blocking_scope(|s| {
+            s.spawn(|| async { panic!("oh no") }).join();
+        });
+    }
+}
@@ -0,0 +1,248 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::future::{Future, IntoFuture};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::ParallelFuture;
+
+/// Wait for the first of several parallel futures to complete, and resolve
+/// with its index, its output, and the futures which are still running.
+///
+/// Unlike racing the futures, the remaining futures aren't cancelled: they're
+/// handed back in their original order, minus the winner, so the caller can
+/// decide whether to keep awaiting them or drop them to cancel them. Futures
+/// which haven't been spawned yet are spawned when the returned future is
+/// first polled, and keep running in the background until the caller decides.
+///
+/// If several futures are ready at once, the one with the lowest index wins.
+///
+/// This is the parallel counterpart of `select_all` from the `futures` crate,
+/// except that the index comes first in the output, matching the order of
+/// `enumerate`. Use [`par_select_all`] for the output in the order `futures`
+/// uses.
+///
+/// # Panics
+///
+/// The returned future panics when polled if `futs` is empty.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use parallel_future::par_select;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let futs = vec![30, 10, 20]
+///         .into_iter()
+///         .map(|ms| async move {
+///             async_std::task::sleep(Duration::from_millis(ms)).await;
+///             ms
+///         }.par())
+///         .collect();
+///     let (index, output, remaining) = par_select(futs).await;
+///     assert_eq!((index, output), (1, 10));
+///     assert_eq!(remaining.len(), 2);
+/// })
+/// ```
+pub fn par_select<Fut>(futs: Vec<ParallelFuture<Fut>>) -> Select<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    Select { futs: Some(futs) }
+}
+
+/// A future which resolves with the first parallel future to complete.
+///
+/// This type is constructed by the [`par_select`][crate::par_select]
+/// function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Select<Fut: IntoFuture> {
+    futs: Option<Vec<ParallelFuture<Fut>>>,
+}
+
+// The futures are never pinned, so `Select` can be moved regardless of their
+// types.
+impl<Fut: IntoFuture> Unpin for Select<Fut> {}
+
+impl<Fut> Future for Select<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = (usize, Fut::Output, Vec<ParallelFuture<Fut>>);
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let futs = self
+            .futs
+            .as_mut()
+            .expect("`Select` polled after completion");
+        assert!(!futs.is_empty(), "`par_select` called with no futures");
+        for (index, fut) in futs.iter_mut().enumerate() {
+            if let Poll::Ready(output) = Pin::new(fut).poll(cx) {
+                let mut remaining = self.futs.take().unwrap();
+                drop(remaining.remove(index));
+                return Poll::Ready((index, output, remaining));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<Fut> fmt::Debug for Select<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Select").field("futs", &self.futs).finish()
+    }
+}
+
+/// Wait for the first of several parallel futures to complete, and resolve
+/// with its output, its index, and the futures which are still running.
+///
+/// This is the same as [`par_select`], with the output in the same order as
+/// `select_all` from the `futures` crate, so code using it can switch over
+/// without reordering.
+///
+/// # Panics
+///
+/// The returned future panics when polled if `futs` is empty.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use parallel_future::par_select_all;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let futs = vec![30, 10, 20]
+///         .into_iter()
+///         .map(|ms| async move {
+///             async_std::task::sleep(Duration::from_millis(ms)).await;
+///             ms
+///         }.par())
+///         .collect();
+///     let (output, index, remaining) = par_select_all(futs).await;
+///     assert_eq!((output, index), (10, 1));
+///     assert_eq!(remaining.len(), 2);
+/// })
+/// ```
+#[doc(alias = "select_all")]
+pub fn par_select_all<Fut>(futs: Vec<ParallelFuture<Fut>>) -> SelectAll<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    SelectAll {
+        select: par_select(futs),
+    }
+}
+
+/// A future which resolves with the first parallel future to complete, in the
+/// output order of `futures::future::select_all`.
+///
+/// This type is constructed by the [`par_select_all`][crate::par_select_all]
+/// function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct SelectAll<Fut: IntoFuture> {
+    select: Select<Fut>,
+}
+
+impl<Fut> Future for SelectAll<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Output = (Fut::Output, usize, Vec<ParallelFuture<Fut>>);
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.select)
+            .poll(cx)
+            .map(|(index, output, remaining)| (output, index, remaining))
+    }
+}
+
+impl<Fut> fmt::Debug for SelectAll<Fut>
+where
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SelectAll")
+            .field("futs", &self.select.futs)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::future;
+
+    use super::*;
+    use crate::prelude::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn keeps_the_rest_running() {
+        runtime::block_on(async {
+            let (guard, dropped) = drop_signal();
+            let (release, released) = drop_signal();
+            let futs = vec![
+                (100, Some(guard), None),
+                (10, None, None),
+                (50, None, Some(released)),
+            ]
+            .into_iter()
+            .map(|(n, guard, released)| {
+                async move {
+                    match (guard, released) {
+                        (Some(_guard), _) => future::pending().await,
+                        (_, Some(released)) => released.await,
+                        _ => {}
+                    }
+                    n
+                }
+                .par()
+                .cancel_on_drop()
+            })
+            .collect();
+            let (index, output, remaining) = par_select(futs).await;
+            assert_eq!((index, output), (1, 10));
+
+            drop(release);
+            let (index, output, remaining) = par_select(remaining).await;
+            assert_eq!((index, output), (1, 50));
+
+            // Dropping the last future cancels it.
+            drop(remaining);
+            dropped.await;
+        })
+    }
+    #[test]
+    fn select_all_order() {
+        runtime::block_on(async {
+            let futs = vec![None, Some(12)]
+                .into_iter()
+                .map(|n| {
+                    async move {
+                        match n {
+                            Some(n) => n,
+                            None => future::pending().await,
+                        }
+                    }
+                    .par()
+                    .cancel_on_drop()
+                })
+                .collect();
+            let (output, index, remaining) = par_select_all(futs).await;
+            assert_eq!((output, index), (12, 1));
+            assert_eq!(remaining.len(), 1);
+        })
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+use crate::ParallelFuture;
+
+/// A parallel future which can be cloned, with every clone resolving to the
+/// same output.
+///
+/// This type is constructed by the [`shared`][crate::ParallelFuture::shared]
+/// method on [`ParallelFuture`]. The future is spawned once, when any of the
+/// clones is first polled. Dropping a clone doesn't cancel the spawned future
+/// while other clones remain; it's only cancelled once the last clone is
+/// dropped.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Shared<Fut: IntoFuture> {
+    inner: Arc<Inner<Fut>>,
+    /// Our key in the map of wakers, once we've been polled.
+    key: Option<usize>,
+}
+
+struct Inner<Fut: IntoFuture> {
+    state: Mutex<State<Fut>>,
+    wakers: Arc<Wakers>,
+}
+
+enum State<Fut: IntoFuture> {
+    // Boxed, since `ParallelFuture` is large compared to the output.
+    Running(Box<ParallelFuture<Fut>>),
+    Done(Fut::Output),
+}
+
+/// The wakers of every clone which is waiting for the output.
+///
+/// The spawned future is polled with a waker which wakes all of them, so it
+/// doesn't matter which clone polled it last.
+#[derive(Debug, Default)]
+struct Wakers {
+    wakers: Mutex<HashMap<usize, Waker>>,
+    next_key: AtomicUsize,
+}
+
+impl Wake for Wakers {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        for waker in self.wakers.lock().unwrap().values() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl<Fut: IntoFuture> Shared<Fut> {
+    pub(crate) fn new(fut: ParallelFuture<Fut>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State::Running(Box::new(fut))),
+                wakers: Arc::default(),
+            }),
+            key: None,
+        }
+    }
+
+    /// Register the waker of this clone, so it's woken once the output is in.
+    fn register(&mut self, waker: &Waker) {
+        let wakers = &self.inner.wakers;
+        let key = *self
+            .key
+            .get_or_insert_with(|| wakers.next_key.fetch_add(1, Ordering::Relaxed));
+        match wakers.wakers.lock().unwrap().entry(key) {
+            Entry::Occupied(mut entry) => entry.get_mut().clone_from(waker),
+            Entry::Vacant(entry) => {
+                entry.insert(waker.clone());
+            }
+        }
+    }
+}
+
+impl<Fut> Future for Shared<Fut>
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Clone + Send + 'static,
+{
+    type Output = Fut::Output;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.register(cx.waker());
+        let inner = self.inner.clone();
+        let mut state = inner.state.lock().unwrap();
+        let output = match &mut *state {
+            State::Done(output) => output.clone(),
+            State::Running(fut) => {
+                let waker = Waker::from(inner.wakers.clone());
+                match Pin::new(fut).poll(&mut Context::from_waker(&waker)) {
+                    Poll::Ready(output) => {
+                        *state = State::Done(output.clone());
+                        output
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        };
+        Poll::Ready(output)
+    }
+}
+
+impl<Fut: IntoFuture> Clone for Shared<Fut> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            key: None,
+        }
+    }
+}
+
+/// Stop waiting for the output when dropped. The spawned future is cancelled
+/// once the last clone drops the shared state.
+impl<Fut: IntoFuture> Drop for Shared<Fut> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.inner.wakers.wakers.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+impl<Fut: IntoFuture> fmt::Debug for Shared<Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future;
+    use std::time::Duration;
+
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::prelude::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn spawns_once() {
+        runtime::block_on(async {
+            let spawned = Arc::new(AtomicUsize::new(0));
+            let spawned_2 = spawned.clone();
+            let a = async move {
+                spawned_2.fetch_add(1, Ordering::SeqCst);
+                runtime::sleep(Duration::from_millis(20)).await;
+                String::from("nori")
+            }
+            .par()
+            .shared();
+            let b = a.clone();
+            let c = a.clone();
+
+            let (a, b, c) = (a, b, c).join().await;
+            assert_eq!([a, b, c], ["nori", "nori", "nori"]);
+            assert_eq!(spawned.load(Ordering::SeqCst), 1);
+        })
+    }
+
+    #[test]
+    fn cancels_with_last_clone() {
+        runtime::block_on(async {
+            let completed = Arc::new(AtomicUsize::new(0));
+            let completed_2 = completed.clone();
+            let (release, released) = drop_signal();
+            let mut a = async move {
+                released.await;
+                completed_2.fetch_add(1, Ordering::SeqCst);
+            }
+            .par()
+            .shared();
+            let b = a.clone();
+
+            // Dropping one clone after it started the future keeps it running.
+            let poll = Pin::new(&mut a).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(a);
+            drop(release);
+            b.clone().await;
+            assert_eq!(completed.load(Ordering::SeqCst), 1);
+
+            let (guard, dropped) = drop_signal();
+            let mut a = async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            }
+            .par()
+            .cancel_on_drop()
+            .shared();
+            let b = a.clone();
+            let poll = Pin::new(&mut a).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(a);
+            drop(b);
+            dropped.await;
+        })
+    }
+}
@@ -0,0 +1,527 @@
+//! Parallel operations over streams.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use atomic_waker::AtomicWaker;
+use concurrent_queue::ConcurrentQueue;
+use futures_core::Stream;
+use pin_project::pin_project;
+
+use crate::{IntoFutureExt, ParallelFuture};
+
+/// Extend the `Stream` trait with parallel operations.
+pub trait ParallelStreamExt: Stream + Sized {
+    /// Map each item to a future, and run up to `limit` of the futures in
+    /// parallel.
+    ///
+    /// The returned stream yields the outputs of the futures in the order they
+    /// complete. Dropping it cancels all futures which are still running.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use async_std::prelude::*;
+    /// use async_std::stream;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let items = stream::from_iter(vec![1, 2, 3]);
+    ///     let mut outputs: Vec<_> = items
+    ///         .par_buffer_unordered(2, |n| async move { n * 2 })
+    ///         .collect()
+    ///         .await;
+    ///     outputs.sort();
+    ///     assert_eq!(outputs, vec![2, 4, 6]);
+    /// })
+    /// ```
+    fn par_buffer_unordered<F, Fut>(self, limit: usize, f: F) -> ParBufferUnordered<Self, F, Fut>
+    where
+        F: FnMut(Self::Item) -> Fut,
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        assert!(limit > 0, "the concurrency limit must be greater than zero");
+        ParBufferUnordered {
+            stream: self,
+            f,
+            limit,
+            done: false,
+            in_flight: Vec::new(),
+        }
+    }
+
+    /// Map each item to a future, and run up to `limit` of the futures in
+    /// parallel, yielding their outputs in order.
+    ///
+    /// This is like
+    /// [`par_buffer_unordered`][ParallelStreamExt::par_buffer_unordered],
+    /// except that outputs are yielded in the same order as the items they were
+    /// created from. Dropping the returned stream cancels all futures which are
+    /// still running.
+    ///
+    /// Outputs which complete out of order are buffered until all earlier
+    /// outputs have been yielded. Buffered outputs count towards the limit, so
+    /// at most `limit` outputs are held in memory. The flip side is that a
+    /// single slow future holds up spawning new ones until it completes.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `limit` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use async_std::prelude::*;
+    /// use async_std::stream;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let items = stream::from_iter(vec![1, 2, 3]);
+    ///     let outputs: Vec<_> = items
+    ///         .par_buffered(2, |n| async move { n * 2 })
+    ///         .collect()
+    ///         .await;
+    ///     assert_eq!(outputs, vec![2, 4, 6]);
+    /// })
+    /// ```
+    fn par_buffered<F, Fut>(self, limit: usize, f: F) -> ParBuffered<Self, F, Fut>
+    where
+        F: FnMut(Self::Item) -> Fut,
+        Fut: IntoFuture,
+        Fut::IntoFuture: Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        assert!(limit > 0, "the concurrency limit must be greater than zero");
+        ParBuffered {
+            stream: self,
+            f,
+            limit,
+            done: false,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Run this stream on a spawned task, and yield its items from there.
+    ///
+    /// This is the streaming counterpart of [`par`][crate::IntoFutureExt::par]:
+    /// the stream is polled in parallel with the consumer, and its items are
+    /// sent back over a channel. Up to 16 items are buffered before the
+    /// producing task waits for the consumer to catch up; use
+    /// [`ParallelStream::buffer`] to change that. Like `par`, the task is only
+    /// spawned once the returned stream is first polled, and dropping the
+    /// returned stream cancels it. Panics in the producing task are resumed in
+    /// the consumer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::prelude::*;
+    /// use async_std::prelude::*;
+    /// use async_std::stream;
+    ///
+    /// parallel_future::par_block_on(async {
+    ///     let items = stream::from_iter(vec![1, 2, 3]).map(|n| n * 2);
+    ///     let outputs: Vec<_> = items.par_stream().collect().await;
+    ///     assert_eq!(outputs, vec![2, 4, 6]);
+    /// })
+    /// ```
+    fn par_stream(self) -> ParallelStream<Self>
+    where
+        Self: Send + 'static,
+        Self::Item: Send + 'static,
+    {
+        ParallelStream {
+            stream: Some(self),
+            buffer: 16,
+            channel: None,
+            task: None,
+        }
+    }
+}
+
+impl<S: Stream> ParallelStreamExt for S {}
+
+/// A stream which maps items to parallel futures, and yields their outputs as
+/// they complete.
+///
+/// This type is constructed by the
+/// [`par_buffer_unordered`][ParallelStreamExt::par_buffer_unordered] method on
+/// [`ParallelStreamExt`].
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+pub struct ParBufferUnordered<S, F, Fut: IntoFuture> {
+    #[pin]
+    stream: S,
+    f: F,
+    limit: usize,
+    /// Whether the underlying stream has been exhausted.
+    done: bool,
+    in_flight: Vec<ParallelFuture<Fut>>,
+}
+
+impl<S, F, Fut> Stream for ParBufferUnordered<S, F, Fut>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Item = Fut::Output;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        // Spawn as many futures as the limit allows.
+        while !*this.done && this.in_flight.len() < *this.limit {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.in_flight.push((this.f)(item).par().cancel_on_drop())
+                }
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        for i in 0..this.in_flight.len() {
+            if let Poll::Ready(output) = Pin::new(&mut this.in_flight[i]).poll(cx) {
+                drop(this.in_flight.swap_remove(i));
+                return Poll::Ready(Some(output));
+            }
+        }
+
+        if *this.done && this.in_flight.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<S, F, Fut> fmt::Debug for ParBufferUnordered<S, F, Fut>
+where
+    S: fmt::Debug,
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParBufferUnordered")
+            .field("stream", &self.stream)
+            .field("limit", &self.limit)
+            .field("in_flight", &self.in_flight)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A stream which maps items to parallel futures, and yields their outputs in
+/// order.
+///
+/// This type is constructed by the
+/// [`par_buffered`][ParallelStreamExt::par_buffered] method on
+/// [`ParallelStreamExt`].
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+pub struct ParBuffered<S, F, Fut: IntoFuture> {
+    #[pin]
+    stream: S,
+    f: F,
+    limit: usize,
+    /// Whether the underlying stream has been exhausted.
+    done: bool,
+    /// The futures in the order they were created, with their output once
+    /// they've completed.
+    in_flight: VecDeque<(ParallelFuture<Fut>, Option<Fut::Output>)>,
+}
+
+impl<S, F, Fut> Stream for ParBuffered<S, F, Fut>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Fut,
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    type Item = Fut::Output;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        // Spawn as many futures as the limit allows.
+        while !*this.done && this.in_flight.len() < *this.limit {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    let fut = (this.f)(item).par().cancel_on_drop();
+                    this.in_flight.push_back((fut, None));
+                }
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        for (fut, output) in this.in_flight.iter_mut() {
+            if output.is_none() {
+                if let Poll::Ready(out) = Pin::new(fut).poll(cx) {
+                    *output = Some(out);
+                }
+            }
+        }
+
+        if let Some((_, Some(_))) = this.in_flight.front() {
+            let (_, output) = this.in_flight.pop_front().unwrap();
+            return Poll::Ready(output);
+        }
+
+        if *this.done && this.in_flight.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<S, F, Fut> fmt::Debug for ParBuffered<S, F, Fut>
+where
+    S: fmt::Debug,
+    Fut: IntoFuture + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParBuffered")
+            .field("stream", &self.stream)
+            .field("limit", &self.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A stream which runs on a spawned task.
+///
+/// This type is constructed by the [`par_stream`][ParallelStreamExt::par_stream]
+/// method on [`ParallelStreamExt`].
+#[must_use = "streams do nothing unless polled"]
+pub struct ParallelStream<S: Stream> {
+    /// The stream, until the task is spawned.
+    stream: Option<S>,
+    buffer: usize,
+    channel: Option<Arc<Channel<S::Item>>>,
+    task: Option<ParallelFuture<Forward<S>>>,
+}
+
+// The stream is never pinned here, only once it's moved into the task, so
+// `ParallelStream` can be moved regardless of its type.
+impl<S: Stream> Unpin for ParallelStream<S> {}
+
+impl<S: Stream> ParallelStream<S> {
+    /// Set the number of items which are buffered before the producing task
+    /// waits for the consumer to catch up.
+    ///
+    /// This only has an effect before the stream is first polled.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `buffer` is zero.
+    pub fn buffer(mut self, buffer: usize) -> Self {
+        assert!(buffer > 0, "the buffer must be greater than zero");
+        self.buffer = buffer;
+        self
+    }
+}
+
+impl<S> Stream for ParallelStream<S>
+where
+    S: Stream + Send + 'static,
+    S::Item: Send + 'static,
+{
+    type Item = S::Item;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some(stream) = this.stream.take() {
+            let channel = Arc::new(Channel {
+                items: ConcurrentQueue::bounded(this.buffer),
+                receiver: AtomicWaker::new(),
+                sender: AtomicWaker::new(),
+            });
+            let forward = Forward {
+                stream,
+                channel: channel.clone(),
+            };
+            this.task = Some(forward.par().cancel_on_drop());
+            this.channel = Some(channel);
+        }
+        let Some(channel) = &this.channel else {
+            return Poll::Ready(None);
+        };
+
+        // Register before popping, so an item pushed in between wakes us up.
+        channel.receiver.register(cx.waker());
+        if let Ok(item) = channel.items.pop() {
+            channel.sender.wake();
+            return Poll::Ready(Some(item));
+        }
+        if let Some(task) = &mut this.task {
+            // This resumes the panic if the producing task panicked.
+            ready!(Pin::new(task).poll(cx));
+            this.task = None;
+        }
+
+        // The producing task has completed, so no more items are coming.
+        match channel.items.pop() {
+            Ok(item) => Poll::Ready(Some(item)),
+            Err(_) => {
+                this.channel = None;
+                Poll::Ready(None)
+            }
+        }
+    }
+}
+
+impl<S: Stream> fmt::Debug for ParallelStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParallelStream")
+            .field("buffer", &self.buffer)
+            .field("spawned", &self.stream.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The channel a [`ParallelStream`] receives items over.
+struct Channel<T> {
+    items: ConcurrentQueue<T>,
+    /// Woken when an item is pushed.
+    receiver: AtomicWaker,
+    /// Woken when an item is popped.
+    sender: AtomicWaker,
+}
+
+/// A future which forwards the items of a stream over a channel, waiting
+/// whenever the channel is full.
+#[pin_project]
+struct Forward<S: Stream> {
+    #[pin]
+    stream: S,
+    channel: Arc<Channel<S::Item>>,
+}
+
+impl<S: Stream> Future for Forward<S> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        loop {
+            if this.channel.items.is_full() {
+                // Register before checking again, so an item popped in
+                // between wakes us up.
+                this.channel.sender.register(cx.waker());
+                if this.channel.items.is_full() {
+                    return Poll::Pending;
+                }
+            }
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(item) => {
+                    // We're the only sender, so there's room for the item.
+                    let _ = this.channel.items.push(item);
+                    this.channel.receiver.wake();
+                }
+                None => return Poll::Ready(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::poll_fn;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    /// A stream which yields the items of an iterator.
+    struct Iter<I>(I);
+
+    impl<I: Iterator + Unpin> Stream for Iter<I> {
+        type Item = I::Item;
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+            Poll::Ready(self.0.next())
+        }
+    }
+
+    /// Collect all items of a stream.
+    async fn collect<S: Stream>(stream: S) -> Vec<S::Item> {
+        let mut stream = pin!(stream);
+        let mut items = vec![];
+        while let Some(item) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn buffer_unordered() {
+        runtime::block_on(async {
+            let running = Arc::new(AtomicUsize::new(0));
+            let max = Arc::new(AtomicUsize::new(0));
+            let stream = Iter(vec![50, 10, 20].into_iter()).par_buffer_unordered(2, |ms| {
+                let running = running.clone();
+                let max = max.clone();
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max.fetch_max(now, Ordering::SeqCst);
+                    runtime::sleep(Duration::from_millis(ms)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    ms
+                }
+            });
+            assert_eq!(collect(stream).await, vec![10, 20, 50]);
+            assert_eq!(max.load(Ordering::SeqCst), 2);
+        })
+    }
+
+    #[test]
+    fn par_stream() {
+        runtime::block_on(async {
+            let stream = Iter(0..100).par_stream().buffer(4);
+            assert_eq!(collect(stream).await, (0..100).collect::<Vec<_>>());
+
+            // Dropping the stream cancels the producing task, which drops the
+            // underlying stream.
+            let produced = Arc::new(AtomicUsize::new(0));
+            let produced_2 = produced.clone();
+            let (guard, dropped) = drop_signal();
+            let mut stream = Iter((0..100).inspect(move |_| {
+                let _guard = &guard;
+                produced_2.fetch_add(1, Ordering::SeqCst);
+            }))
+            .par_stream()
+            .buffer(4);
+            let item = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await;
+            assert_eq!(item, Some(0));
+            // The producer fills the buffer, and then waits for the consumer.
+            while produced.load(Ordering::SeqCst) < 5 {
+                crate::yield_now().await;
+            }
+            assert!(produced.load(Ordering::SeqCst) <= 6);
+            drop(stream);
+            dropped.await;
+        })
+    }
+
+    #[test]
+    fn buffered() {
+        runtime::block_on(async {
+            let stream = Iter(vec![50, 10, 20].into_iter()).par_buffered(2, |ms| async move {
+                runtime::sleep(Duration::from_millis(ms)).await;
+                ms
+            });
+            assert_eq!(collect(stream).await, vec![50, 10, 20]);
+        })
+    }
+}
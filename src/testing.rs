@@ -0,0 +1,466 @@
+//! Utilities for testing code which uses parallel futures.
+//!
+//! The [`TestExecutor`] runs all tasks spawned on it on a single thread, one
+//! poll at a time, in the order they were woken up. That makes the order in
+//! which parallel futures make progress reproducible between test runs.
+//!
+//! [`assert_cancelled`] checks that dropping a parallel future actually stops
+//! its task, which otherwise happens asynchronously at the task's next await
+//! point.
+//!
+//! [`par_ok`] and [`par_err`] create parallel futures which resolve to a fixed
+//! `Result`, for testing code which handles fallible parallel futures.
+
+use std::collections::VecDeque;
+use std::future::{self, poll_fn, Future, IntoFuture, Ready};
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use crate::executor::{BoxedHandle, Executor, Task, TaskHandle};
+#[cfg(test)]
+use crate::oneshot;
+use crate::{runtime, IntoFutureExt, ParallelFuture};
+
+/// Create a parallel future which resolves to `Ok(value)`.
+///
+/// The future is spawned like any other parallel future, so it behaves the
+/// same way in combinators as one which does real work.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::testing::{par_err, par_ok};
+/// use parallel_future::ParallelTryGroup;
+///
+/// parallel_future::par_block_on(async {
+///     let mut group = ParallelTryGroup::new();
+///     group.spawn(par_ok(1));
+///     group.spawn(par_err("oh no"));
+///     assert_eq!(group.try_join().await, Err("oh no"));
+/// })
+/// ```
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_ok<T, E>(value: T) -> ParallelFuture<Ready<Result<T, E>>>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    future::ready(Ok(value)).par()
+}
+
+/// Create a parallel future which resolves to `Err(error)`.
+///
+/// The future is spawned like any other parallel future, so it behaves the
+/// same way in combinators as one which does real work. See [`par_ok`] for an
+/// example.
+#[cfg_attr(feature = "debug", track_caller)]
+pub fn par_err<T, E>(error: E) -> ParallelFuture<Ready<Result<T, E>>>
+where
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    future::ready(Err(error)).par()
+}
+
+/// Drop a parallel future, and assert that its task stops within the given
+/// duration.
+///
+/// Dropping a `ParallelFuture` cancels its task, but the task only stops once
+/// it reaches its next await point, or finishes what it was doing. This waits
+/// for the task to be dropped by its executor, and panics if that takes longer
+/// than `within`. That catches tasks which are kept alive by mistake, such as
+/// those made [`keep_alive_on_drop`][ParallelFuture::keep_alive_on_drop], and
+/// tasks which block without ever yielding. A future which was never polled
+/// was never spawned, so this returns right away for it.
+///
+/// # Panics
+///
+/// This panics if the task is still running after `within`.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use parallel_future::testing::assert_cancelled;
+/// use std::time::Duration;
+///
+/// parallel_future::par_block_on(async {
+///     let fut = async_std::task::sleep(Duration::from_secs(10))
+///         .par_eager()
+///         .cancel_on_drop();
+///     assert_cancelled(fut, Duration::from_secs(1)).await;
+/// })
+/// ```
+pub async fn assert_cancelled<Fut>(fut: ParallelFuture<Fut>, within: Duration)
+where
+    Fut: IntoFuture,
+    Fut::IntoFuture: Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let Some(mut closed) = fut.task_closed() else {
+        return;
+    };
+    drop(fut);
+    let mut timeout = runtime::sleep(within);
+    poll_fn(|cx| {
+        if Pin::new(&mut closed).poll(cx).is_ready() {
+            return Poll::Ready(());
+        }
+        if timeout.as_mut().poll(cx).is_ready() {
+            panic!(
+                "the parallel future's task was still running after {:?}",
+                within
+            );
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Create a guard to move into a future, and a future which resolves once that
+/// guard has been dropped.
+///
+/// Tests wait on this to see a task stop, rather than sleeping for a fixed
+/// amount of time and hoping it stopped by then. Waiting panics after ten
+/// seconds, so a task which is never dropped fails the test instead of hanging
+/// it.
+#[cfg(test)]
+pub(crate) fn drop_signal() -> (oneshot::Sender<()>, impl Future<Output = ()>) {
+    let (guard, mut receiver) = oneshot::channel();
+    let dropped = async move {
+        let mut timeout = runtime::sleep(Duration::from_secs(10));
+        poll_fn(|cx| {
+            if Pin::new(&mut receiver).poll(cx).is_ready() {
+                return Poll::Ready(());
+            }
+            if timeout.as_mut().poll(cx).is_ready() {
+                panic!("the guard was still alive after 10s");
+            }
+            Poll::Pending
+        })
+        .await
+    };
+    (guard, dropped)
+}
+
+/// A deterministic, single-threaded executor for tests.
+///
+/// Futures are spawned on it through [`par_on`][crate::IntoFutureExt::par_on].
+/// Spawned tasks only run when the executor is driven, either one poll at a
+/// time with [`step`][TestExecutor::step], or alongside a future with
+/// [`block_on`][TestExecutor::block_on].
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+/// use parallel_future::testing::TestExecutor;
+///
+/// let executor = TestExecutor::new();
+/// let handle = async { 12 }.par_on(&executor).detach();
+/// assert_eq!(executor.pending(), 1);
+///
+/// assert!(executor.step());          // ← polls the task exactly once
+/// assert!(!executor.step());         // ← nothing left to poll
+/// assert!(handle.is_finished());
+/// assert_eq!(executor.block_on(handle), 12);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TestExecutor {
+    state: Arc<Mutex<State>>,
+}
+
+impl TestExecutor {
+    /// Create a new executor without any tasks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Poll the next task which is ready to make progress, once.
+    ///
+    /// Returns `false` if there was no task to poll.
+    pub fn step(&self) -> bool {
+        let (id, mut task) = {
+            let mut state = self.state.lock().unwrap();
+            loop {
+                let Some(id) = state.queue.pop_front() else {
+                    return false;
+                };
+                let slot = &mut state.tasks[id];
+                slot.queued = false;
+                if let Some(task) = slot.task.take() {
+                    break (id, task);
+                }
+            }
+        };
+
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            state: Arc::downgrade(&self.state),
+        }));
+        let poll = Pin::new(&mut task).poll(&mut Context::from_waker(&waker));
+
+        let mut state = self.state.lock().unwrap();
+        let slot = &mut state.tasks[id];
+        if poll.is_ready() || slot.done {
+            slot.done = true;
+            // Dropping the task may cancel other tasks, which needs the lock.
+            drop(state);
+            drop(task);
+        } else {
+            slot.task = Some(task);
+        }
+        true
+    }
+
+    /// Poll tasks until none of them can make progress anymore.
+    pub fn run_until_stalled(&self) {
+        while self.step() {}
+    }
+
+    /// Returns the number of spawned tasks which haven't completed or been
+    /// cancelled yet.
+    pub fn pending(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.tasks.iter().filter(|slot| !slot.done).count()
+    }
+
+    /// Block the current thread on a future, running the executor's tasks
+    /// while the future is pending.
+    ///
+    /// The future is polled whenever it's woken up, and otherwise one task is
+    /// polled at a time. If neither can make progress, the thread is parked
+    /// until something wakes it up.
+    pub fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let main = Arc::new(MainWaker {
+            woken: AtomicBool::new(true),
+            thread: thread::current(),
+        });
+        let waker = Waker::from(main.clone());
+        self.state.lock().unwrap().thread = Some(thread::current());
+
+        loop {
+            if main.woken.swap(false, Ordering::SeqCst) {
+                let mut cx = Context::from_waker(&waker);
+                if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+            if !self.step() && !main.woken.load(Ordering::SeqCst) {
+                thread::park();
+            }
+        }
+    }
+}
+
+impl Executor for TestExecutor {
+    fn spawn(&self, task: Task) -> BoxedHandle {
+        let mut state = self.state.lock().unwrap();
+        let id = state.tasks.len();
+        state.tasks.push(Slot {
+            task: Some(task),
+            queued: true,
+            done: false,
+        });
+        state.queue.push_back(id);
+        state.unpark();
+        Box::new(TestHandle {
+            id,
+            state: Arc::downgrade(&self.state),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// All tasks ever spawned, indexed by their id.
+    tasks: Vec<Slot>,
+    /// The ids of tasks which are ready to be polled, in the order they were
+    /// woken.
+    queue: VecDeque<usize>,
+    /// The thread blocked in `block_on`, if any.
+    thread: Option<Thread>,
+}
+
+impl State {
+    /// Queue a task to be polled, unless it already is.
+    fn schedule(&mut self, id: usize) {
+        let slot = &mut self.tasks[id];
+        if !slot.done && !slot.queued {
+            slot.queued = true;
+            self.queue.push_back(id);
+            self.unpark();
+        }
+    }
+
+    /// Wake up the thread blocked in `block_on`.
+    fn unpark(&self) {
+        if let Some(thread) = &self.thread {
+            thread.unpark();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    /// The task, unless it's being polled or is done.
+    task: Option<Task>,
+    queued: bool,
+    /// Whether the task has completed or been cancelled.
+    done: bool,
+}
+
+/// Wakes a task by queueing it on the executor.
+struct TaskWaker {
+    id: usize,
+    state: Weak<Mutex<State>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if let Some(state) = self.state.upgrade() {
+            state.lock().unwrap().schedule(self.id);
+        }
+    }
+}
+
+/// Wakes the future passed to `block_on`.
+struct MainWaker {
+    woken: AtomicBool,
+    thread: Thread,
+}
+
+impl Wake for MainWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        self.thread.unpark();
+    }
+}
+
+/// A handle to a task spawned on a [`TestExecutor`].
+struct TestHandle {
+    id: usize,
+    state: Weak<Mutex<State>>,
+}
+
+impl TaskHandle for TestHandle {
+    fn cancel(self: Box<Self>) {
+        let Some(state) = self.state.upgrade() else {
+            return;
+        };
+        let mut state = state.lock().unwrap();
+        let slot = &mut state.tasks[self.id];
+        slot.done = true;
+        // If the task is being polled right now, it's dropped once the poll
+        // completes instead. Dropping it may cancel other tasks, which needs
+        // the lock.
+        let task = slot.task.take();
+        drop(state);
+        drop(task);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_concurrency::prelude::*;
+
+    use super::*;
+    use crate::yield_now;
+
+    #[test]
+    fn deterministic_order() {
+        let executor = TestExecutor::new();
+        let log = Arc::new(Mutex::new(vec![]));
+        let task = |name: &'static str| {
+            let log = log.clone();
+            async move {
+                log.lock().unwrap().push(format!("{name}1"));
+                yield_now().await;
+                log.lock().unwrap().push(format!("{name}2"));
+            }
+            .par_on(&executor)
+        };
+
+        executor.block_on((task("a"), task("b")).join());
+        assert_eq!(*log.lock().unwrap(), vec!["a1", "b1", "a2", "b2"]);
+    }
+
+    #[test]
+    fn nested() {
+        let executor = TestExecutor::new();
+        let inner = executor.clone();
+        let fut = async move {
+            let innermost = inner.clone();
+            let fut = async move { async { 12 }.par_on(&innermost).await };
+            fut.par_on(&inner).await + 1
+        }
+        .par_on(&executor);
+        assert_eq!(executor.block_on(fut), 13);
+        assert_eq!(executor.pending(), 0);
+    }
+
+    #[test]
+    fn cancel() {
+        let executor = TestExecutor::new();
+        let mut fut = async { yield_now().await }
+            .par_on(&executor)
+            .cancel_on_drop();
+        let poll = Pin::new(&mut fut).poll(&mut Context::from_waker(Waker::noop()));
+        assert!(poll.is_pending());
+
+        assert!(executor.step());
+        assert_eq!(executor.pending(), 1);
+        drop(fut);
+        assert_eq!(executor.pending(), 0);
+        assert!(!executor.step());
+    }
+
+    #[test]
+    fn par_ok_and_err() {
+        runtime::block_on(async {
+            let ok = par_ok::<_, ()>(12);
+            let err = par_err::<(), _>("oh no");
+            let (ok, err) = (ok, err).join().await;
+            assert_eq!(ok, Ok(12));
+            assert_eq!(err, Err("oh no"));
+
+            let outputs = crate::par_first_n([par_err(1), par_ok(2), par_ok(3)], 2).await;
+            assert_eq!(outputs.len(), 2);
+        })
+    }
+
+    #[test]
+    fn assert_cancelled() {
+        runtime::block_on(async {
+            let fut = runtime::sleep(Duration::from_secs(10))
+                .par_eager()
+                .cancel_on_drop();
+            super::assert_cancelled(fut, Duration::from_secs(1)).await;
+        })
+    }
+
+    #[test]
+    #[should_panic(expected = "still running")]
+    fn assert_cancelled_kept_alive() {
+        runtime::block_on(async {
+            let mut fut = runtime::sleep(Duration::from_secs(10))
+                .par()
+                .keep_alive_on_drop();
+            fut.start();
+            super::assert_cancelled(fut, Duration::from_millis(50)).await;
+        })
+    }
+}
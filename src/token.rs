@@ -0,0 +1,236 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+/// A token which signals cooperative cancellation.
+///
+/// Tokens are cheap to clone, and all clones observe the same cancellation.
+/// Parallel futures created through [`par_with_token`][crate::par_with_token],
+/// or made [`graceful`][crate::ParallelFuture::graceful], cancel their token
+/// when dropped rather than cancelling the spawned task outright. That gives
+/// the task a chance to finish what it's doing and clean up.
+///
+/// Tokens can be branched with [`child`][CancellationToken::child], so a task
+/// can hand its own subtasks a token which is cancelled along with it, but
+/// which can also be cancelled on its own.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let token_2 = token.clone();
+/// assert!(!token_2.is_cancelled());
+///
+/// token.cancel();
+/// assert!(token_2.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    wakers: Vec<Waker>,
+    /// Tokens derived through `child`, which are cancelled along with this one.
+    children: Vec<Weak<Inner>>,
+}
+
+impl Inner {
+    fn cancel(&self) {
+        let (wakers, children) = {
+            let mut state = self.state.lock().unwrap();
+            self.cancelled.store(true, Ordering::SeqCst);
+            (
+                std::mem::take(&mut state.wakers),
+                std::mem::take(&mut state.children),
+            )
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+impl CancellationToken {
+    /// Create a new token which hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token, waking up everything waiting on
+    /// [`cancelled`][CancellationToken::cancelled].
+    ///
+    /// This also cancels every token derived from this one through
+    /// [`child`][CancellationToken::child].
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    /// Returns `true` once the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Derive a new token which is cancelled whenever this token is.
+    ///
+    /// Cancelling the child token doesn't affect this token, nor any of its
+    /// other children. If this token has already been cancelled, the child
+    /// starts out cancelled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_future::CancellationToken;
+    ///
+    /// let parent = CancellationToken::new();
+    /// let child = parent.child();
+    ///
+    /// child.cancel();
+    /// assert!(!parent.is_cancelled());
+    ///
+    /// let child = parent.child();
+    /// parent.cancel();
+    /// assert!(child.is_cancelled());
+    /// ```
+    pub fn child(&self) -> Self {
+        let child = Self::new();
+        let mut state = self.inner.state.lock().unwrap();
+        // Checked while holding the lock, so a concurrent `cancel` can't miss
+        // the child.
+        if self.is_cancelled() {
+            child.inner.cancelled.store(true, Ordering::SeqCst);
+        } else {
+            state.children.retain(|child| child.strong_count() > 0);
+            state.children.push(Arc::downgrade(&child.inner));
+        }
+        child
+    }
+
+    /// Wait for the token to be cancelled.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+        }
+    }
+}
+
+/// A future which resolves once a [`CancellationToken`] is cancelled.
+///
+/// This type is constructed by the [`cancelled`][CancellationToken::cancelled]
+/// method on [`CancellationToken`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Cancelled {
+    token: CancellationToken,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = &self.token.inner;
+        let mut state = inner.state.lock().unwrap();
+        // Checked while holding the lock, so a concurrent `cancel` can't miss
+        // our waker.
+        if inner.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl fmt::Debug for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cancelled")
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::prelude::*;
+    use crate::runtime;
+    use crate::testing::drop_signal;
+
+    #[test]
+    fn graceful_drop() {
+        runtime::block_on(async {
+            let cleaned_up = Arc::new(AtomicUsize::new(0));
+            let cleaned_up_2 = cleaned_up.clone();
+            let (guard, dropped) = drop_signal();
+            let mut fut = crate::par_with_token(|token| async move {
+                let _guard = guard;
+                token.cancelled().await;
+                cleaned_up_2.fetch_add(1, Ordering::SeqCst);
+            });
+
+            // Spawn the future, then drop it.
+            let poll = Pin::new(&mut fut).poll(&mut Context::from_waker(Waker::noop()));
+            assert!(poll.is_pending());
+            drop(fut);
+
+            dropped.await;
+            assert_eq!(cleaned_up.load(Ordering::SeqCst), 1);
+        })
+    }
+
+    #[test]
+    fn child_tokens() {
+        runtime::block_on(async {
+            let parent = CancellationToken::new();
+            let child = parent.child();
+            let grandchild = child.child();
+            let sibling = parent.child();
+
+            // Cancelling a child leaves its parent and siblings alone.
+            child.cancel();
+            assert!(grandchild.is_cancelled());
+            assert!(!parent.is_cancelled());
+            assert!(!sibling.is_cancelled());
+
+            // Cancelling the parent wakes tasks waiting on its children.
+            let waiting = sibling.child();
+            let (started, is_started) = drop_signal();
+            let fut = async move {
+                drop(started);
+                waiting.cancelled().await
+            }
+            .par_eager();
+            is_started.await;
+            parent.cancel();
+            fut.await;
+            assert!(sibling.is_cancelled());
+            assert!(parent.child().is_cancelled());
+        })
+    }
+
+    #[test]
+    fn graceful_completes() {
+        runtime::block_on(async {
+            let token = CancellationToken::new();
+            let res = async { 12 }.par().graceful(token.clone()).await;
+            assert_eq!(res, 12);
+            assert!(!token.is_cancelled());
+        })
+    }
+}
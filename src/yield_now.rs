@@ -0,0 +1,75 @@
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+thread_local! {
+    /// Whether the task being polled on this thread yielded through
+    /// `yield_now`.
+    static YIELDED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Yield back to the executor, letting other tasks run before this one
+/// continues.
+///
+/// This works the same on every runtime, so code running inside parallel
+/// futures doesn't need to import a yield function from a specific one. The
+/// current task is woken up right away, and continues once the executor gets
+/// around to polling it again.
+///
+/// Yielding also resets the task's
+/// [`yield_every`][crate::future::Builder::yield_every] budget, so a task which
+/// yields on its own isn't made to yield again by the budget right after.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_future::prelude::*;
+///
+/// parallel_future::par_block_on(async {
+///     let res = async {
+///         let mut sum = 0;
+///         for n in 0..1_000 {
+///             sum += n;
+///             if n % 100 == 0 {
+///                 parallel_future::yield_now().await; // ← let others run
+///             }
+///         }
+///         sum
+///     }
+///     .par()
+///     .await;
+///     assert_eq!(res, 499_500);
+/// })
+/// ```
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// A future which yields back to the executor once.
+///
+/// This type is constructed by the [`yield_now`][crate::yield_now] function.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        YIELDED.with(|yielded| yielded.set(true));
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Returns whether the task yielded through `yield_now` since the last call,
+/// and resets it.
+pub(crate) fn take_yielded() -> bool {
+    YIELDED.with(|yielded| yielded.replace(false))
+}